@@ -13,12 +13,53 @@ pub mod libinput;
 pub mod vulkan;
 pub mod winit;
 
+/// Get the current time of the monotonic clock in milliseconds, truncated to 32 bits. This is the clock libinput
+/// uses for its event timestamps, so every backend uses it to make input event times comparable.
+pub fn get_time_ms() -> u32 {
+	let mut time = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+	unsafe {
+		libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time);
+	}
+	(time.tv_sec as u64 * 1000 + time.tv_nsec as u64 / 1_000_000) as u32
+}
+
 pub trait InputBackend {
 	type Error: fmt::Debug + fmt::Display;
 
 	fn update(&mut self) -> Result<(), Self::Error>;
 
 	fn get_event_source(&mut self) -> Channel<BackendEvent>;
+
+	/// Turn the lock LEDs of every keyboard on or off. Backends that don't own their keyboards, like nested ones,
+	/// can ignore this.
+	fn set_keyboard_leds(&mut self, leds: KeyboardLeds);
+}
+
+bitflags::bitflags! {
+	/// The lock indicator LEDs of a keyboard
+	pub struct KeyboardLeds: u32 {
+		const NUM_LOCK = 0b001;
+		const CAPS_LOCK = 0b010;
+		const SCROLL_LOCK = 0b100;
+	}
+}
+
+/// The wl_shm formats that graphics backends have to be able to create textures from, and the number of bytes a pixel
+/// takes in each of them
+pub const SUPPORTED_SHM_FORMATS: &[(wl_shm::Format, u32)] = &[
+	(wl_shm::Format::Argb8888, 4),
+	(wl_shm::Format::Xrgb8888, 4),
+	(wl_shm::Format::Abgr8888, 4),
+	(wl_shm::Format::Xbgr8888, 4),
+	(wl_shm::Format::Rgb565, 2),
+];
+
+/// Get the number of bytes a pixel takes in a wl_shm format, or None if the format isn't supported
+pub fn shm_format_bytes_per_pixel(format: wl_shm::Format) -> Option<u32> {
+	SUPPORTED_SHM_FORMATS
+		.iter()
+		.find(|&&(supported_format, _bytes_per_pixel)| supported_format == format)
+		.map(|&(_supported_format, bytes_per_pixel)| bytes_per_pixel)
 }
 
 pub trait ShmBuffer {
@@ -49,6 +90,7 @@ pub trait GraphicsBackend: Sized + fmt::Debug {
 
 	fn update(&mut self) -> Result<(), Self::Error>;
 
+	/// Map a shm pool, which takes ownership of `fd` and closes it once the pool is dropped or fails to be created
 	fn create_shm_pool(&mut self, fd: RawFd, size: usize) -> Result<Self::ShmPool, Self::Error>;
 
 	fn resize_shm_pool(&mut self, shm_pool: &mut Self::ShmPool, new_size: usize) -> Result<(), Self::Error>;
@@ -92,6 +134,20 @@ pub trait GraphicsBackend: Sized + fmt::Debug {
 
 	unsafe fn begin_render_pass(&mut self, target: Self::RenderTargetHandle) -> Result<(), Self::Error>;
 
+	/// Begin a render pass that only clears and draws to the part of the target inside `scissor`, which is in
+	/// render target coordinates. Returns true if everything outside the scissor keeps the target's previous
+	/// contents, or false if the backend can't do that and began a normal render pass instead, in which case
+	/// everything has to be drawn.
+	unsafe fn begin_scissored_render_pass(
+		&mut self,
+		target: Self::RenderTargetHandle,
+		scissor: Rect,
+	) -> Result<bool, Self::Error> {
+		let _ = scissor;
+		self.begin_render_pass(target)?;
+		Ok(false)
+	}
+
 	unsafe fn draw(
 		&mut self,
 		vertex_buffer: Self::VertexBufferHandle,
@@ -107,13 +163,29 @@ pub trait GraphicsBackend: Sized + fmt::Debug {
 		handle: Self::RenderTargetHandle,
 	) -> Result<(), Self::Error>;
 
+	/// Check without blocking whether the GPU has finished all of the rendering and presenting submitted so far,
+	/// after which nothing that was read by those frames is in use anymore
+	fn frames_finished(&mut self) -> Result<bool, Self::Error>;
+
+	/// Copy the contents of a render target back to CPU memory. The pixels are returned as RGBA8 rows from top to
+	/// bottom with a stride of exactly `width * 4` bytes. Backends that can't do this return an error.
+	fn read_render_target(&mut self, handle: Self::RenderTargetHandle) -> Result<Vec<u8>, Self::Error>;
+
 	fn destroy_texture(&mut self, handle: Self::TextureHandle) -> Result<(), Self::Error>;
 
+	/// Get the number of bytes of memory used by the textures, vertex buffers and MVP buffers that currently exist
+	fn memory_usage(&self) -> u64;
+
 	fn destroy_vertex_buffer(&mut self, handle: Self::VertexBufferHandle) -> Result<(), Self::Error>;
 
 	fn destroy_mvp_buffer(&mut self, handle: Self::MvpBufferHandle) -> Result<(), Self::Error>;
 
 	fn destroy_render_target(&mut self, handle: Self::RenderTargetHandle) -> Result<(), Self::Error>;
+
+	/// Release everything the backend holds on to before the compositor exits, like restoring the display state
+	/// that was active before the compositor started. This is called once, after every texture, buffer, and
+	/// render target has been destroyed.
+	fn shutdown(&mut self) -> Result<(), Self::Error>;
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -138,6 +210,12 @@ pub enum BackendEvent {
 	KeyPress(KeyPress),
 	PointerMotion(PointerMotion),
 	PointerButton(PointerButton),
+	PointerAxis(PointerAxis),
+	ModifiersChanged(ModifiersChanged),
+	OutputResized(OutputResized),
+	GestureSwipe(GestureSwipe),
+	GesturePinch(GesturePinch),
+	Touch(Touch),
 	StopRequested,
 }
 
@@ -173,22 +251,142 @@ pub struct PointerButton {
 	pub state: PressState,
 }
 
+/// A scroll of the pointer, such as by a mouse wheel or two fingers on a touchpad. `horizontal` and `vertical` are
+/// in the same units as pointer motion, positive to the right and down, and are 0 for an axis that wasn't scrolled.
+/// Sources with discrete steps, like wheels, also report the number of steps scrolled on each axis.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointerAxis {
+	pub time: u32,
+	pub horizontal: f64,
+	pub vertical: f64,
+	pub horizontal_discrete: Option<i32>,
+	pub vertical_discrete: Option<i32>,
+	pub source: AxisSource,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AxisSource {
+	Wheel,
+	Finger,
+	Continuous,
+	WheelTilt,
+}
+
+/// Sent by windowed backends when the modifier keys that are held down change. Windowed backends miss the key
+/// events that happen while their window is unfocused, so this keeps the compositor's modifier state correct.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModifiersChanged {
+	pub shift: bool,
+	pub ctrl: bool,
+	pub alt: bool,
+	pub logo: bool,
+}
+
+/// A multi-finger swipe on a touchpad. Every swipe begins, updates any number of times with the motion of the
+/// fingers since the last event, and then ends.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GestureSwipe {
+	Begin { serial: u32, time: u32, fingers: u32 },
+	Update { time: u32, dx: f64, dy: f64 },
+	End { serial: u32, time: u32, cancelled: bool },
+}
+
+/// A multi-finger pinch on a touchpad. `scale` is relative to the finger distance when the pinch began, and
+/// `rotation` is the clockwise rotation in degrees since the last update.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GesturePinch {
+	Begin {
+		serial: u32,
+		time: u32,
+		fingers: u32,
+	},
+	Update {
+		time: u32,
+		dx: f64,
+		dy: f64,
+		scale: f64,
+		rotation: f64,
+	},
+	End {
+		serial: u32,
+		time: u32,
+		cancelled: bool,
+	},
+}
+
+/// A touch point on a touchscreen, identified by the slot it was given when it went down. Positions are fractions
+/// of the width and height of the touchscreen, from 0 to 1.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Touch {
+	Down {
+		serial: u32,
+		time: u32,
+		slot: u32,
+		x: f64,
+		y: f64,
+	},
+	Motion {
+		time: u32,
+		slot: u32,
+		x: f64,
+		y: f64,
+	},
+	Up {
+		serial: u32,
+		time: u32,
+		slot: u32,
+	},
+	Cancel {
+		serial: u32,
+		time: u32,
+		slot: u32,
+	},
+	/// Sent after every touch point that changed at the same time has been sent
+	Frame {
+		time: u32,
+	},
+}
+
+/// Sent by windowed backends when the host window, and therefore the output presented into it, is resized
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputResized {
+	pub width: u32,
+	pub height: u32,
+}
+
+/// A pointer button. Wayland identifies buttons by their evdev BTN_* codes (see linux/input-event-codes.h), so
+/// `Other` holds the raw code of any button that doesn't have a named variant, such as side or extra buttons.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Button {
 	Left,
 	Right,
 	Middle,
-	Other(u8),
+	Other(u32),
 }
 
 impl Button {
+	pub const BTN_LEFT: u32 = 0x110;
+	pub const BTN_RIGHT: u32 = 0x111;
+	pub const BTN_MIDDLE: u32 = 0x112;
+	pub const BTN_SIDE: u32 = 0x113;
+	pub const BTN_EXTRA: u32 = 0x114;
+
+	/// Get the button with the given evdev code, using the named variants where possible
+	pub fn from_evdev(code: u32) -> Self {
+		match code {
+			Self::BTN_LEFT => Button::Left,
+			Self::BTN_RIGHT => Button::Right,
+			Self::BTN_MIDDLE => Button::Middle,
+			code => Button::Other(code),
+		}
+	}
+
 	pub fn to_wl(self) -> u32 {
-		// According to smithay, this is how wayland sees mouse buttons
 		match self {
-			Button::Left => 0x110,
-			Button::Right => 0x111,
-			Button::Middle => 0x112,
-			Button::Other(b) => b.into(),
+			Button::Left => Self::BTN_LEFT,
+			Button::Right => Self::BTN_RIGHT,
+			Button::Middle => Self::BTN_MIDDLE,
+			Button::Other(code) => code,
 		}
 	}
 }
@@ -232,6 +430,17 @@ impl From<PressState> for wl_pointer::ButtonState {
 	}
 }
 
+impl From<AxisSource> for wl_pointer::AxisSource {
+	fn from(t: AxisSource) -> Self {
+		match t {
+			AxisSource::Wheel => wl_pointer::AxisSource::Wheel,
+			AxisSource::Finger => wl_pointer::AxisSource::Finger,
+			AxisSource::Continuous => wl_pointer::AxisSource::Continuous,
+			AxisSource::WheelTilt => wl_pointer::AxisSource::WheelTilt,
+		}
+	}
+}
+
 impl From<xkbcommon::xkb::KeyDirection> for PressState {
 	fn from(t: xkbcommon::xkb::KeyDirection) -> Self {
 		match t {
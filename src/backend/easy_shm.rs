@@ -1,22 +1,24 @@
 use std::{
 	convert::TryFrom,
 	os::{raw::c_void, unix::io::RawFd},
+	sync::Arc,
 };
 
-use nix::sys::mman;
+use nix::{sys::mman, unistd};
 use wayland_server::protocol::*;
 
 use crate::backend::ShmBuffer;
 
+/// A read only mapping of a shm pool's file, which is unmapped once the pool and every buffer created from it while
+/// it was the pool's mapping are gone
 #[derive(Debug)]
-pub struct EasyShmPool {
+struct Mapping {
 	ptr: *mut c_void,
-	fd: RawFd,
 	size: usize,
 }
 
-impl EasyShmPool {
-	pub unsafe fn create(fd: RawFd, size: usize) -> Result<Self, nix::Error> {
+impl Mapping {
+	unsafe fn new(fd: RawFd, size: usize) -> Result<Self, nix::Error> {
 		let ptr: *mut c_void = mman::mmap(
 			std::ptr::null_mut(),
 			size,
@@ -25,36 +27,84 @@ impl EasyShmPool {
 			fd,
 			0,
 		)?;
-		Ok(Self { ptr, fd, size })
+		Ok(Self { ptr, size })
 	}
+}
+
+impl Drop for Mapping {
+	fn drop(&mut self) {
+		if let Err(e) = unsafe { mman::munmap(self.ptr, self.size) } {
+			log::error!("Failed to unmap a shm pool: {}", e);
+		}
+	}
+}
 
+// The mapping is never written through, so it can be read from any thread
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+/// A shm pool, which owns the file descriptor the client sent for it and closes it when dropped. The pool's mapping
+/// is shared with the buffers created from it, so dropping the pool doesn't unmap memory its buffers still use.
+#[derive(Debug)]
+pub struct EasyShmPool {
+	mapping: Arc<Mapping>,
+	fd: RawFd,
+}
+
+impl EasyShmPool {
+	/// Map a pool's file, taking ownership of `fd`. The fd is closed if it can't be mapped.
+	pub unsafe fn create(fd: RawFd, size: usize) -> Result<Self, nix::Error> {
+		match Mapping::new(fd, size) {
+			Ok(mapping) => Ok(Self {
+				mapping: Arc::new(mapping),
+				fd,
+			}),
+			Err(e) => {
+				let _ = unistd::close(fd);
+				Err(e)
+			}
+		}
+	}
+
+	/// Map the pool again at a new size. Buffers that were created before keep the old mapping alive until they're
+	/// destroyed, since the new mapping can be at a different address. The old mapping maps the same file, so those
+	/// buffers still see what the client draws into them.
 	pub unsafe fn resize(&mut self, new_size: usize) -> Result<(), nix::Error> {
-		mman::munmap(self.ptr, self.size)?;
-		let new_ptr = mman::mmap(
-			std::ptr::null_mut(),
-			new_size,
-			mman::ProtFlags::PROT_READ,
-			mman::MapFlags::MAP_SHARED,
-			self.fd,
-			0,
-		)?;
-		self.ptr = new_ptr;
-		self.size = new_size;
+		self.mapping = Arc::new(Mapping::new(self.fd, new_size)?);
 		Ok(())
 	}
 
-	pub unsafe fn duplicate(&self) -> Self {
-		EasyShmPool {
-			ptr: self.ptr,
-			fd: self.fd,
-			size: self.size,
+	/// Create a buffer in the pool's current mapping, which the buffer keeps alive
+	pub fn create_buffer(
+		&self,
+		offset: usize,
+		width: u32,
+		height: u32,
+		stride: u32,
+		format: wl_shm::Format,
+	) -> EasyShmBuffer {
+		EasyShmBuffer {
+			mapping: Arc::clone(&self.mapping),
+			offset,
+			width,
+			height,
+			stride,
+			format,
+		}
+	}
+}
+
+impl Drop for EasyShmPool {
+	fn drop(&mut self) {
+		if let Err(e) = unistd::close(self.fd) {
+			log::error!("Failed to close a shm pool's fd: {}", e);
 		}
 	}
 }
 
 #[derive(Debug)]
 pub struct EasyShmBuffer {
-	pub pool: EasyShmPool,
+	mapping: Arc<Mapping>,
 	pub offset: usize,
 	pub width: u32,
 	pub height: u32,
@@ -71,13 +121,13 @@ impl EasyShmBuffer {
 	}
 
 	pub unsafe fn get_ptr(&self) -> *mut u8 {
-		let ptr = (self.pool.ptr as *mut u8).offset(self.offset as isize) as *mut _;
+		let ptr = (self.mapping.ptr as *mut u8).offset(self.offset as isize) as *mut _;
 		ptr
 	}
 
 	pub unsafe fn as_slice<'a>(&self) -> &'a [u8] {
 		let ptr = self.get_ptr();
-		assert!(self.offset + self.get_size() <= self.pool.size);
+		assert!(self.offset + self.get_size() <= self.mapping.size);
 		let slice = std::slice::from_raw_parts(ptr as *mut _ as *const _, self.get_size() as usize);
 		std::mem::transmute(slice)
 	}
@@ -101,4 +151,35 @@ impl ShmBuffer for EasyShmBuffer {
 	}
 }
 
-unsafe impl Send for EasyShmPool {}
+#[cfg(test)]
+mod tests {
+	use std::{
+		io::{Seek, SeekFrom, Write},
+		os::unix::io::IntoRawFd,
+	};
+
+	use super::*;
+
+	#[test]
+	fn buffers_keep_their_mapping_when_the_pool_is_resized() {
+		let mut file = tempfile::tempfile().unwrap();
+		file.write_all(&[1; 16]).unwrap();
+		let pool_fd = file.try_clone().unwrap().into_raw_fd();
+		let mut pool = unsafe { EasyShmPool::create(pool_fd, 16) }.unwrap();
+		let old_buffer = pool.create_buffer(0, 2, 2, 8, wl_shm::Format::Argb8888);
+
+		file.write_all(&[2; 16]).unwrap();
+		unsafe { pool.resize(32) }.unwrap();
+		let new_buffer = pool.create_buffer(16, 2, 2, 8, wl_shm::Format::Argb8888);
+		assert!(!Arc::ptr_eq(&old_buffer.mapping, &new_buffer.mapping));
+		assert_eq!(old_buffer.mapping.size, 16);
+		assert_eq!(new_buffer.mapping.size, 32);
+
+		// The old buffer still reads the pool's file through its own mapping after the pool is gone
+		drop(pool);
+		file.seek(SeekFrom::Start(0)).unwrap();
+		file.write_all(&[3; 16]).unwrap();
+		assert_eq!(unsafe { old_buffer.as_slice() }, &[3; 16][..]);
+		assert_eq!(unsafe { new_buffer.as_slice() }, &[2; 16][..]);
+	}
+}
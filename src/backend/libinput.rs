@@ -1,5 +1,3 @@
-use std::convert::TryInto;
-
 use calloop::{
 	channel::{self, Channel, Sender},
 	generic::{EventedRawFd, Generic},
@@ -7,9 +5,9 @@ use calloop::{
 };
 use thiserror::Error;
 
-use crate::backend::{PointerButton, PointerMotion};
+use crate::backend::{AxisSource, GesturePinch, GestureSwipe, PointerAxis, PointerButton, PointerMotion, Touch};
 use crate::{
-	backend::{BackendEvent, Button, GraphicsBackend, InputBackend, KeyPress},
+	backend::{BackendEvent, Button, GraphicsBackend, InputBackend, KeyPress, KeyboardLeds},
 	compositor::Compositor,
 };
 
@@ -21,6 +19,9 @@ pub struct LibinputInputBackend {
 	event_source: Source<Generic<EventedRawFd>>,
 	event_sender: Sender<BackendEvent>,
 	event_receiver: Option<Channel<BackendEvent>>,
+	/// The connected keyboards, which have their LEDs updated whenever the lock state changes
+	keyboards: Vec<input::Device>,
+	leds: KeyboardLeds,
 }
 
 impl LibinputInputBackend {
@@ -76,6 +77,8 @@ impl LibinputInputBackend {
 			event_source,
 			event_sender,
 			event_receiver: Some(event_receiver),
+			keyboards: Vec::new(),
+			leds: KeyboardLeds::empty(),
 		})
 	}
 }
@@ -94,6 +97,9 @@ impl InputBackend for LibinputInputBackend {
 			log::error!("Failed to dispatch libinput events: {}", e);
 		});
 		while let Some(event) = self.libinput.next() {
+			if let input::Event::Device(ref device_event) = event {
+				self.handle_device_event(device_event);
+			}
 			if let Some(backend_event) = libinput_event_to_backend_event(event) {
 				let _ = self
 					.event_sender
@@ -110,10 +116,57 @@ impl InputBackend for LibinputInputBackend {
 			.take()
 			.expect("Already took event receiver from libinput backend")
 	}
+
+	fn set_keyboard_leds(&mut self, leds: KeyboardLeds) {
+		self.leds = leds;
+		for keyboard in &mut self.keyboards {
+			keyboard.led_update(keyboard_leds_to_libinput(leds));
+		}
+	}
+}
+
+impl LibinputInputBackend {
+	/// Keep track of keyboards as they're plugged in and out. New keyboards start out with the current LED state.
+	fn handle_device_event(&mut self, device_event: &input::event::DeviceEvent) {
+		use input::event::{DeviceEvent, EventTrait};
+		match *device_event {
+			DeviceEvent::Added(ref added) => {
+				let mut device = added.device();
+				if device.has_capability(input::DeviceCapability::Keyboard) {
+					device.led_update(keyboard_leds_to_libinput(self.leds));
+					self.keyboards.push(device);
+				}
+			}
+			DeviceEvent::Removed(ref removed) => {
+				let device = removed.device();
+				self.keyboards.retain(|keyboard| *keyboard != device);
+			}
+		}
+	}
+}
+
+fn keyboard_leds_to_libinput(leds: KeyboardLeds) -> input::Led {
+	let mut libinput_leds = input::Led::empty();
+	if leds.contains(KeyboardLeds::NUM_LOCK) {
+		libinput_leds |= input::Led::NUMLOCK;
+	}
+	if leds.contains(KeyboardLeds::CAPS_LOCK) {
+		libinput_leds |= input::Led::CAPSLOCK;
+	}
+	if leds.contains(KeyboardLeds::SCROLL_LOCK) {
+		libinput_leds |= input::Led::SCROLLLOCK;
+	}
+	libinput_leds
 }
 
 fn libinput_event_to_backend_event(event: input::Event) -> Option<BackendEvent> {
-	use input::event::{keyboard::KeyboardEventTrait, pointer::PointerEventTrait};
+	use input::event::{
+		gesture::{GestureEndEvent, GestureEventCoordinates, GestureEventTrait, GesturePinchEventTrait},
+		keyboard::KeyboardEventTrait,
+		pointer::{Axis, AxisSource as LibinputAxisSource, PointerEventTrait},
+		touch::{TouchEventPosition, TouchEventSlot, TouchEventTrait},
+		GestureEvent, GesturePinchEvent, GestureSwipeEvent, TouchEvent,
+	};
 	Some(match event {
 		input::Event::Keyboard(keyboard_event) => match keyboard_event {
 			input::event::KeyboardEvent::Key(keyboard_key_event) => BackendEvent::KeyPress(KeyPress {
@@ -135,19 +188,113 @@ fn libinput_event_to_backend_event(event: input::Event) -> Option<BackendEvent>
 			input::event::PointerEvent::Button(button) => BackendEvent::PointerButton(PointerButton {
 				serial: crate::compositor::get_input_serial(),
 				time: button.time(),
-				button: match button.button() {
-					0x110 => Button::Left,
-					0x111 => Button::Right,
-					0x112 => Button::Middle,
-					b => Button::Other(b.try_into().unwrap()),
-				},
+				// libinput already reports evdev button codes
+				button: Button::from_evdev(button.button()),
 				state: button.button_state().into(),
 			}),
+			input::event::PointerEvent::Axis(axis) => {
+				let value = |which| {
+					if axis.has_axis(which) {
+						axis.axis_value(which)
+					} else {
+						0.0
+					}
+				};
+				let discrete = |which| {
+					if axis.has_axis(which) {
+						axis.axis_value_discrete(which).map(|steps| steps as i32)
+					} else {
+						None
+					}
+				};
+				BackendEvent::PointerAxis(PointerAxis {
+					time: axis.time(),
+					horizontal: value(Axis::Horizontal),
+					vertical: value(Axis::Vertical),
+					horizontal_discrete: discrete(Axis::Horizontal),
+					vertical_discrete: discrete(Axis::Vertical),
+					source: match axis.axis_source() {
+						LibinputAxisSource::Wheel => AxisSource::Wheel,
+						LibinputAxisSource::Finger => AxisSource::Finger,
+						LibinputAxisSource::Continuous => AxisSource::Continuous,
+						LibinputAxisSource::WheelTilt => AxisSource::WheelTilt,
+					},
+				})
+			}
 			_ => {
 				log::warn!("Got unsupported mouse event");
 				return None;
 			}
 		},
+		input::Event::Gesture(gesture_event) => match gesture_event {
+			GestureEvent::Swipe(GestureSwipeEvent::Begin(begin)) => BackendEvent::GestureSwipe(GestureSwipe::Begin {
+				serial: crate::compositor::get_input_serial(),
+				time: begin.time(),
+				fingers: begin.finger_count() as u32,
+			}),
+			GestureEvent::Swipe(GestureSwipeEvent::Update(update)) => {
+				BackendEvent::GestureSwipe(GestureSwipe::Update {
+					time: update.time(),
+					dx: update.dx(),
+					dy: update.dy(),
+				})
+			}
+			GestureEvent::Swipe(GestureSwipeEvent::End(end)) => BackendEvent::GestureSwipe(GestureSwipe::End {
+				serial: crate::compositor::get_input_serial(),
+				time: end.time(),
+				cancelled: end.cancelled(),
+			}),
+			GestureEvent::Pinch(GesturePinchEvent::Begin(begin)) => BackendEvent::GesturePinch(GesturePinch::Begin {
+				serial: crate::compositor::get_input_serial(),
+				time: begin.time(),
+				fingers: begin.finger_count() as u32,
+			}),
+			GestureEvent::Pinch(GesturePinchEvent::Update(update)) => {
+				BackendEvent::GesturePinch(GesturePinch::Update {
+					time: update.time(),
+					dx: update.dx(),
+					dy: update.dy(),
+					scale: update.scale(),
+					rotation: update.angle_delta(),
+				})
+			}
+			GestureEvent::Pinch(GesturePinchEvent::End(end)) => BackendEvent::GesturePinch(GesturePinch::End {
+				serial: crate::compositor::get_input_serial(),
+				time: end.time(),
+				cancelled: end.cancelled(),
+			}),
+			_ => {
+				log::warn!("Got unsupported gesture event");
+				return None;
+			}
+		},
+		// Transforming to a size of 1 gives positions as fractions of the touchscreen size
+		input::Event::Touch(touch_event) => match touch_event {
+			TouchEvent::Down(down) => BackendEvent::Touch(Touch::Down {
+				serial: crate::compositor::get_input_serial(),
+				time: down.time(),
+				slot: down.seat_slot(),
+				x: down.x_transformed(1),
+				y: down.y_transformed(1),
+			}),
+			TouchEvent::Motion(motion) => BackendEvent::Touch(Touch::Motion {
+				time: motion.time(),
+				slot: motion.seat_slot(),
+				x: motion.x_transformed(1),
+				y: motion.y_transformed(1),
+			}),
+			TouchEvent::Up(up) => BackendEvent::Touch(Touch::Up {
+				serial: crate::compositor::get_input_serial(),
+				time: up.time(),
+				slot: up.seat_slot(),
+			}),
+			TouchEvent::Cancel(cancel) => BackendEvent::Touch(Touch::Cancel {
+				serial: crate::compositor::get_input_serial(),
+				time: cancel.time(),
+				slot: cancel.seat_slot(),
+			}),
+			TouchEvent::Frame(frame) => BackendEvent::Touch(Touch::Frame { time: frame.time() }),
+		},
 		u => {
 			log::trace!("Got unknown libinput event {:?}", u);
 			return None;
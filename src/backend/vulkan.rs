@@ -1,9 +1,9 @@
-use std::{fmt, os::unix::io::RawFd, path::Path};
+use std::{fmt, mem, os::unix::io::RawFd, path::Path};
 
 use festus::{
 	geometry::*,
 	present::{self, PresentBackend, PresentBackendEvent},
-	renderer::{self, texture::BufferTextureSource, Renderer, TextureSource, VulkanTextureData},
+	renderer::{self, Renderer, TextureSource, VulkanTextureData},
 	rk::{
 		ash::{version::DeviceV1_0, vk},
 		Device,
@@ -15,21 +15,177 @@ use wayland_server::protocol::*;
 use super::RgbaInfo;
 use crate::backend::{
 	easy_shm::{EasyShmBuffer, EasyShmPool},
-	GraphicsBackend, GraphicsBackendEvent, OutputInfo, Vertex,
+	shm_format_bytes_per_pixel, GraphicsBackend, GraphicsBackendEvent, OutputInfo, Vertex,
 };
 
 pub struct VulkanGraphicsBackend<P: PresentBackend> {
 	renderer: Renderer,
 	present_backend: P,
+	/// The size of the memory of every live texture, vertex buffer and MVP buffer, by the resource's id
+	memory: Vec<(u64, u64)>,
+	/// The id given to the next resource that's created
+	next_resource_id: u64,
 }
 
+/// A festus handle, along with an id that identifies the resource to the backend, since festus's handles can't be
+/// compared
+#[derive(Debug, Clone, Copy)]
+pub struct VulkanHandle<H> {
+	id: u64,
+	handle: H,
+}
+
+pub type VulkanTextureHandle = VulkanHandle<renderer::TextureHandle>;
+pub type VulkanVertexBufferHandle = VulkanHandle<renderer::VertexBufferHandle>;
+pub type VulkanMvpBufferHandle = VulkanHandle<renderer::MvpBufferHandle>;
+
 impl<P: PresentBackend> VulkanGraphicsBackend<P> {
 	pub fn new(renderer: Renderer, present_backend: P) -> Self {
 		Self {
 			renderer,
 			present_backend,
+			memory: Vec::new(),
+			next_resource_id: 0,
 		}
 	}
+
+	/// Give a newly created resource an id and count the bytes of memory allocated for it
+	fn track<H>(&mut self, handle: H, bytes: u64) -> VulkanHandle<H> {
+		let id = self.next_resource_id;
+		self.next_resource_id += 1;
+		self.memory.push((id, bytes));
+		VulkanHandle { id, handle }
+	}
+
+	/// Stop counting the memory of a resource that was destroyed
+	fn untrack<H>(&mut self, handle: VulkanHandle<H>) {
+		self.memory.retain(|&(id, _bytes)| id != handle.id);
+	}
+}
+
+/// Create an image for a texture and bind memory for it. Textures are only ever written by copying from a staging
+/// buffer and read by sampling, so the image uses optimal tiling and is placed in `DEVICE_LOCAL` memory, which is
+/// the fastest to sample from. The host never maps the image directly, so the memory doesn't have to be host visible,
+/// which is also why optimal tiling is allowed at all, since host visible optimally tiled images aren't supported
+/// by every implementation. The size of the image's memory is added to `allocated_bytes`.
+unsafe fn create_texture_image(
+	device: &Device,
+	device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+	width: u32,
+	height: u32,
+	format: vk::Format,
+	allocated_bytes: &mut u64,
+) -> Result<(vk::Image, vk::DeviceMemory), ()> {
+	let image = renderer::create_image(
+		device,
+		width,
+		height,
+		format,
+		vk::ImageTiling::OPTIMAL,
+		vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+	)?;
+	let image_memory_requirements = device.get_image_memory_requirements(image);
+	let image_memory = match renderer::allocate_memory(
+		device,
+		device_memory_properties,
+		image_memory_requirements.size,
+		vk::MemoryPropertyFlags::DEVICE_LOCAL,
+	) {
+		Ok(image_memory) => image_memory,
+		Err(()) => {
+			device.destroy_image(image, None);
+			return Err(());
+		}
+	};
+	if let Err(()) = renderer::bind_image_memory(device, image, image_memory) {
+		device.destroy_image(image, None);
+		device.free_memory(image_memory, None);
+		return Err(());
+	}
+	*allocated_bytes += image_memory_requirements.size;
+	Ok((image, image_memory))
+}
+
+/// Create a texture from tightly packed RGBA pixels, copying them to the image through a staging buffer
+unsafe fn create_rgba_texture(
+	device: &Device,
+	queue: vk::Queue,
+	command_pool: vk::CommandPool,
+	device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+	rgba: RgbaInfo,
+	allocated_bytes: &mut u64,
+) -> Result<VulkanTextureData, ()> {
+	let RgbaInfo { width, height, data } = rgba;
+	let vk_format = vk::Format::R8G8B8A8_UNORM;
+	let (staging_buffer, staging_buffer_memory) = renderer::make_buffer(
+		device,
+		device_memory_properties,
+		data,
+		vk::BufferUsageFlags::TRANSFER_SRC,
+	)?;
+	let (image, image_memory) = create_texture_image(
+		device,
+		device_memory_properties,
+		width,
+		height,
+		vk_format,
+		allocated_bytes,
+	)?;
+	renderer::transition_image_layout(
+		device,
+		queue,
+		command_pool,
+		image,
+		vk::ImageLayout::UNDEFINED,
+		vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+	)?;
+	let buffer_image_copy = vk::BufferImageCopy {
+		buffer_offset: 0,
+		buffer_row_length: 0,
+		buffer_image_height: 0,
+		image_subresource: vk::ImageSubresourceLayers {
+			aspect_mask: vk::ImageAspectFlags::COLOR,
+			mip_level: 0,
+			base_array_layer: 0,
+			layer_count: 1,
+		},
+		image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+		image_extent: vk::Extent3D {
+			width,
+			height,
+			depth: 1,
+		},
+	};
+	renderer::record_submit_one_time_commands(device, queue, command_pool, |cmd_buf| {
+		device.cmd_copy_buffer_to_image(
+			cmd_buf,
+			staging_buffer,
+			image,
+			vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+			&[buffer_image_copy],
+		);
+		Ok(())
+	})?;
+	renderer::transition_image_layout(
+		device,
+		queue,
+		command_pool,
+		image,
+		vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+		vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+	)?;
+
+	device.destroy_buffer(staging_buffer, None);
+	device.free_memory(staging_buffer_memory, None);
+
+	let image_view = renderer::create_image_view(device, image, vk_format, vk::ImageAspectFlags::COLOR)?;
+
+	Ok(VulkanTextureData {
+		image,
+		image_view,
+		image_memory,
+		size: Size::new(width, height),
+	})
 }
 
 impl<P: PresentBackend> fmt::Debug for VulkanGraphicsBackend<P> {
@@ -66,6 +222,12 @@ pub enum VulkanGraphicsBackendError {
 	ShmResizeFailed(nix::Error),
 	#[error("Vulkan error: {0}")]
 	VulkanError(vk::Result),
+	#[error("Failed to present a render result")]
+	PresentFailed,
+	#[error("Failed to get output info")]
+	OutputInfoFailed,
+	#[error("Reading back render targets isn't supported by the vulkan render backend")]
+	ReadbackUnsupported,
 }
 
 impl<P: PresentBackend + 'static> GraphicsBackend for VulkanGraphicsBackend<P> {
@@ -74,11 +236,11 @@ impl<P: PresentBackend + 'static> GraphicsBackend for VulkanGraphicsBackend<P> {
 	type ShmPool = EasyShmPool;
 	type ShmBuffer = EasyShmBuffer;
 
-	type VertexBufferHandle = festus::renderer::VertexBufferHandle;
-	type MvpBufferHandle = festus::renderer::MvpBufferHandle;
+	type VertexBufferHandle = VulkanVertexBufferHandle;
+	type MvpBufferHandle = VulkanMvpBufferHandle;
 
 	type RenderTargetHandle = festus::renderer::VulkanRenderTargetHandle;
-	type TextureHandle = festus::renderer::TextureHandle;
+	type TextureHandle = VulkanTextureHandle;
 
 	type OutputHandle = P::OutputHandle;
 
@@ -108,43 +270,35 @@ impl<P: PresentBackend + 'static> GraphicsBackend for VulkanGraphicsBackend<P> {
 		stride: u32,
 		format: wl_shm::Format,
 	) -> Result<Self::ShmBuffer, Self::Error> {
-		unsafe {
-			Ok(EasyShmBuffer {
-				pool: shm_pool.duplicate(), // TODO this is probably unsound, I was tired when I wrote it
-				offset,
-				width,
-				height,
-				stride,
-				format,
-			})
-		}
+		Ok(shm_pool.create_buffer(offset, width, height, stride, format))
 	}
 
 	fn create_texture_from_rgba(&mut self, rgba: RgbaInfo) -> Result<Self::TextureHandle, Self::Error> {
-		unsafe {
+		let mut allocated_bytes = 0;
+		let handle = unsafe {
 			self.renderer
-				.create_texture(BufferTextureSource {
-					width: rgba.width,
-					height: rgba.height,
-					format: vk::Format::R8G8B8A8_UNORM,
-					buffer: rgba.data,
+				.create_texture(RgbaTextureSource {
+					rgba,
+					allocated_bytes: &mut allocated_bytes,
 				})
-				.map_err(|_e| VulkanGraphicsBackendError::Unknown)
-		}
+				.map_err(|_e| VulkanGraphicsBackendError::Unknown)?
+		};
+		Ok(self.track(handle, allocated_bytes))
 	}
 
 	fn create_texture_from_shm_buffer(
 		&mut self,
 		shm_buffer: &Self::ShmBuffer,
 	) -> Result<Self::TextureHandle, Self::Error> {
-		unsafe {
-			let texture_source = EasyShmBufferTextureSource::new(shm_buffer);
-			let texture_handle = self.renderer.create_texture(texture_source).map_err(|_e| {
+		let mut allocated_bytes = 0;
+		let handle = unsafe {
+			let texture_source = EasyShmBufferTextureSource::new(shm_buffer, &mut allocated_bytes);
+			self.renderer.create_texture(texture_source).map_err(|_e| {
 				log::error!("An unknown error occurred while creating a texture");
 				VulkanGraphicsBackendError::VulkanError(vk::Result::ERROR_UNKNOWN)
-			})?;
-			Ok(texture_handle)
-		}
+			})?
+		};
+		Ok(self.track(handle, allocated_bytes))
 	}
 
 	fn create_vertex_buffer(
@@ -156,45 +310,56 @@ impl<P: PresentBackend + 'static> GraphicsBackend for VulkanGraphicsBackend<P> {
 			.iter()
 			.map(|vertex| festus::renderer::Vertex {
 				pos: festus::math::Point3::new(vertex.pos[0], vertex.pos[1], vertex.pos[2]),
-				col: festus::math::Vec4::new(1.0, 0.0, 0.0, 1.0),
+				col: festus::math::Vec4::new(1.0, 1.0, 1.0, 1.0),
 				tex: festus::math::Point2::new(vertex.uv[0], vertex.uv[1]),
 			})
 			.collect::<Vec<_>>();
-		unsafe {
+		let handle = unsafe {
 			self.renderer.create_vertex_buffer(&vertices, indices).map_err(|_e| {
 				log::error!("An unknown error occurred creating a vertex buffer");
 				VulkanGraphicsBackendError::VulkanError(vk::Result::ERROR_UNKNOWN)
-			})
-		}
+			})?
+		};
+		// festus sizes the vertex and index buffers to fit exactly what's uploaded to them
+		let bytes = mem::size_of_val(vertices.as_slice()) + mem::size_of_val(indices);
+		Ok(self.track(handle, bytes as u64))
 	}
 
 	fn create_mvp_buffer(&mut self, mvp: [[[f32; 4]; 4]; 3]) -> Result<Self::MvpBufferHandle, Self::Error> {
 		let mvp = renderer::Mvp::from(mvp);
-		unsafe {
+		let handle = unsafe {
 			self.renderer.create_mvp_buffer(mvp).map_err(|_e| {
 				log::error!("An unknown error occurred creating an MVP buffer");
 				VulkanGraphicsBackendError::VulkanError(vk::Result::ERROR_UNKNOWN)
-			})
-		}
+			})?
+		};
+		Ok(self.track(handle, mem::size_of::<renderer::Mvp>() as u64))
 	}
 
 	fn map_mvp_buffer(&mut self, handle: Self::MvpBufferHandle) -> Option<&mut [[[f32; 4]; 4]; 3]> {
 		unsafe {
 			self.renderer
 				.resources
-				.get_mvp_buffer(handle)
+				.get_mvp_buffer(handle.handle)
 				.map(|mvp_buffer| &mut *(mvp_buffer.mvp_buffer_memory_map as *mut [[[f32; 4]; 4]; 3]))
 		}
 	}
 
 	// A lot of assumptions are made by this function right now.
 	fn create_texture(&mut self, size: Size) -> Result<Self::TextureHandle, Self::Error> {
-		unsafe {
-			self.renderer.create_texture(UninitTextureSource { size }).map_err(|_| {
-				log::error!("An unknown error occurred creating a texture");
-				VulkanGraphicsBackendError::Unknown
-			})
-		}
+		let mut allocated_bytes = 0;
+		let handle = unsafe {
+			self.renderer
+				.create_texture(UninitTextureSource {
+					size,
+					allocated_bytes: &mut allocated_bytes,
+				})
+				.map_err(|_| {
+					log::error!("An unknown error occurred creating a texture");
+					VulkanGraphicsBackendError::Unknown
+				})?
+		};
+		Ok(self.track(handle, allocated_bytes))
 	}
 
 	fn create_render_target(&mut self, size: Size) -> Result<Self::RenderTargetHandle, Self::Error> {
@@ -220,10 +385,12 @@ impl<P: PresentBackend + 'static> GraphicsBackend for VulkanGraphicsBackend<P> {
 		texture: Self::TextureHandle,
 		mvp: Self::MvpBufferHandle,
 	) -> Result<(), Self::Error> {
-		self.renderer.draw(vertex_buffer, texture, mvp).map_err(|_e| {
-			log::error!("An unknown error occurred while drawing a surface");
-			VulkanGraphicsBackendError::Unknown
-		})?;
+		self.renderer
+			.draw(vertex_buffer.handle, texture.handle, mvp.handle)
+			.map_err(|_e| {
+				log::error!("An unknown error occurred while drawing a surface");
+				VulkanGraphicsBackendError::Unknown
+			})?;
 		Ok(())
 	}
 
@@ -247,31 +414,46 @@ impl<P: PresentBackend + 'static> GraphicsBackend for VulkanGraphicsBackend<P> {
 		unsafe {
 			self.present_backend
 				.present(&mut self.renderer, output, handle)
-				.map_err(|_e| {
-					log::error!("An unknown error occurred while presenting a render result");
-					VulkanGraphicsBackendError::Unknown
-				})
+				.map_err(|_| VulkanGraphicsBackendError::PresentFailed)
 		}
 	}
 
+	fn frames_finished(&mut self) -> Result<bool, Self::Error> {
+		// Texture sources copy client buffers into a staging buffer on the host while the texture is created, so
+		// once a frame has been presented, nothing the GPU does for it reads client memory anymore
+		Ok(true)
+	}
+
+	fn read_render_target(&mut self, _handle: Self::RenderTargetHandle) -> Result<Vec<u8>, Self::Error> {
+		// festus doesn't give access to the images of its render targets, so there's nothing to copy from
+		Err(VulkanGraphicsBackendError::ReadbackUnsupported)
+	}
+
 	fn destroy_texture(&mut self, handle: Self::TextureHandle) -> Result<(), Self::Error> {
 		unsafe {
-			self.renderer.destroy_texture(handle);
+			self.renderer.destroy_texture(handle.handle);
 		}
+		self.untrack(handle);
 		Ok(())
 	}
 
+	fn memory_usage(&self) -> u64 {
+		self.memory.iter().map(|(_id, bytes)| bytes).sum()
+	}
+
 	fn destroy_vertex_buffer(&mut self, handle: Self::VertexBufferHandle) -> Result<(), Self::Error> {
 		unsafe {
-			self.renderer.destroy_vertex_buffer(handle);
+			self.renderer.destroy_vertex_buffer(handle.handle);
 		}
+		self.untrack(handle);
 		Ok(())
 	}
 
 	fn destroy_mvp_buffer(&mut self, handle: Self::MvpBufferHandle) -> Result<(), Self::Error> {
 		unsafe {
-			self.renderer.destroy_mvp_buffer(handle);
+			self.renderer.destroy_mvp_buffer(handle.handle);
 		}
+		self.untrack(handle);
 		Ok(())
 	}
 
@@ -282,6 +464,12 @@ impl<P: PresentBackend + 'static> GraphicsBackend for VulkanGraphicsBackend<P> {
 		Ok(())
 	}
 
+	fn shutdown(&mut self) -> Result<(), Self::Error> {
+		// festus only tears down its renderer and present backend when they're dropped, so there's nothing to do
+		// before that
+		Ok(())
+	}
+
 	fn get_current_outputs(&self) -> Vec<Self::OutputHandle> {
 		self.present_backend.get_current_outputs()
 	}
@@ -290,15 +478,16 @@ impl<P: PresentBackend + 'static> GraphicsBackend for VulkanGraphicsBackend<P> {
 		self.present_backend
 			.get_output_info(output)
 			.map(|info| super::OutputInfo { size: info.size })
-			.map_err(|_| VulkanGraphicsBackendError::Unknown)
+			.map_err(|_| VulkanGraphicsBackendError::OutputInfoFailed)
 	}
 }
 
-pub struct UninitTextureSource {
+pub struct UninitTextureSource<'a> {
 	size: Size,
+	allocated_bytes: &'a mut u64,
 }
 
-impl renderer::TextureSource for UninitTextureSource {
+impl<'a> renderer::TextureSource for UninitTextureSource<'a> {
 	unsafe fn create_texture(
 		self,
 		device: &Device,
@@ -307,22 +496,14 @@ impl renderer::TextureSource for UninitTextureSource {
 		device_memory_properties: vk::PhysicalDeviceMemoryProperties,
 	) -> Result<VulkanTextureData, ()> {
 		let format = vk::Format::R8G8B8A8_UNORM;
-		let image = renderer::create_image(
+		let (image, image_memory) = create_texture_image(
 			device,
+			device_memory_properties,
 			self.size.width,
 			self.size.height,
 			format,
-			vk::ImageTiling::OPTIMAL,
-			vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+			self.allocated_bytes,
 		)?;
-		let image_memory_requirements = device.get_image_memory_requirements(image);
-		let image_memory = renderer::allocate_memory(
-			device,
-			device_memory_properties,
-			image_memory_requirements.size,
-			vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-		)?;
-		renderer::bind_image_memory(device, image, image_memory)?;
 		renderer::transition_image_layout(
 			device,
 			queue,
@@ -347,11 +528,16 @@ impl renderer::TextureSource for UninitTextureSource {
 
 pub struct EasyShmBufferTextureSource<'a> {
 	buffer: &'a EasyShmBuffer,
+	allocated_bytes: &'a mut u64,
 }
 
 impl<'a> EasyShmBufferTextureSource<'a> {
-	pub fn new(buffer: &'a EasyShmBuffer) -> Self {
-		Self { buffer }
+	/// Create a texture source for a shm buffer, which adds the size of the texture's memory to `allocated_bytes`
+	pub fn new(buffer: &'a EasyShmBuffer, allocated_bytes: &'a mut u64) -> Self {
+		Self {
+			buffer,
+			allocated_bytes,
+		}
 	}
 }
 
@@ -363,7 +549,19 @@ impl<'a> renderer::TextureSource for EasyShmBufferTextureSource<'a> {
 		command_pool: vk::CommandPool,
 		device_memory_properties: vk::PhysicalDeviceMemoryProperties,
 	) -> Result<VulkanTextureData, ()> {
-		let vk_format = wl_format_to_vk_format(self.buffer.format);
+		let (vk_format, bytes_per_pixel) = match (
+			wl_format_to_vk_format(self.buffer.format),
+			shm_format_bytes_per_pixel(self.buffer.format),
+		) {
+			(Some(vk_format), Some(bytes_per_pixel)) => (vk_format, bytes_per_pixel),
+			_ => {
+				log::error!(
+					"Can't create a texture from a shm buffer of format {:?}",
+					self.buffer.format
+				);
+				return Err(());
+			}
+		};
 		let slice = self.buffer.as_slice();
 		let (staging_buffer, staging_buffer_memory) = renderer::make_buffer(
 			device,
@@ -371,22 +569,14 @@ impl<'a> renderer::TextureSource for EasyShmBufferTextureSource<'a> {
 			slice,
 			vk::BufferUsageFlags::TRANSFER_SRC,
 		)?;
-		let image = renderer::create_image(
+		let (image, image_memory) = create_texture_image(
 			device,
+			device_memory_properties,
 			self.buffer.width as u32,
 			self.buffer.height as u32,
 			vk_format,
-			vk::ImageTiling::LINEAR,
-			vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+			self.allocated_bytes,
 		)?;
-		let image_memory_requirements = device.get_image_memory_requirements(image);
-		let image_memory = renderer::allocate_memory(
-			device,
-			device_memory_properties,
-			image_memory_requirements.size,
-			vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-		)?;
-		renderer::bind_image_memory(device, image, image_memory)?;
 		renderer::transition_image_layout(
 			device,
 			queue,
@@ -397,7 +587,8 @@ impl<'a> renderer::TextureSource for EasyShmBufferTextureSource<'a> {
 		)?;
 		let buffer_image_copy = vk::BufferImageCopy {
 			buffer_offset: 0,
-			buffer_row_length: 0,
+			// Rows of shm buffers can be padded, and the row length is counted in pixels
+			buffer_row_length: self.buffer.stride / bytes_per_pixel,
 			buffer_image_height: 0,
 			image_subresource: vk::ImageSubresourceLayers {
 				aspect_mask: vk::ImageAspectFlags::COLOR,
@@ -434,7 +625,21 @@ impl<'a> renderer::TextureSource for EasyShmBufferTextureSource<'a> {
 		device.destroy_buffer(staging_buffer, None);
 		device.free_memory(staging_buffer_memory, None);
 
-		let image_view = renderer::create_image_view(device, image, vk_format, vk::ImageAspectFlags::COLOR)?;
+		let image_view_info = vk::ImageViewCreateInfo::builder()
+			.image(image)
+			.view_type(vk::ImageViewType::TYPE_2D)
+			.format(vk_format)
+			.components(wl_format_component_mapping(self.buffer.format))
+			.subresource_range(vk::ImageSubresourceRange {
+				aspect_mask: vk::ImageAspectFlags::COLOR,
+				base_mip_level: 0,
+				level_count: 1,
+				base_array_layer: 0,
+				layer_count: 1,
+			});
+		let image_view = device.create_image_view(&image_view_info, None).map_err(|e| {
+			log::error!("Failed to create an image view for a shm buffer: {}", e);
+		})?;
 
 		Ok(VulkanTextureData {
 			image,
@@ -445,13 +650,43 @@ impl<'a> renderer::TextureSource for EasyShmBufferTextureSource<'a> {
 	}
 }
 
+/// A texture source for RGBA pixels that are already in memory
+pub struct RgbaTextureSource<'a> {
+	rgba: RgbaInfo<'a>,
+	allocated_bytes: &'a mut u64,
+}
+
+impl<'a> TextureSource for RgbaTextureSource<'a> {
+	unsafe fn create_texture(
+		self,
+		device: &Device,
+		queue: vk::Queue,
+		command_pool: vk::CommandPool,
+		device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+	) -> Result<VulkanTextureData, ()> {
+		create_rgba_texture(
+			device,
+			queue,
+			command_pool,
+			device_memory_properties,
+			self.rgba,
+			self.allocated_bytes,
+		)
+	}
+}
+
 pub struct ImagePathTextureSource<'a> {
 	path: &'a Path,
+	allocated_bytes: &'a mut u64,
 }
 
 impl<'a> ImagePathTextureSource<'a> {
-	pub fn new<P: AsRef<Path> + ?Sized>(path: &'a P) -> Self {
-		Self { path: path.as_ref() }
+	/// Create a texture source for an image file, which adds the size of the texture's memory to `allocated_bytes`
+	pub fn new<P: AsRef<Path> + ?Sized>(path: &'a P, allocated_bytes: &'a mut u64) -> Self {
+		Self {
+			path: path.as_ref(),
+			allocated_bytes,
+		}
 	}
 }
 
@@ -463,96 +698,49 @@ impl<'a> TextureSource for ImagePathTextureSource<'a> {
 		command_pool: vk::CommandPool,
 		device_memory_properties: vk::PhysicalDeviceMemoryProperties,
 	) -> Result<VulkanTextureData, ()> {
-		let vk_format = vk::Format::R8G8B8A8_UNORM;
 		let load_image = image::open(self.path)
 			.map_err(|e| log::error!("Failed to open image at path '{}': {}", self.path.display(), e))?;
 		let image_rgba = load_image.into_rgba();
 		let dims = image_rgba.dimensions();
-		let image_data = image_rgba.into_raw();
-		let (staging_buffer, staging_buffer_memory) = renderer::make_buffer(
-			device,
-			device_memory_properties,
-			image_data.as_slice(),
-			vk::BufferUsageFlags::TRANSFER_SRC,
-		)?;
-		let image = renderer::create_image(
-			device,
-			dims.0,
-			dims.1,
-			vk_format,
-			vk::ImageTiling::OPTIMAL,
-			vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
-		)?;
-		let image_memory_requirements = device.get_image_memory_requirements(image);
-		let image_memory = renderer::allocate_memory(
-			device,
-			device_memory_properties,
-			image_memory_requirements.size,
-			vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-		)?;
-		renderer::bind_image_memory(device, image, image_memory)?;
-		renderer::transition_image_layout(
+		create_rgba_texture(
 			device,
 			queue,
 			command_pool,
-			image,
-			vk::ImageLayout::UNDEFINED,
-			vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-		)?;
-		let buffer_image_copy = vk::BufferImageCopy {
-			buffer_offset: 0,
-			buffer_row_length: 0,
-			buffer_image_height: 0,
-			image_subresource: vk::ImageSubresourceLayers {
-				aspect_mask: vk::ImageAspectFlags::COLOR,
-				mip_level: 0,
-				base_array_layer: 0,
-				layer_count: 1,
-			},
-			image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
-			image_extent: vk::Extent3D {
+			device_memory_properties,
+			RgbaInfo {
 				width: dims.0,
 				height: dims.1,
-				depth: 1,
+				data: image_rgba.as_raw(),
 			},
-		};
-		renderer::record_submit_one_time_commands(device, queue, command_pool, |cmd_buf| {
-			device.cmd_copy_buffer_to_image(
-				cmd_buf,
-				staging_buffer,
-				image,
-				vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-				&[buffer_image_copy],
-			);
-			Ok(())
-		})?;
-		renderer::transition_image_layout(
-			device,
-			queue,
-			command_pool,
-			image,
-			vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-			vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-		)?;
-
-		device.destroy_buffer(staging_buffer, None);
-		device.free_memory(staging_buffer_memory, None);
-
-		let image_view = renderer::create_image_view(device, image, vk_format, vk::ImageAspectFlags::COLOR)?;
-
-		Ok(VulkanTextureData {
-			image,
-			image_view,
-			image_memory,
-			size: Size::new(dims.0, dims.1),
-		})
+			self.allocated_bytes,
+		)
 	}
 }
 
-pub fn wl_format_to_vk_format(wl_format: wl_shm::Format) -> vk::Format {
+/// Get the Vulkan format with the same memory layout as a wl_shm format, or None if there isn't one. wl_shm formats
+/// are little endian, so argb8888 and xrgb8888 are both stored as B, G, R, A/X bytes, and abgr8888 and xbgr8888 as
+/// R, G, B, A/X bytes. rgb565 is a little endian 16 bit value with red in its top bits, which is what Vulkan's packed
+/// format is too.
+pub fn wl_format_to_vk_format(wl_format: wl_shm::Format) -> Option<vk::Format> {
 	match wl_format {
-		wl_shm::Format::Argb8888 => vk::Format::B8G8R8A8_UNORM,
-		wl_shm::Format::Xrgb8888 => vk::Format::R8G8B8A8_UNORM,
-		_ => panic!("Unsupported shm format: {:?}", wl_format),
+		wl_shm::Format::Argb8888 | wl_shm::Format::Xrgb8888 => Some(vk::Format::B8G8R8A8_UNORM),
+		wl_shm::Format::Abgr8888 | wl_shm::Format::Xbgr8888 => Some(vk::Format::R8G8B8A8_UNORM),
+		wl_shm::Format::Rgb565 => Some(vk::Format::R5G6B5_UNORM_PACK16),
+		_ => None,
+	}
+}
+
+/// Get the component mapping to sample a wl_shm format with. The padding byte of formats without alpha can hold
+/// anything, so it's read as fully opaque instead of blending with whatever the client left in it.
+fn wl_format_component_mapping(wl_format: wl_shm::Format) -> vk::ComponentMapping {
+	let a = match wl_format {
+		wl_shm::Format::Xrgb8888 | wl_shm::Format::Xbgr8888 => vk::ComponentSwizzle::ONE,
+		_ => vk::ComponentSwizzle::IDENTITY,
+	};
+	vk::ComponentMapping {
+		r: vk::ComponentSwizzle::IDENTITY,
+		g: vk::ComponentSwizzle::IDENTITY,
+		b: vk::ComponentSwizzle::IDENTITY,
+		a,
 	}
 }
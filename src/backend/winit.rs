@@ -1,44 +1,85 @@
-use std::time::Instant;
-
 use calloop::channel::{self, Channel, Sender};
 use thiserror::Error;
 use winit::{
-	event::{ElementState, Event as WinitEvent, WindowEvent},
+	event::{ElementState, Event as WinitEvent, KeyboardInput, MouseScrollDelta, VirtualKeyCode, WindowEvent},
 	event_loop::{ControlFlow, EventLoop},
+	platform::desktop::EventLoopExtDesktop,
 };
 
-use crate::backend::{BackendEvent, Button, InputBackend, KeyPress, PointerButton, PointerMotion};
+use crate::backend::{
+	get_time_ms, AxisSource, BackendEvent, Button, InputBackend, KeyPress, KeyboardLeds, ModifiersChanged,
+	OutputResized, PointerAxis, PointerButton, PointerMotion,
+};
 use std::sync::Arc;
 
+/// How far one step of a scroll wheel scrolls, which is the 15 degrees libinput reports for a step of most mice
+const SCROLL_STEP: f64 = 15.0;
+
+/// An input backend that takes its input from the events of a winit window. The window's event loop is pumped
+/// whenever the backend is updated, so the window, its event loop, and the compositor that presents to it all stay
+/// on the thread the compositor runs on.
 pub struct WinitInputBackend {
+	event_loop: EventLoop<()>,
+	window: Arc<winit::window::Window>,
 	event_sender: Sender<BackendEvent>,
 	event_receiver: Option<Channel<BackendEvent>>,
+	/// Whether either control key is held down, for the binding that grabs and releases the pointer
+	ctrl_pressed: bool,
+	/// Whether the pointer is grabbed by the window, which is when pointer events are forwarded to the compositor
+	pointer_grabbed: bool,
 }
 
 impl WinitInputBackend {
-	pub fn new() -> Self {
+	pub fn new(event_loop: EventLoop<()>, window: Arc<winit::window::Window>) -> Self {
 		let (event_sender, event_receiver) = channel::channel();
 		Self {
+			event_loop,
+			window,
 			event_sender,
 			event_receiver: Some(event_receiver),
+			ctrl_pressed: false,
+			pointer_grabbed: false,
 		}
 	}
 
-	pub fn start(sender: Sender<BackendEvent>, event_loop: EventLoop<()>, window: Arc<winit::window::Window>) {
-		let start = Instant::now();
-		let mut ctrl_pressed = false;
-		let mut pointer_grabbed = false;
-		event_loop.run(
-			move |event: WinitEvent<()>, _event_loop_window_target, control_flow: &mut ControlFlow| {
-				*control_flow = ControlFlow::Wait;
+	/// Handle every event the window has received since the last update without blocking, and send the ones the
+	/// compositor cares about to its event source
+	fn pump_events(&mut self) {
+		let Self {
+			event_loop,
+			window,
+			event_sender: sender,
+			ctrl_pressed,
+			pointer_grabbed,
+			..
+		} = self;
+		event_loop.run_return(
+			|event: WinitEvent<()>, _event_loop_window_target, control_flow: &mut ControlFlow| {
+				*control_flow = ControlFlow::Poll;
 				let backend_event = match event {
 					WinitEvent::WindowEvent {
 						window_id: _window_id,
 						event: WindowEvent::CloseRequested,
+					} => Some(BackendEvent::StopRequested),
+					WinitEvent::WindowEvent {
+						window_id: _window_id,
+						event: WindowEvent::ModifiersChanged(modifiers),
 					} => {
-						*control_flow = ControlFlow::Exit;
-						Some(BackendEvent::StopRequested)
+						*ctrl_pressed = modifiers.ctrl();
+						Some(BackendEvent::ModifiersChanged(ModifiersChanged {
+							shift: modifiers.shift(),
+							ctrl: modifiers.ctrl(),
+							alt: modifiers.alt(),
+							logo: modifiers.logo(),
+						}))
 					}
+					WinitEvent::WindowEvent {
+						window_id: _window_id,
+						event: WindowEvent::Resized(size),
+					} => Some(BackendEvent::OutputResized(OutputResized {
+						width: size.width,
+						height: size.height,
+					})),
 					WinitEvent::WindowEvent {
 						window_id: _window_id,
 						event:
@@ -48,25 +89,25 @@ impl WinitInputBackend {
 								is_synthetic: _is_synthetic,
 							},
 					} => {
-						// TODO: store an xkbcommon::xkb::State in here and update it with every key
-						// press so we can keep track of modifiers and serialize them
-						if input.virtual_keycode == Some(winit::event::VirtualKeyCode::LControl) {
-							if input.state == ElementState::Pressed {
-								ctrl_pressed = true;
-							} else {
-								ctrl_pressed = false;
-							}
+						// Modifiers are tracked by the compositor's xkb state using these key events and the
+						// ModifiersChanged events, this only needs to know about ctrl for the pointer grab binding.
+						// ModifiersChanged also covers ctrl being released while the window was unfocused.
+						if matches!(
+							input.virtual_keycode,
+							Some(VirtualKeyCode::LControl) | Some(VirtualKeyCode::RControl)
+						) {
+							*ctrl_pressed = input.state == ElementState::Pressed;
 						}
 						if input.virtual_keycode == Some(winit::event::VirtualKeyCode::Space) {
-							if input.state == ElementState::Pressed && ctrl_pressed {
-								if pointer_grabbed {
-									pointer_grabbed = false;
+							if input.state == ElementState::Pressed && *ctrl_pressed {
+								if *pointer_grabbed {
+									*pointer_grabbed = false;
 									let _ = window
 										.set_cursor_grab(false)
 										.map_err(|e| log::error!("Failed to release cursor: {}", e));
 									window.set_cursor_visible(true);
 								} else {
-									pointer_grabbed = true;
+									*pointer_grabbed = true;
 									let _ = window
 										.set_cursor_grab(true)
 										.map_err(|e| log::error!("Failed to grab cursor: {}", e));
@@ -76,8 +117,8 @@ impl WinitInputBackend {
 						}
 						Some(BackendEvent::KeyPress(KeyPress {
 							serial: crate::compositor::get_input_serial(),
-							time: start.elapsed().as_millis() as u32,
-							key: input.scancode,
+							time: get_time_ms(),
+							key: evdev_keycode(&input),
 							state: input.state.into(),
 						}))
 					}
@@ -85,10 +126,10 @@ impl WinitInputBackend {
 						device_id: _device_id,
 						event: winit::event::DeviceEvent::MouseMotion { delta },
 					} => {
-						if pointer_grabbed {
+						if *pointer_grabbed {
 							Some(BackendEvent::PointerMotion(PointerMotion {
 								serial: crate::compositor::get_input_serial(),
-								time: start.elapsed().as_millis() as u32,
+								time: get_time_ms(),
 								dx: delta.0,
 								dx_unaccelerated: delta.0,
 								dy: delta.1,
@@ -102,16 +143,19 @@ impl WinitInputBackend {
 						device_id: _device_id,
 						event: winit::event::DeviceEvent::Button { button, state },
 					} => {
+						// These are X11 button numbers, 4 through 7 are used for scrolling
 						if let Some(button) = match button {
 							1 => Some(Button::Left),
 							2 => Some(Button::Middle),
 							3 => Some(Button::Right),
+							8 => Some(Button::from_evdev(Button::BTN_SIDE)),
+							9 => Some(Button::from_evdev(Button::BTN_EXTRA)),
 							_ => None,
 						} {
-							if pointer_grabbed {
+							if *pointer_grabbed {
 								Some(BackendEvent::PointerButton(PointerButton {
 									serial: crate::compositor::get_input_serial(),
-									time: start.elapsed().as_millis() as u32,
+									time: get_time_ms(),
 									button,
 									state: state.into(),
 								}))
@@ -123,6 +167,40 @@ impl WinitInputBackend {
 							None
 						}
 					}
+					WinitEvent::DeviceEvent {
+						device_id: _device_id,
+						event: winit::event::DeviceEvent::MouseWheel { delta },
+					} => {
+						// Winit scrolls up and to the left for positive deltas, the opposite of Wayland
+						let (horizontal, vertical, discrete, source) = match delta {
+							MouseScrollDelta::LineDelta(x, y) => (
+								-x as f64 * SCROLL_STEP,
+								-y as f64 * SCROLL_STEP,
+								Some((-x.round() as i32, -y.round() as i32)),
+								AxisSource::Wheel,
+							),
+							MouseScrollDelta::PixelDelta(position) => {
+								(-position.x, -position.y, None, AxisSource::Continuous)
+							}
+						};
+						if *pointer_grabbed {
+							Some(BackendEvent::PointerAxis(PointerAxis {
+								time: get_time_ms(),
+								horizontal,
+								vertical,
+								horizontal_discrete: discrete.map(|(x, _y)| x),
+								vertical_discrete: discrete.map(|(_x, y)| y),
+								source,
+							}))
+						} else {
+							None
+						}
+					}
+					// Stop pumping once every pending event has been handled, so the compositor can go on
+					WinitEvent::MainEventsCleared => {
+						*control_flow = ControlFlow::Exit;
+						None
+					}
 					_ => None,
 				};
 				if let Some(backend_event) = backend_event {
@@ -131,12 +209,143 @@ impl WinitInputBackend {
 					});
 				}
 			},
-		)
+		);
 	}
+}
 
-	pub fn get_sender(&self) -> Sender<BackendEvent> {
-		self.event_sender.clone()
+/// Get the evdev keycode for a winit key event, which is what the compositor's keymap (compiled with the "evdev"
+/// rules) expects.
+///
+/// On Linux, winit's scancodes already are evdev keycodes: under X11 they're X keycodes with the offset of 8 removed,
+/// and under Wayland they're passed through from the host compositor. These are forwarded as is. Other platforms use
+/// their own scancodes, so there the virtual keycode is translated instead, and the scancode is only passed through
+/// for keys that have no virtual keycode or no translation.
+fn evdev_keycode(input: &KeyboardInput) -> u32 {
+	if cfg!(target_os = "linux") {
+		return input.scancode;
 	}
+	input
+		.virtual_keycode
+		.and_then(virtual_keycode_to_evdev)
+		.unwrap_or(input.scancode)
+}
+
+/// Translate a winit virtual keycode to an evdev keycode (see linux/input-event-codes.h)
+fn virtual_keycode_to_evdev(keycode: VirtualKeyCode) -> Option<u32> {
+	use VirtualKeyCode::*;
+	Some(match keycode {
+		Escape => 1,
+		Key1 => 2,
+		Key2 => 3,
+		Key3 => 4,
+		Key4 => 5,
+		Key5 => 6,
+		Key6 => 7,
+		Key7 => 8,
+		Key8 => 9,
+		Key9 => 10,
+		Key0 => 11,
+		Minus => 12,
+		Equals => 13,
+		Back => 14,
+		Tab => 15,
+		Q => 16,
+		W => 17,
+		E => 18,
+		R => 19,
+		T => 20,
+		Y => 21,
+		U => 22,
+		I => 23,
+		O => 24,
+		P => 25,
+		LBracket => 26,
+		RBracket => 27,
+		Return => 28,
+		LControl => 29,
+		A => 30,
+		S => 31,
+		D => 32,
+		F => 33,
+		G => 34,
+		H => 35,
+		J => 36,
+		K => 37,
+		L => 38,
+		Semicolon => 39,
+		Apostrophe => 40,
+		Grave => 41,
+		LShift => 42,
+		Backslash => 43,
+		Z => 44,
+		X => 45,
+		C => 46,
+		V => 47,
+		B => 48,
+		N => 49,
+		M => 50,
+		Comma => 51,
+		Period => 52,
+		Slash => 53,
+		RShift => 54,
+		Multiply => 55,
+		LAlt => 56,
+		Space => 57,
+		Capital => 58,
+		F1 => 59,
+		F2 => 60,
+		F3 => 61,
+		F4 => 62,
+		F5 => 63,
+		F6 => 64,
+		F7 => 65,
+		F8 => 66,
+		F9 => 67,
+		F10 => 68,
+		Numlock => 69,
+		Scroll => 70,
+		Numpad7 => 71,
+		Numpad8 => 72,
+		Numpad9 => 73,
+		Subtract => 74,
+		Numpad4 => 75,
+		Numpad5 => 76,
+		Numpad6 => 77,
+		Add => 78,
+		Numpad1 => 79,
+		Numpad2 => 80,
+		Numpad3 => 81,
+		Numpad0 => 82,
+		Decimal => 83,
+		OEM102 => 86,
+		F11 => 87,
+		F12 => 88,
+		NumpadEnter => 96,
+		RControl => 97,
+		Divide => 98,
+		Sysrq => 99,
+		RAlt => 100,
+		Home => 102,
+		Up => 103,
+		PageUp => 104,
+		Left => 105,
+		Right => 106,
+		End => 107,
+		Down => 108,
+		PageDown => 109,
+		Insert => 110,
+		Delete => 111,
+		Mute => 113,
+		VolumeDown => 114,
+		VolumeUp => 115,
+		NumpadEquals => 117,
+		Pause => 119,
+		NumpadComma => 121,
+		LWin => 125,
+		RWin => 126,
+		Compose => 127,
+		_ => return None,
+	})
 }
 
 #[derive(Debug, Error)]
@@ -149,6 +358,7 @@ impl InputBackend for WinitInputBackend {
 	type Error = WinitInputBackendError;
 
 	fn update(&mut self) -> Result<(), Self::Error> {
+		self.pump_events();
 		Ok(())
 	}
 
@@ -157,4 +367,8 @@ impl InputBackend for WinitInputBackend {
 			.take()
 			.expect("Already took event source from Winit backend")
 	}
+
+	fn set_keyboard_leds(&mut self, _leds: KeyboardLeds) {
+		// The LEDs belong to the host's keyboard
+	}
 }
@@ -1,4 +1,4 @@
-use crate::compositor::prelude::*;
+use crate::compositor::{prelude::*, surface::same_surface};
 
 pub struct WindowManager<G: GraphicsBackend> {
 	pub manager_impl: Box<dyn WindowManagerBehavior<G>>,
@@ -19,22 +19,64 @@ impl<G: GraphicsBackend + 'static> WindowManager<G> {
 }
 
 pub trait WindowManagerBehavior<G: GraphicsBackend + 'static> {
-	fn add_surface(&mut self, surface: wl_surface::WlSurface);
+	/// Start managing a surface that has just been given a role. `work_area` is the work area of the active output,
+	/// which new windows should be placed on, or `None` if there are no outputs.
+	fn add_surface(&mut self, surface: wl_surface::WlSurface, work_area: Option<Rect>);
 
 	fn surfaces_ascending<'a>(&'a self) -> Box<dyn Iterator<Item = &'a wl_surface::WlSurface> + 'a>;
 
 	fn handle_surface_resize(&mut self, surface: wl_surface::WlSurface, size: Size);
 
+	/// Stop managing a surface because its role was destroyed or the surface itself was destroyed
+	fn remove_surface(&mut self, surface: &wl_surface::WlSurface);
+
+	/// Move a window above every other window in its layer
+	fn raise_surface(&mut self, surface: &wl_surface::WlSurface) {
+		log::warn!(
+			"The window manager doesn't support raising windows, ignoring surface@{}",
+			surface.as_ref().id()
+		);
+	}
+
+	/// Start managing a popup, which is shown directly above `parent` and the parent's other popups. The popup has
+	/// already been placed relative to its parent.
+	fn add_popup(&mut self, surface: wl_surface::WlSurface, parent: &wl_surface::WlSurface) {
+		log::warn!(
+			"The window manager doesn't support popups, ignoring surface@{} with parent surface@{}",
+			surface.as_ref().id(),
+			parent.as_ref().id()
+		);
+	}
+
+	/// Stack a window directly above `parent` so it's raised and moved along with it, like a dialog, or stop
+	/// stacking it above anything if `parent` is None
+	fn set_parent(&mut self, surface: &wl_surface::WlSurface, parent: Option<&wl_surface::WlSurface>) {
+		log::warn!(
+			"The window manager doesn't support window parents, ignoring parent {:?} of surface@{}",
+			parent.map(|parent| parent.as_ref().id()),
+			surface.as_ref().id()
+		);
+	}
+
+	/// Get the surfaces stacked above a surface, like its popups and dialogs, from the bottom up
+	fn stacked_above(&self, _surface: &wl_surface::WlSurface) -> Vec<wl_surface::WlSurface> {
+		Vec::new()
+	}
+
+	/// Pin a window above all other windows, or unpin it if it's already pinned
+	fn toggle_always_on_top(&mut self, surface: &wl_surface::WlSurface) {
+		log::warn!(
+			"The window manager doesn't support pinning windows, ignoring surface@{}",
+			surface.as_ref().id()
+		);
+	}
+
 	fn get_surface_under_point(&self, point: Point) -> Option<wl_surface::WlSurface> {
 		let mut got_surface = None;
 		for surface in self.surfaces_ascending() {
 			let surface_data = surface.get_synced::<SurfaceData<G>>();
 			let surface_data_lock = surface_data.lock().unwrap();
-			if surface_data_lock
-				.try_get_surface_geometry()
-				.map(|geometry| geometry.contains_point(point))
-				.unwrap_or(false)
-			{
+			if surface_data_lock.accepts_input_at(point) {
 				got_surface = Some(surface);
 			}
 		}
@@ -50,6 +92,7 @@ pub trait WindowManagerBehavior<G: GraphicsBackend + 'static> {
 				.try_get_window_geometry()
 				.map(|geometry| geometry.contains_point(point))
 				.unwrap_or(false)
+				&& surface_data_lock.input_region_contains(point)
 			{
 				got_surface = Some(surface);
 			}
@@ -58,6 +101,42 @@ pub trait WindowManagerBehavior<G: GraphicsBackend + 'static> {
 	}
 }
 
+/// A part of an output's work area that a window can be snapped to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapPosition {
+	LeftHalf,
+	RightHalf,
+	TopLeft,
+	TopRight,
+	BottomLeft,
+	BottomRight,
+	/// The whole work area
+	Maximized,
+	/// The whole output. Unlike the other positions, it's given the output's viewport instead of its work area.
+	Fullscreen,
+}
+
+impl SnapPosition {
+	/// Get the area a window snapped to this position covers in the given work area
+	pub fn area(self, work_area: Rect) -> Rect {
+		let left_width = work_area.width / 2;
+		let top_height = work_area.height / 2;
+		let right_x = work_area.x + left_width as i32;
+		let bottom_y = work_area.y + top_height as i32;
+		let right_width = work_area.width - left_width;
+		let bottom_height = work_area.height - top_height;
+		match self {
+			SnapPosition::LeftHalf => Rect::new(work_area.x, work_area.y, left_width, work_area.height),
+			SnapPosition::RightHalf => Rect::new(right_x, work_area.y, right_width, work_area.height),
+			SnapPosition::TopLeft => Rect::new(work_area.x, work_area.y, left_width, top_height),
+			SnapPosition::TopRight => Rect::new(right_x, work_area.y, right_width, top_height),
+			SnapPosition::BottomLeft => Rect::new(work_area.x, bottom_y, left_width, bottom_height),
+			SnapPosition::BottomRight => Rect::new(right_x, bottom_y, right_width, bottom_height),
+			SnapPosition::Maximized | SnapPosition::Fullscreen => work_area,
+		}
+	}
+}
+
 pub struct SurfaceTree<G: GraphicsBackend + ?Sized> {
 	pub(crate) nodes: Vec<Node>,
 	pub pointer: Arc<Mutex<PointerState>>,
@@ -67,11 +146,19 @@ pub struct SurfaceTree<G: GraphicsBackend + ?Sized> {
 #[derive(Clone)]
 pub struct Node {
 	pub wl_surface: wl_surface::WlSurface,
+	/// Whether the window is pinned above every window that isn't
+	pub always_on_top: bool,
+	/// The surface this one is stacked directly above, like the parent of a popup
+	pub parent: Option<wl_surface::WlSurface>,
 }
 
 impl From<wl_surface::WlSurface> for Node {
 	fn from(wl_surface: wl_surface::WlSurface) -> Self {
-		Node { wl_surface }
+		Node {
+			wl_surface,
+			always_on_top: false,
+			parent: None,
+		}
 	}
 }
 
@@ -84,8 +171,224 @@ impl<G: GraphicsBackend + 'static> SurfaceTree<G> {
 		}
 	}
 
+	/// Add a surface to the top of the tree, below any windows that are always on top. Surfaces that are already
+	/// in the tree are ignored.
 	pub fn add_surface(&mut self, surface: wl_surface::WlSurface) {
-		self.nodes.push(Node::from(surface));
+		if self.contains(&surface) {
+			log::warn!(
+				"Tried to add surface@{} to the surface tree twice",
+				surface.as_ref().id()
+			);
+			return;
+		}
+		let index = self
+			.nodes
+			.iter()
+			.position(|node| node.always_on_top)
+			.unwrap_or_else(|| self.nodes.len());
+		self.nodes.insert(index, Node::from(surface));
+	}
+
+	/// Add a popup directly above its parent and the surfaces that are already stacked above the parent. The popup
+	/// is in the same layer as its parent. Popups whose parent isn't in the tree are ignored.
+	pub fn add_popup(&mut self, surface: wl_surface::WlSurface, parent: &wl_surface::WlSurface) {
+		if self.contains(&surface) {
+			log::warn!(
+				"Tried to add surface@{} to the surface tree twice",
+				surface.as_ref().id()
+			);
+			return;
+		}
+		let parent_node = match self.find_node(parent) {
+			Some(parent_node) => parent_node.clone(),
+			None => {
+				log::warn!(
+					"Tried to add popup surface@{} without its parent",
+					surface.as_ref().id()
+				);
+				return;
+			}
+		};
+		let index = self.index_above_stack(parent);
+		self.nodes.insert(
+			index,
+			Node {
+				wl_surface: surface,
+				always_on_top: parent_node.always_on_top,
+				parent: Some(parent_node.wl_surface),
+			},
+		);
+	}
+
+	/// Stack a surface and the surfaces above it directly above `parent` and the surfaces already stacked above the
+	/// parent, in the parent's layer. If `parent` is None, the surface stops being stacked above anything and is
+	/// moved to the top of its layer. A surface can't be stacked above itself or a surface stacked above it.
+	pub fn set_parent(&mut self, surface: &wl_surface::WlSurface, parent: Option<&wl_surface::WlSurface>) {
+		let node = match self.find_node(surface) {
+			Some(node) => node,
+			None => return,
+		};
+		let unchanged = match (node.parent.as_ref(), parent) {
+			(Some(old_parent), Some(parent)) => same_surface::<G>(old_parent, parent),
+			(None, None) => true,
+			_ => false,
+		};
+		if unchanged {
+			return;
+		}
+		let parent_node = match parent.map(|parent| self.find_node(parent)) {
+			Some(Some(parent_node)) => {
+				if same_surface::<G>(&parent_node.wl_surface, surface) || self.is_descendant(parent_node, surface) {
+					log::warn!("Surface@{} can't be stacked above itself", surface.as_ref().id());
+					return;
+				}
+				Some(parent_node.clone())
+			}
+			// Parents that aren't in the tree can't be stacked above
+			Some(None) => return,
+			None => None,
+		};
+		let always_on_top = parent_node
+			.as_ref()
+			.map(|parent_node| parent_node.always_on_top)
+			.unwrap_or(node.always_on_top);
+		let mut stack = self.take_stack(surface);
+		for node in &mut stack {
+			node.always_on_top = always_on_top;
+			if same_surface::<G>(&node.wl_surface, surface) {
+				node.parent = parent_node.as_ref().map(|parent_node| parent_node.wl_surface.clone());
+			}
+		}
+		let index = match parent_node {
+			Some(parent_node) => self.index_above_stack(&parent_node.wl_surface),
+			None => self.layer_top(always_on_top),
+		};
+		self.nodes.splice(index..index, stack);
+	}
+
+	/// Get the surfaces stacked above a surface, from the bottom up
+	pub fn stacked_above(&self, surface: &wl_surface::WlSurface) -> Vec<wl_surface::WlSurface> {
+		self.nodes
+			.iter()
+			.filter(|node| self.is_descendant(node, surface))
+			.map(|node| node.wl_surface.clone())
+			.collect()
+	}
+
+	/// Get the index just above a surface and every surface stacked above it
+	fn index_above_stack(&self, surface: &wl_surface::WlSurface) -> usize {
+		self.nodes
+			.iter()
+			.rposition(|node| same_surface::<G>(&node.wl_surface, surface) || self.is_descendant(node, surface))
+			.map(|index| index + 1)
+			.unwrap_or_else(|| self.nodes.len())
+	}
+
+	/// Get the index just above the top surface of a layer
+	fn layer_top(&self, always_on_top: bool) -> usize {
+		if always_on_top {
+			self.nodes.len()
+		} else {
+			self.nodes
+				.iter()
+				.position(|node| node.always_on_top)
+				.unwrap_or_else(|| self.nodes.len())
+		}
+	}
+
+	fn find_node(&self, surface: &wl_surface::WlSurface) -> Option<&Node> {
+		self.nodes
+			.iter()
+			.find(|node| same_surface::<G>(&node.wl_surface, surface))
+	}
+
+	/// Get the surface at the bottom of the stack that `surface` is part of, which is the surface itself unless it's
+	/// stacked above a parent
+	fn root_surface(&self, surface: &wl_surface::WlSurface) -> wl_surface::WlSurface {
+		let mut root = surface.clone();
+		// Every parent is below its children in the tree, so following parents always ends within the tree's length
+		for _ in 0..self.nodes.len() {
+			match self.find_node(&root).and_then(|node| node.parent.clone()) {
+				Some(parent) => root = parent,
+				None => break,
+			}
+		}
+		root
+	}
+
+	/// Check whether `node` is stacked above `ancestor`, directly or through other surfaces
+	fn is_descendant(&self, node: &Node, ancestor: &wl_surface::WlSurface) -> bool {
+		let mut parent = node.parent.clone();
+		for _ in 0..self.nodes.len() {
+			match parent {
+				Some(ref surface) if same_surface::<G>(surface, ancestor) => return true,
+				Some(ref surface) => parent = self.find_node(surface).and_then(|node| node.parent.clone()),
+				None => return false,
+			}
+		}
+		false
+	}
+
+	/// Take a surface and every surface stacked above it out of the tree, keeping their order
+	fn take_stack(&mut self, root: &wl_surface::WlSurface) -> Vec<Node> {
+		let (stack, rest): (Vec<Node>, Vec<Node>) = self
+			.nodes
+			.iter()
+			.cloned()
+			.partition(|node| same_surface::<G>(&node.wl_surface, root) || self.is_descendant(node, root));
+		self.nodes = rest;
+		stack
+	}
+
+	/// Pin a surface above the other windows, or unpin it if it's already pinned. The tree is kept sorted so that
+	/// every pinned window is drawn after, and found under the pointer before, every window that isn't. Surfaces
+	/// stacked above the surface, like its popups, are pinned or unpinned with it.
+	pub fn toggle_always_on_top(&mut self, surface: &wl_surface::WlSurface) {
+		let root = self.root_surface(surface);
+		if let Some(always_on_top) = self.find_node(&root).map(|node| !node.always_on_top) {
+			let mut stack = self.take_stack(&root);
+			for node in &mut stack {
+				node.always_on_top = always_on_top;
+			}
+			self.nodes.append(&mut stack);
+		}
+		// The sort is stable, so windows keep their order within each layer
+		self.nodes.sort_by_key(|node| node.always_on_top);
+	}
+
+	/// Move a surface to the top of the tree, staying below any windows that are always on top if it isn't one
+	/// itself. Surfaces stacked above it, like its popups, are moved with it, and raising a popup raises its parent.
+	pub fn raise_surface(&mut self, surface: &wl_surface::WlSurface) {
+		let root = self.root_surface(surface);
+		let always_on_top = match self.find_node(&root) {
+			Some(node) => node.always_on_top,
+			None => return,
+		};
+		let stack = self.take_stack(&root);
+		let above = self.layer_top(always_on_top);
+		self.nodes.splice(above..above, stack);
+	}
+
+	/// Remove a surface from the tree without destroying it. Destroyed surfaces don't compare equal to anything, so
+	/// surfaces are matched by their surface data instead. The surfaces stacked directly above the removed one are
+	/// stacked above its parent instead, if it had one.
+	pub fn remove_surface(&mut self, surface: &wl_surface::WlSurface) {
+		let parent = self.find_node(surface).and_then(|node| node.parent.clone());
+		self.nodes.retain(|node| !same_surface::<G>(&node.wl_surface, surface));
+		for node in &mut self.nodes {
+			if node
+				.parent
+				.as_ref()
+				.map(|node_parent| same_surface::<G>(node_parent, surface))
+				.unwrap_or(false)
+			{
+				node.parent = parent.clone();
+			}
+		}
+	}
+
+	pub fn contains(&self, surface: &wl_surface::WlSurface) -> bool {
+		self.nodes.iter().any(|node| node.wl_surface == *surface)
 	}
 
 	pub fn nodes_ascending(&self) -> impl Iterator<Item = &Node> {
@@ -126,11 +429,24 @@ impl<G: GraphicsBackend + 'static> DumbWindowManagerBehavior<G> {
 }
 
 impl<G: GraphicsBackend + 'static> WindowManagerBehavior<G> for DumbWindowManagerBehavior<G> {
-	fn add_surface(&mut self, surface: wl_surface::WlSurface) {
+	fn add_surface(&mut self, surface: wl_surface::WlSurface, work_area: Option<Rect>) {
+		if self.surface_tree.contains(&surface) {
+			log::warn!(
+				"Surface@{} was already added to the window manager",
+				surface.as_ref().id()
+			);
+			return;
+		}
 		let surface_data = surface.get_synced::<SurfaceData<G>>();
 		let mut surface_data_lock = surface_data.lock().unwrap();
 		if let Some(ref _role) = surface_data_lock.role {
-			let position = Point::new((dumb_rand() % 200 + 50) as i32, (dumb_rand() % 200 + 50) as i32);
+			let origin = work_area
+				.map(|work_area| Point::new(work_area.x, work_area.y))
+				.unwrap_or(Point::new(0, 0));
+			let position = Point::new(
+				origin.x + (dumb_rand() % 200 + 50) as i32,
+				origin.y + (dumb_rand() % 200 + 50) as i32,
+			);
 			let size = Size::new(500, 375);
 			surface_data_lock.set_window_position(position);
 			surface_data_lock.resize_window(size);
@@ -147,6 +463,30 @@ impl<G: GraphicsBackend + 'static> WindowManagerBehavior<G> for DumbWindowManage
 		log::warn!("Surface resize handling not implemented");
 	}
 
+	fn remove_surface(&mut self, surface: &wl_surface::WlSurface) {
+		self.surface_tree.remove_surface(surface);
+	}
+
+	fn add_popup(&mut self, surface: wl_surface::WlSurface, parent: &wl_surface::WlSurface) {
+		self.surface_tree.add_popup(surface, parent);
+	}
+
+	fn set_parent(&mut self, surface: &wl_surface::WlSurface, parent: Option<&wl_surface::WlSurface>) {
+		self.surface_tree.set_parent(surface, parent);
+	}
+
+	fn stacked_above(&self, surface: &wl_surface::WlSurface) -> Vec<wl_surface::WlSurface> {
+		self.surface_tree.stacked_above(surface)
+	}
+
+	fn raise_surface(&mut self, surface: &wl_surface::WlSurface) {
+		self.surface_tree.raise_surface(surface);
+	}
+
+	fn toggle_always_on_top(&mut self, surface: &wl_surface::WlSurface) {
+		self.surface_tree.toggle_always_on_top(surface);
+	}
+
 	fn surfaces_ascending<'a>(&'a self) -> Box<dyn Iterator<Item = &'a wl_surface::WlSurface> + 'a> {
 		Box::new(self.surface_tree.nodes_ascending().map(|node| &node.wl_surface))
 	}
@@ -167,3 +507,178 @@ fn dumb_rand() -> u32 {
 		x
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_util::{test_client, test_client_info, test_surface, TestBackend};
+
+	type TestTree = SurfaceTree<TestBackend>;
+
+	/// Create a surface with surface data, which the tree uses to tell surfaces apart
+	fn create_surface(client: &Client, client_info: &Synced<ClientInfo>) -> wl_surface::WlSurface {
+		test_surface::<TestBackend>(client, client_info).0
+	}
+
+	fn test_tree() -> TestTree {
+		SurfaceTree::new(Arc::new(Mutex::new(PointerState {
+			pos: (0.0, 0.0),
+			sensitivity: 1.0,
+			custom_cursor: None,
+		})))
+	}
+
+	fn surfaces(tree: &TestTree) -> Vec<wl_surface::WlSurface> {
+		tree.nodes_ascending().map(|node| node.wl_surface.clone()).collect()
+	}
+
+	#[test]
+	fn add_surface_stacks_on_top() {
+		let mut display = Display::new();
+		let (client, _socket) = test_client(&mut display);
+		let client_info = test_client_info(&client);
+		let a = create_surface(&client, &client_info);
+		let b = create_surface(&client, &client_info);
+		let c = create_surface(&client, &client_info);
+		let mut tree = test_tree();
+		tree.add_surface(a.clone());
+		tree.add_surface(b.clone());
+		tree.add_surface(c.clone());
+		assert!(surfaces(&tree) == vec![a, b, c]);
+	}
+
+	#[test]
+	fn add_surface_stays_below_always_on_top() {
+		let mut display = Display::new();
+		let (client, _socket) = test_client(&mut display);
+		let client_info = test_client_info(&client);
+		let a = create_surface(&client, &client_info);
+		let pinned = create_surface(&client, &client_info);
+		let b = create_surface(&client, &client_info);
+		let mut tree = test_tree();
+		tree.add_surface(a.clone());
+		tree.add_surface(pinned.clone());
+		tree.toggle_always_on_top(&pinned);
+		tree.add_surface(b.clone());
+		assert!(surfaces(&tree) == vec![a, b, pinned]);
+	}
+
+	#[test]
+	fn add_surface_ignores_duplicates() {
+		let mut display = Display::new();
+		let (client, _socket) = test_client(&mut display);
+		let client_info = test_client_info(&client);
+		let a = create_surface(&client, &client_info);
+		let b = create_surface(&client, &client_info);
+		let mut tree = test_tree();
+		tree.add_surface(a.clone());
+		tree.add_surface(b.clone());
+		tree.add_surface(a.clone());
+		assert!(surfaces(&tree) == vec![a, b]);
+	}
+
+	#[test]
+	fn contains_only_added_surfaces() {
+		let mut display = Display::new();
+		let (client, _socket) = test_client(&mut display);
+		let client_info = test_client_info(&client);
+		let a = create_surface(&client, &client_info);
+		let b = create_surface(&client, &client_info);
+		let mut tree = test_tree();
+		assert!(!tree.contains(&a));
+		tree.add_surface(a.clone());
+		assert!(tree.contains(&a));
+		assert!(!tree.contains(&b));
+	}
+
+	#[test]
+	fn add_popup_stacks_above_parent() {
+		let mut display = Display::new();
+		let (client, _socket) = test_client(&mut display);
+		let client_info = test_client_info(&client);
+		let a = create_surface(&client, &client_info);
+		let b = create_surface(&client, &client_info);
+		let first_popup = create_surface(&client, &client_info);
+		let second_popup = create_surface(&client, &client_info);
+		let orphan = create_surface(&client, &client_info);
+		let not_added = create_surface(&client, &client_info);
+		let mut tree = test_tree();
+		tree.add_surface(a.clone());
+		tree.add_surface(b.clone());
+		tree.add_popup(first_popup.clone(), &a);
+		tree.add_popup(second_popup.clone(), &a);
+		// Popups whose parent isn't in the tree are ignored
+		tree.add_popup(orphan, &not_added);
+		assert!(surfaces(&tree) == vec![a.clone(), first_popup.clone(), second_popup.clone(), b]);
+		assert!(tree.stacked_above(&a) == vec![first_popup, second_popup]);
+	}
+
+	#[test]
+	fn set_parent_moves_the_surface_above_its_parent() {
+		let mut display = Display::new();
+		let (client, _socket) = test_client(&mut display);
+		let client_info = test_client_info(&client);
+		let a = create_surface(&client, &client_info);
+		let b = create_surface(&client, &client_info);
+		let c = create_surface(&client, &client_info);
+		let mut tree = test_tree();
+		tree.add_surface(a.clone());
+		tree.add_surface(b.clone());
+		tree.add_surface(c.clone());
+		tree.set_parent(&a, Some(&b));
+		assert!(surfaces(&tree) == vec![b.clone(), a.clone(), c.clone()]);
+		assert!(tree.stacked_above(&b) == vec![a.clone()]);
+		// A surface can't be stacked above a surface that's stacked above it
+		tree.set_parent(&b, Some(&a));
+		assert!(surfaces(&tree) == vec![b.clone(), a.clone(), c.clone()]);
+		tree.set_parent(&a, None);
+		assert!(surfaces(&tree) == vec![b.clone(), c, a]);
+		assert!(tree.stacked_above(&b).is_empty());
+	}
+
+	#[test]
+	fn toggle_always_on_top_moves_popups_along() {
+		let mut display = Display::new();
+		let (client, _socket) = test_client(&mut display);
+		let client_info = test_client_info(&client);
+		let a = create_surface(&client, &client_info);
+		let b = create_surface(&client, &client_info);
+		let popup = create_surface(&client, &client_info);
+		let c = create_surface(&client, &client_info);
+		let mut tree = test_tree();
+		tree.add_surface(a.clone());
+		tree.add_surface(b.clone());
+		tree.add_popup(popup.clone(), &a);
+		tree.toggle_always_on_top(&a);
+		assert!(surfaces(&tree) == vec![b.clone(), a.clone(), popup.clone()]);
+		tree.add_surface(c.clone());
+		assert!(surfaces(&tree) == vec![b.clone(), c.clone(), a.clone(), popup.clone()]);
+		// Toggling a popup toggles the window it belongs to
+		tree.toggle_always_on_top(&popup);
+		assert!(tree.nodes_ascending().all(|node| !node.always_on_top));
+		assert!(surfaces(&tree) == vec![b, c, a, popup]);
+	}
+
+	#[test]
+	fn raise_surface_stays_below_always_on_top() {
+		let mut display = Display::new();
+		let (client, _socket) = test_client(&mut display);
+		let client_info = test_client_info(&client);
+		let a = create_surface(&client, &client_info);
+		let b = create_surface(&client, &client_info);
+		let c = create_surface(&client, &client_info);
+		let popup = create_surface(&client, &client_info);
+		let mut tree = test_tree();
+		tree.add_surface(a.clone());
+		tree.add_surface(b.clone());
+		tree.add_surface(c.clone());
+		tree.add_popup(popup.clone(), &a);
+		// Raising a popup raises its parent along with it
+		tree.raise_surface(&popup);
+		assert!(surfaces(&tree) == vec![b.clone(), c.clone(), a.clone(), popup.clone()]);
+		tree.toggle_always_on_top(&b);
+		assert!(surfaces(&tree) == vec![c.clone(), a.clone(), popup.clone(), b.clone()]);
+		tree.raise_surface(&c);
+		assert!(surfaces(&tree) == vec![a, popup, c, b]);
+	}
+}
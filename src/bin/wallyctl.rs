@@ -0,0 +1,60 @@
+use std::{
+	io::{BufRead, BufReader, Write},
+	os::unix::net::UnixStream,
+	path::PathBuf,
+	process,
+};
+
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+#[structopt(name = "wallyctl", about = "Send a request to the control socket of a running wally")]
+struct Opts {
+	#[structopt(
+		short,
+		long,
+		env = "WALLY_IPC_SOCKET",
+		parse(from_os_str),
+		help = "The path of the control socket, as passed to wally with --ipc-socket"
+	)]
+	socket: PathBuf,
+	#[structopt(
+		name = "REQUEST",
+		required = true,
+		help = "The request to send, such as \"windows\", \"focus 3\", or \"snap focused left-half\""
+	)]
+	request: Vec<String>,
+}
+
+fn main() {
+	let opts = Opts::from_args();
+	let mut stream = match UnixStream::connect(&opts.socket) {
+		Ok(stream) => stream,
+		Err(e) => {
+			eprintln!("Failed to connect to '{}': {}", opts.socket.display(), e);
+			process::exit(1);
+		}
+	};
+	if let Err(e) = writeln!(stream, "{}", opts.request.join(" ")) {
+		eprintln!("Failed to send the request: {}", e);
+		process::exit(1);
+	}
+	for line in BufReader::new(stream).lines() {
+		let line = match line {
+			Ok(line) => line,
+			Err(e) => {
+				eprintln!("Failed to read the response: {}", e);
+				process::exit(1);
+			}
+		};
+		if line == "ok" {
+			return;
+		} else if line.starts_with("error: ") {
+			eprintln!("{}", &line["error: ".len()..]);
+			process::exit(1);
+		}
+		println!("{}", line);
+	}
+	eprintln!("The compositor closed the connection without responding");
+	process::exit(1);
+}
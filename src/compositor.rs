@@ -1,37 +1,62 @@
 use std::{
+	env,
+	ffi::{OsStr, OsString},
 	fmt,
 	fs::{self},
 	io::{self},
 	marker::PhantomData,
+	mem,
+	path::PathBuf,
 	sync::atomic::{AtomicBool, AtomicU32, Ordering},
+	thread,
 	time::{Duration, Instant},
 };
 
 use calloop::{
 	mio,
 	signals::{Signal, Signals},
+	timer::{Timer, TimerHandle},
 	EventLoop, LoopHandle, Source,
 };
 use std::sync::{Arc, Mutex};
 use thiserror::Error;
 use wayland_server::{protocol::*, Client, Display, Filter, Global, Interface, Main, Resource};
+use xkbcommon::xkb;
 
 use crate::{
-	backend::{BackendEvent, GraphicsBackend, InputBackend, ShmBuffer},
-	behavior::WindowManager,
+	backend::{BackendEvent, Button, GraphicsBackend, InputBackend, ShmBuffer, Touch},
+	behavior::{SnapPosition, WindowManager},
 	compositor::prelude::*,
-	compositor::surface::SurfaceData,
-	input::KeyboardState,
-	renderer::{Output, Renderer},
+	compositor::{
+		buffer::BufferData,
+		client::ClientUsage,
+		ipc::IpcServer,
+		region::RegionData,
+		seat::send_pointer_frame,
+		surface::{same_surface, SurfaceData},
+	},
+	config::{Config, NewWindowFocus},
+	input::{KeyModifiers, Keybinding, KeyboardState},
+	renderer::{hud::HudStats, Output, Renderer, ResourceCounts},
 };
 
+pub mod buffer;
+pub mod builder;
 pub mod client;
+pub mod data_device;
+pub mod gestures;
+pub mod ipc;
+pub mod key_repeat;
 pub mod output;
+pub mod ping;
+pub mod region;
 pub mod role;
 pub mod seat;
 pub mod shell;
 pub mod shm;
 pub mod surface;
+pub mod touch;
+pub mod window;
 pub mod xdg;
 
 pub mod prelude {
@@ -82,8 +107,37 @@ where
 }
 
 pub(crate) static INPUT_SERIAL: AtomicU32 = AtomicU32::new(1);
-pub(crate) static PROFILE_OUTPUT: AtomicBool = AtomicBool::new(false);
-pub(crate) static DEBUG_OUTPUT: AtomicBool = AtomicBool::new(false);
+pub static PROFILE_OUTPUT: AtomicBool = AtomicBool::new(false);
+pub static DEBUG_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// How often the statistics shown on the debug HUD are refreshed
+const HUD_UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long each phase of a frame took. With profiling output enabled this is logged as a single line of `key=value`
+/// fields per frame, so the phases of different frames can be lined up and compared.
+#[derive(Debug, Default)]
+struct FrameTimings {
+	phases: Vec<(&'static str, Duration)>,
+}
+
+impl FrameTimings {
+	/// Record that a phase which started at `start` has just finished
+	fn record(&mut self, phase: &'static str, start: Instant) {
+		self.phases.push((phase, start.elapsed()));
+	}
+}
+
+impl fmt::Display for FrameTimings {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		for (i, (phase, duration)) in self.phases.iter().enumerate() {
+			if i > 0 {
+				write!(f, " ")?;
+			}
+			write!(f, "{}_ms={:.3}", phase, duration.as_secs_f64() * 1000.0)?;
+		}
+		Ok(())
+	}
+}
 
 pub fn get_input_serial() -> u32 {
 	INPUT_SERIAL.fetch_add(1, Ordering::Relaxed)
@@ -99,6 +153,10 @@ pub fn debug_output() -> bool {
 
 pub struct Compositor<I: InputBackend, G: GraphicsBackend> {
 	display: Display,
+	/// The name of the wayland socket clients connect to
+	socket_name: OsString,
+	/// The path of the wayland socket, which is removed when the compositor is dropped
+	socket_path: Option<PathBuf>,
 	inner: Arc<Mutex<CompositorInner<I, G>>>,
 	pub(crate) input_backend_state: Arc<Mutex<InputBackendState<I>>>,
 	pub(crate) graphics_backend_state: Arc<Mutex<GraphicsBackendState<G>>>,
@@ -106,6 +164,9 @@ pub struct Compositor<I: InputBackend, G: GraphicsBackend> {
 	_idle_event_source: calloop::Idle,
 	_display_event_source: calloop::Source<calloop::generic::Generic<calloop::generic::EventedRawFd>>,
 	_input_event_source: calloop::Source<calloop::channel::Channel<BackendEvent>>,
+	_key_repeat_event_source: Source<Timer<()>>,
+	/// The control socket, if one was configured
+	ipc_server: Option<IpcServer<I, G>>,
 }
 
 pub struct InputBackendState<I: InputBackend> {
@@ -118,16 +179,110 @@ pub struct GraphicsBackendState<G: GraphicsBackend> {
 
 pub struct CompositorInner<I: InputBackend, G: GraphicsBackend> {
 	running: bool,
+	pub config: Config,
 	pub client_manager: ClientManager,
 	pub window_manager: WindowManager<G>,
 	pub pointer: Synced<PointerState>,
 	pub pointer_focus: Option<wl_surface::WlSurface>,
+	/// The surface the current touchpad gesture began on, which receives the rest of the gesture
+	pub gesture_focus: Option<wl_surface::WlSurface>,
+	/// Whether the cursor is currently hidden because the user is typing
+	pub cursor_hidden: bool,
 	pub keyboard_state: Synced<KeyboardState>,
 	pub keyboard_focus: Option<wl_surface::WlSurface>,
-	pub output_globals: Vec<(Global<wl_output::WlOutput>, Output<G>)>,
+	/// The data source of the current clipboard selection
+	pub selection: Option<wl_data_source::WlDataSource>,
+	pub output_globals: Vec<(Global<wl_output::WlOutput>, Synced<Output<G>>)>,
+	pub keybindings: Vec<(Keybinding, KeybindingAction<I, G>)>,
+	pub surface_listeners: Vec<SurfaceListener<I, G>>,
+	/// The keys whose press triggered a keybinding, so that their release isn't sent to clients either
+	grabbed_keys: Vec<u32>,
+	/// The serials of the last pointer button press and key press sent to clients, which clients pass back to start
+	/// interactive grabs
+	pointer_button_serial: Option<u32>,
+	key_serial: Option<u32>,
+	/// The serial of the enter event sent for the surface with pointer focus, which clients pass back to set the
+	/// cursor
+	pointer_enter_serial: Option<u32>,
+	/// The time of the last press of every pointer button that has been pressed, used to detect double clicks
+	button_presses: Vec<(Button, u32)>,
+	/// The slot of the touch point that is moving the pointer while pointer emulation is enabled
+	emulated_touch_slot: Option<u32>,
+	/// The touch points that are down on surfaces while pointer emulation is disabled, and the clients that have
+	/// been sent touch events since the last touch frame
+	pub(crate) touch_points: Vec<touch::TouchPoint>,
+	touch_frame_clients: Vec<Synced<ClientInfo>>,
+	/// The key being repeated if server side repeat is enabled, and the timer its repeats are scheduled on
+	pub(crate) key_repeat: Option<key_repeat::KeyRepeat>,
+	key_repeat_timer: TimerHandle<()>,
+	/// The window being moved with the pointer, and the offset from the pointer to the window's position
+	pub moving_surface: Option<(wl_surface::WlSurface, Point)>,
+	/// The ping state of every bound xdg_wm_base
+	pub(crate) xdg_wm_base_pings: Vec<ping::XdgWmBasePing>,
 	phantom: PhantomData<I>,
 }
 
+/// An action run by the compositor when a keybinding is pressed
+pub type KeybindingAction<I, G> = Box<dyn FnMut(&mut CompositorInner<I, G>) + Send>;
+
+/// Something that happened to a surface, which is reported to every registered [`SurfaceListener`]
+#[derive(Debug, Clone)]
+pub enum SurfaceEvent {
+	/// The surface committed a buffer after having none, and is now shown
+	Mapped(wl_surface::WlSurface),
+	/// The surface committed a null buffer, and is no longer shown
+	Unmapped(wl_surface::WlSurface),
+	/// The surface committed a buffer with a different size than its previous one
+	Resized(wl_surface::WlSurface, Size),
+	/// The window gave itself a new title
+	TitleChanged(wl_surface::WlSurface, String),
+	/// The surface was destroyed. It is no longer alive, but its surface data can still be read.
+	Destroyed(wl_surface::WlSurface),
+}
+
+/// A function run by the compositor whenever a [`SurfaceEvent`] happens, which lets embedders implement their own
+/// window management
+pub type SurfaceListener<I, G> = Box<dyn FnMut(&mut CompositorInner<I, G>, &SurfaceEvent) + Send>;
+
+/// The double click time used when the config doesn't set one, in milliseconds
+const DEFAULT_DOUBLE_CLICK_TIME: u32 = 400;
+
+/// The keybindings every compositor starts with. Embedders can add their own through the
+/// [`CompositorBuilder`](builder::CompositorBuilder).
+fn default_keybindings<I: InputBackend + 'static, G: GraphicsBackend + 'static>(
+) -> Vec<(Keybinding, KeybindingAction<I, G>)> {
+	vec![
+		(
+			Keybinding::new(KeyModifiers::LOGO, xkb::keysyms::KEY_Left),
+			Box::new(|inner| inner.snap_focused(SnapPosition::LeftHalf)),
+		),
+		(
+			Keybinding::new(KeyModifiers::LOGO, xkb::keysyms::KEY_Right),
+			Box::new(|inner| inner.snap_focused(SnapPosition::RightHalf)),
+		),
+		(
+			Keybinding::new(KeyModifiers::LOGO, xkb::keysyms::KEY_Home),
+			Box::new(|inner| inner.snap_focused(SnapPosition::TopLeft)),
+		),
+		(
+			Keybinding::new(KeyModifiers::LOGO, xkb::keysyms::KEY_Prior),
+			Box::new(|inner| inner.snap_focused(SnapPosition::TopRight)),
+		),
+		(
+			Keybinding::new(KeyModifiers::LOGO, xkb::keysyms::KEY_End),
+			Box::new(|inner| inner.snap_focused(SnapPosition::BottomLeft)),
+		),
+		(
+			Keybinding::new(KeyModifiers::LOGO, xkb::keysyms::KEY_Next),
+			Box::new(|inner| inner.snap_focused(SnapPosition::BottomRight)),
+		),
+		(
+			Keybinding::new(KeyModifiers::LOGO, xkb::keysyms::KEY_t),
+			Box::new(|inner| inner.toggle_focused_always_on_top()),
+		),
+	]
+}
+
 pub struct PointerState {
 	pub pos: (f64, f64),
 	pub sensitivity: f64,
@@ -159,6 +314,85 @@ impl fmt::Debug for CustomCursor {
 }
 
 impl<I: InputBackend, G: GraphicsBackend> CompositorInner<I, G> {
+	/// Stop the compositor after the current frame
+	pub fn stop(&mut self) {
+		self.running = false;
+	}
+
+	/// Run `action` whenever `keybinding` is pressed
+	pub fn add_keybinding<F>(&mut self, keybinding: Keybinding, action: F)
+	where
+		F: FnMut(&mut CompositorInner<I, G>) + Send + 'static,
+	{
+		self.keybindings.push((keybinding, Box::new(action)));
+	}
+
+	/// Run the action bound to the given key combination, returning whether there was one
+	fn run_keybinding(&mut self, keybinding: Keybinding) -> bool {
+		let mut keybindings = mem::take(&mut self.keybindings);
+		let ran = match keybindings.iter_mut().find(|(bound, _action)| *bound == keybinding) {
+			Some((_bound, action)) => {
+				action(self);
+				true
+			}
+			None => false,
+		};
+		// Keep any keybindings that the action added
+		keybindings.append(&mut self.keybindings);
+		self.keybindings = keybindings;
+		ran
+	}
+
+	/// Run `listener` whenever something happens to a surface
+	pub fn add_surface_listener<F>(&mut self, listener: F)
+	where
+		F: FnMut(&mut CompositorInner<I, G>, &SurfaceEvent) + Send + 'static,
+	{
+		self.surface_listeners.push(Box::new(listener));
+	}
+
+	/// Report a surface event to every surface listener
+	pub(crate) fn emit_surface_event(&mut self, event: SurfaceEvent) {
+		let mut surface_listeners = mem::take(&mut self.surface_listeners);
+		for listener in &mut surface_listeners {
+			listener(self, &event);
+		}
+		// Keep any listeners that were added while handling the event
+		surface_listeners.append(&mut self.surface_listeners);
+		self.surface_listeners = surface_listeners;
+	}
+
+	/// Check whether `serial` is the serial of the latest pointer button or key press. Requests that start a grab,
+	/// like interactive moves or popup grabs, must be in response to one of those, and any older serial is stale.
+	pub fn validate_serial(&self, serial: u32) -> bool {
+		self.pointer_button_serial == Some(serial) || self.key_serial == Some(serial)
+	}
+
+	/// Check whether `serial` is the serial of the latest pointer button press, for grabs that last until the
+	/// button is released
+	pub fn validate_pointer_button_serial(&self, serial: u32) -> bool {
+		self.pointer_button_serial == Some(serial)
+	}
+
+	/// Record a press of a pointer button at `time`, returning whether it finishes a double click. A press that
+	/// finishes a double click doesn't start another one, so triple clicks aren't reported as two double clicks.
+	pub fn register_button_press(&mut self, button: Button, time: u32) -> bool {
+		let double_click_time = self.config.double_click_time.unwrap_or(DEFAULT_DOUBLE_CLICK_TIME);
+		if let Some(i) = self
+			.button_presses
+			.iter()
+			.position(|&(pressed, _time)| pressed == button)
+		{
+			let (_button, last_time) = self.button_presses.remove(i);
+			// Input timestamps are in milliseconds and wrap around
+			if time.wrapping_sub(last_time) <= double_click_time {
+				return true;
+			}
+		}
+		self.button_presses.push((button, time));
+		false
+	}
+
 	fn trim_dead_clients(&mut self) {
 		/* self.surface_tree.surfaces.retain(|surface| {
 			log::debug!("Checking surface");
@@ -172,6 +406,362 @@ impl<I: InputBackend, G: GraphicsBackend> CompositorInner<I, G> {
 	}
 }
 
+impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> CompositorInner<I, G> {
+	/// Create the state of a compositor that has no clients or outputs yet. Key repeats are scheduled on
+	/// `key_repeat_timer`.
+	pub(crate) fn new(config: Config, key_repeat_timer: TimerHandle<()>) -> Self {
+		let client_manager = ClientManager::new();
+
+		let pointer_state = Arc::new(Mutex::new(PointerState {
+			pos: (0.0, 0.0),
+			sensitivity: 1.0,
+			custom_cursor: None,
+		}));
+		let mut keyboard_state = KeyboardState::new();
+		if let Some(repeat_rate) = config.repeat_rate {
+			keyboard_state.repeat_rate = repeat_rate as i32;
+		}
+		if let Some(repeat_delay) = config.repeat_delay {
+			keyboard_state.repeat_delay = repeat_delay as i32;
+		}
+		let keyboard_state = Arc::new(Mutex::new(keyboard_state));
+
+		CompositorInner {
+			running: true,
+			config,
+			client_manager,
+			window_manager: WindowManager::new(Box::new(crate::behavior::DumbWindowManagerBehavior::new(Arc::clone(
+				&pointer_state,
+			)))),
+			//surface_tree: SurfaceTree::new(Arc::clone(&pointer_state)),
+			pointer: pointer_state,
+			pointer_focus: None,
+			gesture_focus: None,
+			cursor_hidden: false,
+			keyboard_state,
+			keyboard_focus: None,
+			selection: None,
+			output_globals: Vec::new(),
+			keybindings: default_keybindings(),
+			grabbed_keys: Vec::new(),
+			pointer_button_serial: None,
+			key_serial: None,
+			pointer_enter_serial: None,
+			surface_listeners: Vec::new(),
+			button_presses: Vec::new(),
+			emulated_touch_slot: None,
+			touch_points: Vec::new(),
+			touch_frame_clients: Vec::new(),
+			key_repeat: None,
+			key_repeat_timer,
+			moving_surface: None,
+			xdg_wm_base_pings: Vec::new(),
+			phantom: PhantomData,
+		}
+	}
+
+	/// Pin the window with keyboard focus above all other windows, or unpin it if it's already pinned
+	pub fn toggle_focused_always_on_top(&mut self) {
+		if let Some(focused) = self.keyboard_focus.clone() {
+			self.window_manager.manager_impl.toggle_always_on_top(&focused);
+		}
+	}
+
+	/// Start treating a surface as a window once it has a window role and a committed buffer. The window manager
+	/// never sees windows that have nothing to draw, so it can't place or focus them before they can be shown.
+	pub fn map_window(&mut self, surface: &wl_surface::WlSurface) {
+		let surface_data = surface.get_synced::<SurfaceData<G>>();
+		let role = surface_data.lock().unwrap().role.clone();
+		if let Some((parent, geometry)) = role.as_ref().and_then(Role::popup_placement) {
+			self.map_popup(surface, &parent, geometry);
+			return;
+		}
+		let is_window = role.as_ref().map(Role::is_window).unwrap_or(false);
+		if !is_window {
+			return;
+		}
+		let work_area = self.active_output().map(|output| output.viewport);
+		self.window_manager.manager_impl.add_surface(surface.clone(), work_area);
+		self.update_toplevel_parents(surface);
+		// Send output enter events for every output viewport the window intersects
+		let mut surface_data_lock = surface_data.lock().unwrap();
+		output::update_surface_outputs(surface, &mut surface_data_lock);
+		drop(surface_data_lock);
+		if self.should_focus_new_window(surface) {
+			self.focus_surface(surface);
+		}
+	}
+
+	/// Give a surface keyboard focus. The surface that had focus is sent a leave event, and the new one is sent an
+	/// enter event, the current modifiers, and the selection.
+	pub fn focus_surface(&mut self, surface: &wl_surface::WlSurface) {
+		if let Some(old_keyboard_focus) = self.keyboard_focus.take() {
+			if same_surface::<G>(&old_keyboard_focus, surface) {
+				self.keyboard_focus = Some(old_keyboard_focus);
+				return;
+			}
+			if old_keyboard_focus.as_ref().is_alive() {
+				let old_surface_data = old_keyboard_focus.get_synced::<SurfaceData<G>>();
+				let old_surface_data_lock = old_surface_data.lock().unwrap();
+				let old_client_info_lock = old_surface_data_lock.client_info.lock().unwrap();
+				for keyboard in &old_client_info_lock.keyboards {
+					keyboard.leave(get_input_serial(), &old_keyboard_focus);
+				}
+			}
+		}
+		let surface_data = surface.get_synced::<SurfaceData<G>>();
+		let surface_data_lock = surface_data.lock().unwrap();
+		let client_info_lock = surface_data_lock.client_info.lock().unwrap();
+		let mods = self.keyboard_state.lock().unwrap().xkb_modifiers_state;
+		for keyboard in &client_info_lock.keyboards {
+			keyboard.enter(get_input_serial(), surface, Vec::new());
+			keyboard.modifiers(
+				get_input_serial(),
+				mods.mods_depressed,
+				mods.mods_latched,
+				mods.mods_locked,
+				mods.group,
+			);
+		}
+		data_device::offer_selection(self.selection.as_ref(), &client_info_lock);
+		self.keyboard_focus = Some(surface.clone());
+	}
+
+	/// Decide whether a window that was just mapped should take keyboard focus, following the new window focus
+	/// policy. Windows of the client that has focus are usually dialogs the user asked for, while windows of other
+	/// clients would take the keyboard away from whatever the user is typing into.
+	fn should_focus_new_window(&self, surface: &wl_surface::WlSurface) -> bool {
+		let client_info = |surface: &wl_surface::WlSurface| {
+			Arc::clone(&surface.get_synced::<SurfaceData<G>>().lock().unwrap().client_info)
+		};
+		match self.config.new_window_focus {
+			NewWindowFocus::Never => false,
+			NewWindowFocus::Always => true,
+			NewWindowFocus::SameClient => match self.keyboard_focus {
+				Some(ref focused) => Arc::ptr_eq(&client_info(focused), &client_info(surface)),
+				None => true,
+			},
+		}
+	}
+
+	/// Stop treating a surface as a window, because it was unmapped or its role or the surface itself was
+	/// destroyed. The surface is removed from the window manager and loses any focus it had.
+	pub fn unmap_window(&mut self, surface: &wl_surface::WlSurface) {
+		self.window_manager.manager_impl.remove_surface(surface);
+		let is_surface = |focus: &Option<wl_surface::WlSurface>| {
+			focus
+				.as_ref()
+				.map(|focus| same_surface::<G>(focus, surface))
+				.unwrap_or(false)
+		};
+		let had_keyboard_focus = is_surface(&self.keyboard_focus);
+		let had_pointer_focus = is_surface(&self.pointer_focus);
+		if is_surface(&self.gesture_focus) {
+			self.gesture_focus = None;
+		}
+		self.touch_points
+			.retain(|touch_point| !same_surface::<G>(&touch_point.surface, surface));
+		let was_moving = self
+			.moving_surface
+			.as_ref()
+			.map(|(moving, _offset)| same_surface::<G>(moving, surface))
+			.unwrap_or(false);
+		if was_moving {
+			self.moving_surface = None;
+		}
+		if had_keyboard_focus {
+			self.keyboard_focus = None;
+		}
+		if had_pointer_focus {
+			self.pointer_focus = None;
+			self.reset_cursor();
+		}
+		// Clients are only told about losing focus if they can still use the surface
+		if (had_keyboard_focus || had_pointer_focus) && surface.as_ref().is_alive() {
+			let surface_data = surface.get_synced::<SurfaceData<G>>();
+			let surface_data_lock = surface_data.lock().unwrap();
+			let client_info_lock = surface_data_lock.client_info.lock().unwrap();
+			if had_keyboard_focus {
+				for keyboard in &client_info_lock.keyboards {
+					keyboard.leave(get_input_serial(), surface);
+				}
+			}
+			if had_pointer_focus {
+				for pointer in &client_info_lock.pointers {
+					pointer.leave(get_input_serial(), surface);
+					send_pointer_frame(pointer);
+				}
+			}
+		}
+	}
+
+	/// Go back to the default cursor and forget the pointer enter serial, for when the pointer leaves a surface
+	pub(crate) fn reset_cursor(&mut self) {
+		self.pointer_enter_serial = None;
+		self.pointer.lock().unwrap().custom_cursor = None;
+	}
+
+	/// Start moving a window with the pointer until a pointer button is released. The window keeps its position
+	/// relative to the pointer while it's moved, and no surface gets pointer focus or motion events in the meantime.
+	pub fn start_move(&mut self, surface: &wl_surface::WlSurface) {
+		let pointer_pos = self.pointer.lock().unwrap().pos;
+		let pointer_pos = Point::new(pointer_pos.0.round() as i32, pointer_pos.1.round() as i32);
+		let window_geometry = match surface
+			.get_synced::<SurfaceData<G>>()
+			.lock()
+			.unwrap()
+			.try_get_window_geometry()
+		{
+			Some(window_geometry) => window_geometry,
+			None => {
+				log::debug!("Ignoring move of a window that hasn't been placed yet");
+				return;
+			}
+		};
+		let offset = Point::new(window_geometry.x - pointer_pos.x, window_geometry.y - pointer_pos.y);
+		self.moving_surface = Some((surface.clone(), offset));
+	}
+
+	/// Move a window so its window geometry is at `position`. The surfaces stacked above it, like its dialogs and
+	/// popups, are moved along with it.
+	pub fn move_window(&mut self, surface: &wl_surface::WlSurface, position: Point) {
+		let surface_data = surface.get_synced::<SurfaceData<G>>();
+		let mut surface_data_lock = surface_data.lock().unwrap();
+		let old_position = surface_data_lock.position;
+		surface_data_lock.set_window_position(position);
+		output::update_surface_outputs(surface, &mut surface_data_lock);
+		let new_position = surface_data_lock.position;
+		drop(surface_data_lock);
+		if let (Some(old_position), Some(new_position)) = (old_position, new_position) {
+			let offset = Point::new(new_position.x - old_position.x, new_position.y - old_position.y);
+			for child in self.window_manager.manager_impl.stacked_above(surface) {
+				let child_data = child.get_synced::<SurfaceData<G>>();
+				let mut child_data_lock = child_data.lock().unwrap();
+				child_data_lock.translate(offset);
+				output::update_surface_outputs(&child, &mut child_data_lock);
+			}
+		}
+	}
+
+	/// Snap the window with keyboard focus to part of the work area of the output its center is on, or restore its
+	/// previous geometry if it's already snapped there
+	pub fn snap_focused(&mut self, position: SnapPosition) {
+		if let Some(focused) = self.keyboard_focus.clone() {
+			self.snap_surface(&focused, position);
+		}
+	}
+
+	/// Snap a window to part of the work area of the output its center is on, or restore its previous geometry if
+	/// it's already snapped there
+	pub fn snap_surface(&mut self, surface: &wl_surface::WlSurface, position: SnapPosition) {
+		let surface_data = surface.get_synced::<SurfaceData<G>>();
+		let mut surface_data_lock = surface_data.lock().unwrap();
+		let center = match surface_data_lock.try_get_window_center() {
+			Some(center) => center,
+			None => return,
+		};
+		if let Some(work_area) = self.work_area_at(center) {
+			surface_data_lock.snap_window(position, work_area);
+			output::update_surface_outputs(surface, &mut surface_data_lock);
+		}
+	}
+
+	/// Maximize a window to the work area of the output its top left corner is on, remembering its geometry so it
+	/// can be restored by [`unmaximize_surface`](Self::unmaximize_surface)
+	pub fn maximize_surface(&mut self, surface: &wl_surface::WlSurface) {
+		let surface_data = surface.get_synced::<SurfaceData<G>>();
+		let mut surface_data_lock = surface_data.lock().unwrap();
+		let top_left = match surface_data_lock.try_get_window_geometry() {
+			Some(geometry) => Point::new(geometry.x, geometry.y),
+			None => return,
+		};
+		if let Some(work_area) = self.work_area_at(top_left) {
+			let maximized = surface_data_lock.snap.map(|snap| snap.position) == Some(SnapPosition::Maximized);
+			// Snapping a window to where it already is would unsnap it, but a maximized window asked to maximize
+			// should just be configured again
+			if maximized {
+				surface_data_lock.resnap_window(work_area);
+			} else {
+				surface_data_lock.snap_window(SnapPosition::Maximized, work_area);
+			}
+			output::update_surface_outputs(surface, &mut surface_data_lock);
+		}
+	}
+
+	/// Restore the geometry a maximized window had before it was maximized. Windows that aren't maximized are left
+	/// alone, even if they are snapped somewhere else.
+	pub fn unmaximize_surface(&mut self, surface: &wl_surface::WlSurface) {
+		let surface_data = surface.get_synced::<SurfaceData<G>>();
+		let mut surface_data_lock = surface_data.lock().unwrap();
+		if surface_data_lock.snap.map(|snap| snap.position) == Some(SnapPosition::Maximized) {
+			surface_data_lock.unsnap_window();
+			output::update_surface_outputs(surface, &mut surface_data_lock);
+		}
+	}
+
+	/// Make a window cover all of `output`, or of the output its center is on if no output is given, and raise it
+	/// above the other windows. The window's geometry is remembered so it can be restored by
+	/// [`unfullscreen_surface`](Self::unfullscreen_surface).
+	pub fn fullscreen_surface(&mut self, surface: &wl_surface::WlSurface, output: Option<&wl_output::WlOutput>) {
+		let surface_data = surface.get_synced::<SurfaceData<G>>();
+		let mut surface_data_lock = surface_data.lock().unwrap();
+		let output_area = match output {
+			Some(output) => Some(output.get_synced::<Output<G>>().lock().unwrap().viewport),
+			None => surface_data_lock
+				.try_get_window_center()
+				.and_then(|center| self.output_at(center))
+				.map(|output| output.viewport),
+		};
+		if let Some(output_area) = output_area {
+			surface_data_lock.fullscreen_window(output_area);
+			output::update_surface_outputs(surface, &mut surface_data_lock);
+			drop(surface_data_lock);
+			self.window_manager.manager_impl.raise_surface(surface);
+		}
+	}
+
+	/// Return a fullscreen window to where it was before it became fullscreen. Windows that aren't fullscreen are
+	/// left alone.
+	pub fn unfullscreen_surface(&mut self, surface: &wl_surface::WlSurface) {
+		let surface_data = surface.get_synced::<SurfaceData<G>>();
+		let mut surface_data_lock = surface_data.lock().unwrap();
+		let work_area = surface_data_lock
+			.try_get_window_center()
+			.and_then(|center| self.work_area_at(center));
+		if let Some(work_area) = work_area {
+			surface_data_lock.unfullscreen_window(work_area);
+			output::update_surface_outputs(surface, &mut surface_data_lock);
+		}
+	}
+
+	/// Get the area windows can be placed in on the output that contains `point`, falling back to the first output
+	/// if no output does. There are no panels or other reserved areas yet, so this is the whole output.
+	pub fn work_area_at(&self, point: Point) -> Option<Rect> {
+		self.output_at(point).map(|output| output.viewport)
+	}
+
+	/// Get the output the pointer is on, which is where new windows are placed. If the pointer somehow isn't on any
+	/// output, the first output is used.
+	pub fn active_output(&self) -> Option<Output<G>> {
+		let pointer_pos = self.pointer.lock().unwrap().pos;
+		self.output_at(Point::new(pointer_pos.0.floor() as i32, pointer_pos.1.floor() as i32))
+	}
+
+	/// Get the output whose viewport contains `point`, or the first output if none does
+	fn output_at(&self, point: Point) -> Option<Output<G>> {
+		let outputs = self
+			.output_globals
+			.iter()
+			.map(|(_global, output)| output.lock().unwrap().clone())
+			.collect::<Vec<_>>();
+		let index = outputs
+			.iter()
+			.position(|output| output.viewport.contains_point(point))
+			.unwrap_or(0);
+		outputs.into_iter().nth(index)
+	}
+}
+
 pub struct ClientManager {
 	pub clients: Vec<Arc<Mutex<ClientInfo>>>,
 }
@@ -196,11 +786,50 @@ impl ClientManager {
 				client,
 				keyboards: Vec::new(),
 				pointers: Vec::new(),
+				touches: Vec::new(),
 				outputs: Vec::new(),
+				data_devices: Vec::new(),
+				swipe_gestures: Vec::new(),
+				pinch_gestures: Vec::new(),
+				usage: ClientUsage::default(),
+				unresponsive: false,
 			})));
 			Arc::clone(self.clients.last().unwrap())
 		}
 	}
+
+	/// The number of surfaces that haven't been destroyed yet, across every client
+	pub fn surface_count(&self) -> usize {
+		self.clients.iter().map(|r| r.lock().unwrap().usage.surfaces).sum()
+	}
+
+	/// The number of clients that are still connected
+	pub fn client_count(&self) -> usize {
+		self.clients.iter().filter(|r| r.lock().unwrap().client.alive()).count()
+	}
+
+	/// The total number of objects tracked for all connected clients
+	pub fn object_count(&self) -> usize {
+		self.objects_by_interface().iter().map(|&(_, count)| count).sum()
+	}
+
+	/// The number of objects tracked for all connected clients, grouped by interface name
+	pub fn objects_by_interface(&self) -> Vec<(&'static str, usize)> {
+		let mut objects: Vec<(&'static str, usize)> = Vec::new();
+		for client_info in &self.clients {
+			let client_info_lock = client_info.lock().unwrap();
+			if !client_info_lock.client.alive() {
+				continue;
+			}
+			for (interface, count) in client_info_lock.objects_by_interface() {
+				match objects.iter_mut().find(|(name, _)| *name == interface) {
+					Some((_, total)) => *total += count,
+					None => objects.push((interface, count)),
+				}
+			}
+		}
+		objects
+	}
 }
 
 pub struct ClientResources {
@@ -213,13 +842,56 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 	pub fn new(
 		mut input_backend: I,
 		graphics_backend: G,
+		config: Config,
 		event_loop_handle: LoopHandle<Compositor<I, G>>,
 	) -> Result<Self, CompositorError<G>> {
+		let mut renderer = Renderer::init(graphics_backend).map_err(CompositorError::RendererInitError)?;
+		// Outputs can't be added later yet, so a compositor without any would never show anything
+		if renderer.outputs().is_empty() {
+			log::error!("The graphics backend didn't find any outputs");
+			if let Err(e) = renderer.shutdown() {
+				log::error!("Failed to shut down the renderer: {}", e);
+			}
+			return Err(CompositorError::NoOutputs);
+		}
+		for output_transform in &config.output_transforms {
+			renderer.set_output_transform(output_transform.output, output_transform.transform);
+		}
+
+		let ipc_server = match config.ipc_socket.clone() {
+			Some(ipc_socket) => match IpcServer::bind(ipc_socket.clone(), event_loop_handle.clone()) {
+				Ok(ipc_server) => Some(ipc_server),
+				Err(e) => {
+					if let Err(e) = renderer.shutdown() {
+						log::error!("Failed to shut down the renderer: {}", e);
+					}
+					return Err(CompositorError::IpcSocketError(ipc_socket, e));
+				}
+			},
+			None => None,
+		};
+
 		let mut display = Display::new();
-		//let f = fs::File::create("/run/user/1000/wayland-0").unwrap();
-		display
-			.add_socket::<&str>(None)
-			.map_err(|e| CompositorError::SocketError(e))?;
+		// Without a configured name, the first free wayland-N name is used so another compositor's socket is left
+		// alone
+		let socket_name = match config.socket_name {
+			Some(ref socket_name) => display
+				.add_socket(Some(socket_name))
+				.map(|()| OsString::from(socket_name)),
+			None => display.add_socket_auto(),
+		};
+		let socket_name = match socket_name {
+			Ok(socket_name) => socket_name,
+			Err(e) => {
+				if let Err(e) = renderer.shutdown() {
+					log::error!("Failed to shut down the renderer: {}", e);
+				}
+				return Err(CompositorError::SocketError(e));
+			}
+		};
+		log::info!("Listening on wayland socket {}", socket_name.to_string_lossy());
+		let socket_path =
+			env::var_os("XDG_RUNTIME_DIR").map(|runtime_dir| PathBuf::from(runtime_dir).join(&socket_name));
 
 		let signals = Signals::new(&[Signal::SIGINT]).expect("Failed to setup signal handler");
 		let signal_event_source = event_loop_handle
@@ -266,38 +938,28 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 			)
 			.expect("Failed to insert input event source");
 
-		let client_manager = ClientManager::new();
-
-		let pointer_state = Arc::new(Mutex::new(PointerState {
-			pos: (0.0, 0.0),
-			sensitivity: 1.0,
-			custom_cursor: None,
-		}));
-		let keyboard_state = Arc::new(Mutex::new(KeyboardState::new()));
+		let key_repeat_timer = Timer::new();
+		let key_repeat_timer_handle = key_repeat_timer.handle();
+		let key_repeat_event_source = event_loop_handle
+			.insert_source(
+				key_repeat_timer,
+				// The key being repeated is kept in the compositor, so timeouts don't carry any data
+				|_timeout, compositor: &mut Compositor<I, G>| {
+					compositor.inner.lock().unwrap().repeat_key();
+				},
+			)
+			.expect("Failed to insert key repeat timer");
 
-		let inner = CompositorInner {
-			running: true,
-			client_manager,
-			window_manager: WindowManager::new(Box::new(crate::behavior::DumbWindowManagerBehavior::new(Arc::clone(
-				&pointer_state,
-			)))),
-			//surface_tree: SurfaceTree::new(Arc::clone(&pointer_state)),
-			pointer: pointer_state,
-			pointer_focus: None,
-			keyboard_state,
-			keyboard_focus: None,
-			output_globals: Vec::new(),
-			phantom: PhantomData,
-		};
+		let inner = CompositorInner::new(config, key_repeat_timer_handle);
 
 		let input_backend_state = Arc::new(Mutex::new(InputBackendState { input_backend }));
 
-		let renderer = Renderer::init(graphics_backend).unwrap(); // TODO no unwrap
-
 		let graphics_backend_state = Arc::new(Mutex::new(GraphicsBackendState { renderer }));
 
 		Ok(Self {
 			display,
+			socket_name,
+			socket_path,
 			inner: Arc::new(Mutex::new(inner)),
 			input_backend_state,
 			graphics_backend_state,
@@ -305,11 +967,18 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 			_idle_event_source: idle_event_source,
 			_display_event_source: display_event_source,
 			_input_event_source: input_event_source,
+			_key_repeat_event_source: key_repeat_event_source,
+			ipc_server,
 		})
 	}
 
 	pub fn print_debug_info(&self) {
 		let inner = self.inner.lock().unwrap();
+		println!("Clients: {}", inner.client_manager.client_count());
+		println!("Objects: {}", inner.client_manager.object_count());
+		for (interface, count) in inner.client_manager.objects_by_interface() {
+			println!("\t{}: {}", interface, count);
+		}
 		println!("Surfaces:");
 		for (i, surface) in inner.window_manager.manager_impl.surfaces_ascending().enumerate() {
 			println!("\tSurface@{} {}", surface.as_ref().id(), i);
@@ -330,11 +999,32 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 					.unwrap_or("No client")
 			);
 		}
+		let graphics_backend_state = self.graphics_backend_state.lock().unwrap();
+		println!(
+			"Live renderer resources: {:?}",
+			graphics_backend_state.renderer.resource_counts()
+		);
+		println!(
+			"Graphics memory: {:.2} MiB",
+			graphics_backend_state.renderer.memory_usage() as f64 / (1024.0 * 1024.0)
+		);
 	}
 
 	pub fn start(&mut self, event_loop: &mut EventLoop<Compositor<I, G>>) {
+		let mut hud_interval_start = Instant::now();
+		let mut hud_interval_frames = 0u32;
+		let min_frame_time = self
+			.inner
+			.lock()
+			.unwrap()
+			.config
+			.max_fps
+			.filter(|&max_fps| max_fps > 0)
+			.map(|max_fps| Duration::from_secs(1) / max_fps);
+		let mut frame = 0u64;
 		while self.inner.lock().unwrap().running {
 			let start = Instant::now();
+			let mut timings = FrameTimings::default();
 			{
 				let mut inner = self.inner.lock().unwrap();
 				let input_update_start = Instant::now();
@@ -344,12 +1034,7 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 					.update()
 					.map_err(|_e| log::error!("Error updating the input backend"))
 					.unwrap();
-				if profile_output() {
-					log::debug!(
-						"Updated input backend in {} ms",
-						input_update_start.elapsed().as_secs_f64() * 1000.0
-					);
-				}
+				timings.record("input_update", input_update_start);
 
 				let render_update_start = Instant::now();
 				let mut graphics_backend_state = self.graphics_backend_state.lock().unwrap();
@@ -358,34 +1043,48 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 					.update()
 					.map_err(|_e| log::error!("Error updating the render backend"))
 					.unwrap();
-				if profile_output() {
-					log::debug!(
-						"Updated render backend in {} ms",
-						render_update_start.elapsed().as_secs_f64() * 1000.0
-					);
-				}
+				timings.record("render_update", render_update_start);
 				let inner = &mut *inner;
+				if !debug_output() {
+					// The HUD is only a debugging aid, so failing to update it isn't worth stopping the compositor over
+					if let Err(e) = graphics_backend_state.renderer.update_hud(None) {
+						log::error!("Failed to hide the debug HUD: {}", e);
+					}
+				} else if hud_interval_start.elapsed() >= HUD_UPDATE_INTERVAL {
+					let interval = hud_interval_start.elapsed().as_secs_f64();
+					let frames = f64::from(hud_interval_frames.max(1));
+					let stats = HudStats {
+						fps: frames / interval,
+						frame_time_ms: interval * 1000.0 / frames,
+						window_count: inner.window_manager.manager_impl.surfaces_ascending().count(),
+						focused_surface: inner.keyboard_focus.as_ref().map(|surface| surface.as_ref().id()),
+					};
+					if let Err(e) = graphics_backend_state.renderer.update_hud(Some(&stats)) {
+						log::error!("Failed to update the debug HUD: {}", e);
+					}
+					hud_interval_start = Instant::now();
+					hud_interval_frames = 0;
+				}
 				let render_tree_start = Instant::now();
 				graphics_backend_state
 					.renderer
-					.render_scene(|mut scene_render_state| {
+					.render_scene(|scene_render_state| {
 						for surface in inner.window_manager.manager_impl.surfaces_ascending() {
 							scene_render_state.draw_surface(surface.clone())?;
 						}
-						let pointer_state = inner.pointer.lock().unwrap();
-						let pointer_pos =
-							Point::new(pointer_state.pos.0.round() as i32, pointer_state.pos.1.round() as i32);
-						scene_render_state.draw_cursor(pointer_pos)?;
+						scene_render_state.draw_hud()?;
+						if !inner.cursor_hidden {
+							let pointer_state_lock = inner.pointer.lock().unwrap();
+							scene_render_state
+								.draw_cursor(pointer_state_lock.pos, pointer_state_lock.custom_cursor.as_ref())?;
+						}
 						Ok(())
 					})
 					.unwrap();
+				timings.record("render", render_tree_start);
+				let present_start = Instant::now();
 				graphics_backend_state.renderer.present().unwrap();
-				if profile_output() {
-					log::debug!(
-						"Rendered surface tree in {} ms",
-						render_tree_start.elapsed().as_secs_f64() * 1000.0
-					);
-				}
+				timings.record("present", present_start);
 			}
 			// TODO change timeout to something that syncs with rendering somehow. The timeout should be the time until
 			// the next frame should start rendering.
@@ -396,25 +1095,55 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 					log::error!("An error occurred in the event loop: {}", e);
 				}
 			}
-			if profile_output() {
-				log::debug!(
-					"Dispatched events in {} ms",
-					dispatch_start.elapsed().as_secs_f64() * 1000.0
-				);
+			timings.record("dispatch", dispatch_start);
+			if let Some(ref mut ipc_server) = self.ipc_server {
+				ipc_server.remove_closed_connections();
 			}
+			self.inner.lock().unwrap().ping_clients();
+			self.remove_disconnected_outputs();
 			let flush_start = Instant::now();
 			self.display.flush_clients(&mut ());
-			if profile_output() {
-				log::debug!("Flushed clients in {} ms", flush_start.elapsed().as_secs_f64() * 1000.0);
-			}
+			timings.record("flush", flush_start);
 			if debug_output() {
 				self.print_debug_info();
 			}
+			hud_interval_frames += 1;
 			let end = start.elapsed();
 			if profile_output() {
-				log::debug!("Ran frame in {} ms", end.as_secs_f64() * 1000.0);
+				log::debug!("frame={} {} total_ms={:.3}", frame, timings, end.as_secs_f64() * 1000.0);
+			}
+			frame += 1;
+			// Wait out the rest of the frame if it finished early, so the frame rate doesn't go over the limit
+			if let Some(min_frame_time) = min_frame_time {
+				if let Some(remaining) = min_frame_time.checked_sub(end) {
+					thread::sleep(remaining);
+				}
 			}
 		}
+		self.shutdown();
+	}
+
+	/// Disconnect every client and release all graphics resources, in that order, since destroying a client's
+	/// surfaces destroys their textures
+	fn shutdown(&mut self) {
+		log::info!("Shutting down");
+		let clients = self
+			.inner
+			.lock()
+			.unwrap()
+			.client_manager
+			.clients
+			.iter()
+			.map(|client_info| client_info.lock().unwrap().client.clone())
+			.collect::<Vec<_>>();
+		for client in clients {
+			client.kill();
+		}
+		self.display.flush_clients(&mut ());
+		let mut graphics_backend_state = self.graphics_backend_state.lock().unwrap();
+		if let Err(e) = graphics_backend_state.renderer.shutdown() {
+			log::error!("Failed to shut down the renderer: {}", e);
+		}
 	}
 
 	pub fn handle_input_event(&mut self, event: BackendEvent) {
@@ -423,12 +1152,76 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 			BackendEvent::StopRequested => {
 				inner.running = false;
 			}
+			BackendEvent::ModifiersChanged(modifiers) => {
+				let inner = &mut *inner;
+				let mut keyboard_state_lock = inner.keyboard_state.lock().unwrap();
+				if keyboard_state_lock.update_modifiers(modifiers) {
+					if let Some(focused) = inner.keyboard_focus.clone() {
+						let surface_data = focused.get_synced::<SurfaceData<G>>();
+						let surface_data_lock = surface_data.lock().unwrap();
+						let client_info_lock = surface_data_lock.client_info.lock().unwrap();
+						let mods = keyboard_state_lock.xkb_modifiers_state;
+						for keyboard in &client_info_lock.keyboards {
+							keyboard.modifiers(
+								get_input_serial(),
+								mods.mods_depressed,
+								mods.mods_latched,
+								mods.mods_locked,
+								mods.group,
+							);
+						}
+					}
+				}
+			}
+			BackendEvent::OutputResized(output_resized) => {
+				drop(inner);
+				self.handle_output_resized(output_resized);
+			}
 			BackendEvent::KeyPress(key_press) => {
 				let inner = &mut *inner;
+				if key_press.state == PressState::Press {
+					inner.key_serial = Some(key_press.serial);
+				}
+				if inner.config.hide_cursor_while_typing && key_press.state == PressState::Press {
+					inner.cursor_hidden = true;
+				}
 
 				// Update the internal xkb keyboard state tracker.
 				let mut keyboard_state_lock = inner.keyboard_state.lock().unwrap();
 				let state_change = keyboard_state_lock.update_key(key_press.clone());
+				if state_change {
+					// Lock keys only change the LEDs by changing the locked modifiers
+					let leds = keyboard_state_lock.leds();
+					self.input_backend_state
+						.lock()
+						.unwrap()
+						.input_backend
+						.set_keyboard_leds(leds);
+				}
+
+				// Keys that trigger a keybinding are handled by the compositor and never reach clients
+				let grabbed = match key_press.state {
+					PressState::Press => {
+						let keybinding = Keybinding::new(
+							keyboard_state_lock.active_modifiers(),
+							keyboard_state_lock.keysym(key_press.key),
+						);
+						drop(keyboard_state_lock);
+						let ran = inner.run_keybinding(keybinding);
+						if ran {
+							inner.grabbed_keys.push(key_press.key);
+						}
+						keyboard_state_lock = inner.keyboard_state.lock().unwrap();
+						ran
+					}
+					PressState::Release => match inner.grabbed_keys.iter().position(|&key| key == key_press.key) {
+						Some(i) => {
+							inner.grabbed_keys.remove(i);
+							true
+						}
+						None => false,
+					},
+				};
 
 				// Send the key event to the surface that currently has keyboard focus, and an updated modifiers event if modifiers changed.
 				if let Some(focused) = inner.keyboard_focus.clone() {
@@ -446,11 +1239,20 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 								mods.group,
 							);
 						}
-						keyboard.key(key_press.serial, key_press.time, key_press.key, key_press.state.into());
+						if !grabbed {
+							keyboard.key(key_press.serial, key_press.time, key_press.key, key_press.state.into());
+						}
 					}
 				}
+				drop(keyboard_state_lock);
+				match key_press.state {
+					PressState::Press if !grabbed => inner.start_key_repeat(key_press.key),
+					PressState::Press => {}
+					PressState::Release => inner.release_repeated_key(key_press.key),
+				}
 			}
 			BackendEvent::PointerMotion(pointer_motion) => {
+				inner.cursor_hidden = false;
 				let mut pointer_state_lock = inner.pointer.lock().unwrap();
 				pointer_state_lock.pos.0 += pointer_motion.dx_unaccelerated * pointer_state_lock.sensitivity;
 				pointer_state_lock.pos.1 += pointer_motion.dy_unaccelerated * pointer_state_lock.sensitivity;
@@ -459,7 +1261,10 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 				drop(pointer_state_lock);
 				let pointer_pos = Point::new(pointer_pos.0.round() as i32, pointer_pos.1.round() as i32);
 
-				if let Some(surface) = inner.window_manager.get_window_under_point(pointer_pos) {
+				if let Some((surface, offset)) = inner.moving_surface.clone() {
+					// The pointer is grabbed by the move, so it stays focused on the window being moved
+					inner.move_window(&surface, Point::new(pointer_pos.x + offset.x, pointer_pos.y + offset.y));
+				} else if let Some(surface) = inner.window_manager.get_window_under_point(pointer_pos) {
 					let surface_data = surface.get_synced::<SurfaceData<G>>();
 					let surface_data_lock = surface_data.lock().unwrap();
 					let surface_relative_coords =
@@ -480,16 +1285,20 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 							let old_client_info_lock = old_surface_data_lock.client_info.lock().unwrap();
 							for pointer in &old_client_info_lock.pointers {
 								pointer.leave(get_input_serial(), &old_pointer_focus);
+								send_pointer_frame(pointer);
 							}
 							for keyboard in &old_client_info_lock.keyboards {
 								keyboard.leave(get_input_serial(), &old_pointer_focus);
 							}
 							drop(old_client_info_lock);
 							drop(old_surface_data_lock);
+							inner.reset_cursor();
+							let enter_serial = get_input_serial();
+							inner.pointer_enter_serial = Some(enter_serial);
 							let surface_client_info_lock = surface_data_lock.client_info.lock().unwrap();
 							for pointer in &surface_client_info_lock.pointers {
 								pointer.enter(
-									get_input_serial(),
+									enter_serial,
 									&surface,
 									surface_relative_coords.x as f64,
 									surface_relative_coords.y as f64,
@@ -506,10 +1315,12 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 						}
 					} else {
 						// The pointer has entered a surface while no other surface is focused, focus this surface
+						let enter_serial = get_input_serial();
+						inner.pointer_enter_serial = Some(enter_serial);
 						let surface_client_info_lock = surface_data_lock.client_info.lock().unwrap();
 						for pointer in &surface_client_info_lock.pointers {
 							pointer.enter(
-								get_input_serial(),
+								enter_serial,
 								&surface,
 								surface_relative_coords.x as f64,
 								surface_relative_coords.y as f64,
@@ -525,14 +1336,15 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 						inner.pointer_focus = Some(surface.clone());
 					}
 
-					// Send the surface the actual motion event
+					// Send the surface the actual motion event, in the same frame as the enter event if there was one
 					let client_info_lock = surface_data_lock.client_info.lock().unwrap();
 					for pointer in &client_info_lock.pointers {
 						pointer.motion(
-							get_input_serial(),
+							pointer_motion.time,
 							surface_relative_coords.x as f64,
 							surface_relative_coords.y as f64,
 						);
+						send_pointer_frame(pointer);
 					}
 				} else {
 					// The pointer is not over any surface, remove pointer focus from the previous focused surface if any
@@ -542,10 +1354,14 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 						let client_info_lock = surface_data_lock.client_info.lock().unwrap();
 						for pointer in &client_info_lock.pointers {
 							pointer.leave(get_input_serial(), &old_pointer_focus);
+							send_pointer_frame(pointer);
 						}
 						for keyboard in &client_info_lock.keyboards {
 							keyboard.leave(get_input_serial(), &old_pointer_focus);
 						}
+						drop(client_info_lock);
+						drop(surface_data_lock);
+						inner.reset_cursor();
 					}
 				}
 			}
@@ -554,41 +1370,17 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 				let pointer_pos = pointer_state.pos;
 				drop(pointer_state);
 				let pointer_pos = Point::new(pointer_pos.0.round() as i32, pointer_pos.1.round() as i32);
+				let double_click = pointer_button.state == PressState::Press
+					&& inner.register_button_press(pointer_button.button, pointer_button.time);
+				if pointer_button.state == PressState::Press {
+					inner.pointer_button_serial = Some(pointer_button.serial);
+				} else {
+					inner.moving_surface = None;
+				}
 
 				if let Some(surface) = inner.window_manager.get_window_under_point(pointer_pos) {
-					let surface_data = surface.get_synced::<SurfaceData<G>>();
-					let surface_data_lock = surface_data.lock().unwrap();
-
 					if pointer_button.state == PressState::Press {
-						if let Some(old_keyboard_focus) = inner.keyboard_focus.clone() {
-							if surface.as_ref().equals(old_keyboard_focus.as_ref()) {
-								// No focus change, this is the same surface
-							} else {
-								// Change the keyboard focus
-								let old_surface_data = old_keyboard_focus.get_synced::<SurfaceData<G>>();
-								let old_surface_data_lock = old_surface_data.lock().unwrap();
-								let old_client_info_lock = old_surface_data_lock.client_info.lock().unwrap();
-								for keyboard in &old_client_info_lock.keyboards {
-									keyboard.leave(get_input_serial(), &old_keyboard_focus);
-								}
-								drop(old_client_info_lock);
-								drop(old_surface_data_lock);
-								let new_client_info_lock = surface_data_lock.client_info.lock().unwrap();
-								for keyboard in &new_client_info_lock.keyboards {
-									keyboard.modifiers(get_input_serial(), 0, 0, 0, 0);
-									keyboard.enter(get_input_serial(), &surface, Vec::new());
-								}
-								inner.keyboard_focus = Some(surface.clone());
-							}
-						} else {
-							// Focus the keyboard on a window when there was no previously focused window
-							let new_client_info_lock = surface_data_lock.client_info.lock().unwrap();
-							for keyboard in &new_client_info_lock.keyboards {
-								keyboard.modifiers(get_input_serial(), 0, 0, 0, 0);
-								keyboard.enter(get_input_serial(), &surface, Vec::new());
-							}
-							inner.keyboard_focus = Some(surface.clone());
-						}
+						inner.focus_surface(&surface);
 					}
 				} else {
 					// Remove the keyboard focus from the current focus if empty space is clicked
@@ -602,6 +1394,22 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 					}
 				}
 
+				// Windows don't have server side decorations, so a double click while holding logo stands in for a
+				// double click on a title bar
+				if double_click
+					&& pointer_button.button == Button::Left
+					&& inner
+						.keyboard_state
+						.lock()
+						.unwrap()
+						.active_modifiers()
+						.contains(KeyModifiers::LOGO)
+				{
+					if let Some(surface) = inner.window_manager.get_window_under_point(pointer_pos) {
+						inner.snap_surface(&surface, SnapPosition::Maximized);
+					}
+				}
+
 				// Send event to focused window
 				if let Some(focused) = inner.keyboard_focus.clone() {
 					let surface_data = focused.get_synced::<SurfaceData<G>>();
@@ -614,22 +1422,150 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 							pointer_button.button.to_wl(),
 							pointer_button.state.into(),
 						);
+						send_pointer_frame(pointer);
 					}
 				}
 			}
+			BackendEvent::PointerAxis(pointer_axis) => {
+				if let Some(focused) = inner.pointer_focus.clone() {
+					let surface_data = focused.get_synced::<SurfaceData<G>>();
+					let surface_data_lock = surface_data.lock().unwrap();
+					let client_info_lock = surface_data_lock.client_info.lock().unwrap();
+					let axes = [
+						(
+							wl_pointer::Axis::HorizontalScroll,
+							pointer_axis.horizontal,
+							pointer_axis.horizontal_discrete,
+						),
+						(
+							wl_pointer::Axis::VerticalScroll,
+							pointer_axis.vertical,
+							pointer_axis.vertical_discrete,
+						),
+					];
+					for pointer in &client_info_lock.pointers {
+						// The axis source and discrete steps were added in version 5 of wl_pointer
+						let version_5 = pointer.as_ref().version() >= 5;
+						if version_5 {
+							pointer.axis_source(pointer_axis.source.into());
+						}
+						for &(axis, value, discrete) in &axes {
+							if value == 0.0 {
+								continue;
+							}
+							if let (true, Some(discrete)) = (version_5, discrete) {
+								pointer.axis_discrete(axis, discrete);
+							}
+							pointer.axis(pointer_axis.time, axis, value);
+						}
+						send_pointer_frame(pointer);
+					}
+				}
+			}
+			BackendEvent::GestureSwipe(gesture_swipe) => inner.handle_gesture_swipe(gesture_swipe),
+			BackendEvent::GesturePinch(gesture_pinch) => inner.handle_gesture_pinch(gesture_pinch),
+			BackendEvent::Touch(touch) => {
+				let touch_down = matches!(touch, Touch::Down { .. });
+				drop(inner);
+				self.handle_touch(touch);
+				// This comes after the touch is handled, since moving the emulated pointer shows the cursor again
+				let mut inner = self.inner.lock().unwrap();
+				if touch_down && inner.config.hide_cursor_while_typing {
+					inner.cursor_hidden = true;
+				}
+			}
 		}
 	}
 
+	/// Emulate the pointer with the first touch point, if pointer emulation is enabled. The pointer jumps to the
+	/// touch point and holds down its left button until the touch point is lifted. Any other touch points are
+	/// ignored, since a pointer can't be in two places at once. Touch events are sent to clients as they are if
+	/// pointer emulation is disabled, or if the touch point went down on a client that bound wl_touch.
+	fn handle_touch(&mut self, touch: Touch) {
+		let mut inner = self.inner.lock().unwrap();
+		if !inner.config.emulate_pointer_with_touch || !inner.should_emulate_touch(&touch) {
+			inner.handle_touch(touch);
+			return;
+		}
+		let (serial, time, pressed) = match touch {
+			Touch::Down {
+				serial,
+				time,
+				slot,
+				x,
+				y,
+			} => {
+				if inner.emulated_touch_slot.is_some() {
+					return;
+				}
+				inner.emulated_touch_slot = Some(slot);
+				drop(inner);
+				self.warp_pointer_to_touch(time, x, y);
+				(serial, time, PressState::Press)
+			}
+			Touch::Motion { time, slot, x, y } => {
+				if inner.emulated_touch_slot == Some(slot) {
+					drop(inner);
+					self.warp_pointer_to_touch(time, x, y);
+				}
+				return;
+			}
+			Touch::Up { serial, time, slot } | Touch::Cancel { serial, time, slot } => {
+				if inner.emulated_touch_slot != Some(slot) {
+					return;
+				}
+				inner.emulated_touch_slot = None;
+				drop(inner);
+				(serial, time, PressState::Release)
+			}
+			Touch::Frame { .. } => return,
+		};
+		self.handle_input_event(BackendEvent::PointerButton(PointerButton {
+			serial,
+			time,
+			button: Button::Left,
+			state: pressed,
+		}));
+	}
+
+	/// Move the pointer to a touch position. Touchscreens aren't mapped to outputs yet, so the position is taken
+	/// to be on the first output.
+	fn warp_pointer_to_touch(&mut self, time: u32, x: f64, y: f64) {
+		let inner = self.inner.lock().unwrap();
+		let position = match inner.touch_position(x, y) {
+			Some(position) => position,
+			None => return,
+		};
+		inner.pointer.lock().unwrap().pos = position;
+		drop(inner);
+		// A motion that doesn't move the pointer any further sends the focus and motion events for its new position
+		self.handle_input_event(BackendEvent::PointerMotion(PointerMotion {
+			serial: get_input_serial(),
+			time,
+			dx: 0.0,
+			dx_unaccelerated: 0.0,
+			dy: 0.0,
+			dy_unaccelerated: 0.0,
+		}));
+	}
+
 	pub fn init(&mut self) {
 		self.setup_globals();
 	}
 
+	/// Get the name of the wayland socket the compositor is listening on, which clients should be given in
+	/// WAYLAND_DISPLAY to connect to it
+	pub fn socket_name(&self) -> &OsStr {
+		&self.socket_name
+	}
+
 	pub(crate) fn setup_globals(&mut self) {
 		self.setup_compositor_global();
 		self.setup_shm_global();
 		self.setup_output_global();
 		self.setup_seat_global();
 		self.setup_data_device_manager_global();
+		self.setup_pointer_gestures_global();
 		self.setup_wl_shell_global();
 		self.setup_xdg_wm_base_global();
 	}
@@ -641,18 +1577,32 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 			move |(main, _num): (Main<wl_compositor::WlCompositor>, u32), _filter, _dispatch_data| {
 				let inner = Arc::clone(&inner);
 				let graphics_backend_state = Arc::clone(&graphics_backend_state);
-				main.quick_assign(move |_main, request, _dispatch_data| {
+				main.quick_assign(move |main, request, _dispatch_data| {
 					let inner = Arc::clone(&inner);
 					let graphics_backend_state = Arc::clone(&graphics_backend_state);
 					match request {
 						wl_compositor::Request::CreateRegion { id } => {
+							let region_data = Arc::new(Mutex::new(RegionData::new()));
+							let region_data_clone = Arc::clone(&region_data);
+							id.as_ref().user_data().set_threadsafe(move || region_data_clone);
 							id.quick_assign(move |_main, request, _| {
 								match request {
 									wl_region::Request::Destroy => {
-										// TODO handle in destructor
+										// Surfaces keep copies of the regions set on them, so nothing has to be
+										// cleaned up
+									}
+									wl_region::Request::Add { x, y, width, height } => {
+										if width > 0 && height > 0 {
+											let rect = Rect::new(x, y, width as u32, height as u32);
+											region_data.lock().unwrap().add(rect);
+										}
+									}
+									wl_region::Request::Subtract { x, y, width, height } => {
+										if width > 0 && height > 0 {
+											let rect = Rect::new(x, y, width as u32, height as u32);
+											region_data.lock().unwrap().subtract(rect);
+										}
 									}
-									wl_region::Request::Add { .. } => {}
-									wl_region::Request::Subtract { .. } => {}
 									_ => log::warn!("Unknown request for wl_region"),
 								}
 							});
@@ -663,11 +1613,20 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 							let inner_destructor = Arc::clone(&inner);
 							let surface = id.clone();
 							let surface_resource = surface.as_ref();
-							let client_info = inner
-								.lock()
-								.unwrap()
+							let mut inner_lock = inner.lock().unwrap();
+							let max_surfaces = inner_lock.config.client_limits.max_surfaces;
+							let client_info = inner_lock
 								.client_manager
 								.get_client_info(surface_resource.client().unwrap());
+							drop(inner_lock);
+							{
+								let mut client_info_lock = client_info.lock().unwrap();
+								if client_info_lock.usage.surfaces >= max_surfaces {
+									client_info_lock.disconnect_over_limit(main.as_ref(), "surfaces");
+									return;
+								}
+								client_info_lock.usage.surfaces += 1;
+							}
 							let mut graphics_backend_state_lock = graphics_backend_state.lock().unwrap();
 							let surface_renderer_data = graphics_backend_state_lock
 								.renderer
@@ -688,23 +1647,34 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 									}
 									wl_surface::Request::Attach { buffer, x, y } => {
 										let mut surface_data_lock = surface_data.lock().unwrap();
-										// Release the previously attached buffer if it hasn't been committed yet
-										if let Some(old_buffer) = surface_data_lock.pending_state.attached_buffer.take()
-										{
-											if let Some(old_buffer) = old_buffer {
-												old_buffer.0.release()
-											}
-										};
-										// Attach the new buffer to the surface
-										if let Some(buffer) = buffer {
-											surface_data_lock.pending_state.attached_buffer =
-												Some(Some((buffer, Point::new(x, y))));
+										// Attach the new buffer to the surface. It's held before the previously
+										// attached buffer is released, in case it's the same buffer.
+										let old_buffer = if let Some(buffer) = buffer {
+											buffer::hold_buffer::<G::ShmBuffer>(&buffer);
+											surface_data_lock
+												.pending_state
+												.attached_buffer
+												.replace(Some((buffer, Point::new(x, y))))
 										} else {
 											// Attaching a null buffer to a surface is equivalent to unmapping it.
-											surface_data_lock.pending_state.attached_buffer = Some(None);
+											surface_data_lock.pending_state.attached_buffer.replace(None)
+										};
+										// Release the previously attached buffer if it hasn't been committed yet
+										if let Some((old_buffer, _)) = old_buffer.and_then(|old_buffer| old_buffer) {
+											buffer::release_buffer::<G::ShmBuffer>(&old_buffer);
+										}
+									}
+									wl_surface::Request::Damage { x, y, width, height } => {
+										if width > 0 && height > 0 {
+											let mut surface_data_lock = surface_data.lock().unwrap();
+											surface_data_lock.pending_state.surface_damage.push(Rect::new(
+												x,
+												y,
+												width as u32,
+												height as u32,
+											));
 										}
 									}
-									wl_surface::Request::Damage { .. } => {}
 									wl_surface::Request::Frame { callback } => {
 										let mut surface_data_lock = surface_data.lock().unwrap();
 										if let Some(_old_callback) =
@@ -713,18 +1683,41 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 											log::warn!("Replacing surface callback with a newly requested one, unclear if this is intended behavior");
 										}
 									}
-									wl_surface::Request::SetOpaqueRegion { .. } => {}
-									wl_surface::Request::SetInputRegion { .. } => {}
+									wl_surface::Request::SetOpaqueRegion { region } => {
+										// The region is copied, so the client can change or destroy it afterwards
+										let region = region.map(|region| region.get_synced::<RegionData>());
+										let region = region.map(|region| region.lock().unwrap().clone());
+										surface_data.lock().unwrap().pending_state.opaque_region = Some(region);
+									}
+									wl_surface::Request::SetInputRegion { region } => {
+										let region = region.map(|region| region.get_synced::<RegionData>());
+										let region = region.map(|region| region.lock().unwrap().clone());
+										surface_data.lock().unwrap().pending_state.input_region = Some(region);
+									}
 									wl_surface::Request::Commit => {
 										// TODO: relying on the impl of ShmBuffer to ascertain the size of the buffer is probably unsound if the ShmBuffer impl lies.
 										// So that trait should either be unsafe, or Shm should be moved out of the Rendering backend and EasyShm should be made canonical
 										let mut surface_data_lock = surface_data.lock().unwrap();
+										if !surface_data_lock.validate_commit() {
+											return;
+										}
+										let old_buffer_size = surface_data_lock.buffer_size;
 										surface_data_lock.commit_pending_state();
+										// The new buffer can change which outputs the surface is on
+										output::update_surface_outputs(&*surface, &mut surface_data_lock);
+										let new_buffer_size = surface_data_lock.buffer_size;
+										// The committed buffer is taken when it's drawn, but the buffer size stays
+										// until a null buffer is committed
+										let was_mapped = old_buffer_size.is_some();
+										let is_mapped = new_buffer_size.is_some();
 										if let Some(ref committed_buffer) = surface_data_lock.committed_buffer {
-											let buffer_data = committed_buffer.0.get_synced::<G::ShmBuffer>();
+											let buffer_data =
+												committed_buffer.0.get_synced::<BufferData<G::ShmBuffer>>();
 											let buffer_data_lock = buffer_data.lock().unwrap();
-											let new_size =
-												Size::new(buffer_data_lock.width(), buffer_data_lock.height());
+											let new_size = Size::new(
+												buffer_data_lock.shm_buffer.width(),
+												buffer_data_lock.shm_buffer.height(),
+											);
 											drop(buffer_data_lock);
 											drop(surface_data_lock);
 											let mut inner_lock = inner.lock().unwrap();
@@ -732,11 +1725,53 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 												.window_manager
 												.manager_impl
 												.handle_surface_resize((*surface).clone(), new_size);
+										} else {
+											drop(surface_data_lock);
+										}
+
+										let surface_event = match (was_mapped, is_mapped) {
+											(false, true) => Some(SurfaceEvent::Mapped((*surface).clone())),
+											(true, false) => Some(SurfaceEvent::Unmapped((*surface).clone())),
+											(true, true) if old_buffer_size != new_buffer_size => new_buffer_size
+												.map(|size| SurfaceEvent::Resized((*surface).clone(), size)),
+											_ => None,
+										};
+										if let Some(surface_event) = surface_event {
+											let mut inner_lock = inner.lock().unwrap();
+											match surface_event {
+												SurfaceEvent::Mapped(ref surface) => inner_lock.map_window(surface),
+												SurfaceEvent::Unmapped(ref surface) => inner_lock.unmap_window(surface),
+												_ => {}
+											}
+											inner_lock.emit_surface_event(surface_event);
+										}
+									}
+									wl_surface::Request::SetBufferTransform { transform } => {
+										let mut surface_data_lock = surface_data.lock().unwrap();
+										surface_data_lock.pending_state.buffer_transform = Some(transform);
+									}
+									wl_surface::Request::SetBufferScale { scale } => {
+										if scale < 1 {
+											surface.as_ref().post_error(
+												wl_surface::Error::InvalidScale as u32,
+												format!("Buffer scale {} is not positive", scale),
+											);
+											return;
+										}
+										let mut surface_data_lock = surface_data.lock().unwrap();
+										surface_data_lock.pending_state.buffer_scale = Some(scale);
+									}
+									wl_surface::Request::DamageBuffer { x, y, width, height } => {
+										if width > 0 && height > 0 {
+											let mut surface_data_lock = surface_data.lock().unwrap();
+											surface_data_lock.pending_state.buffer_damage.push(Rect::new(
+												x,
+												y,
+												width as u32,
+												height as u32,
+											));
 										}
 									}
-									wl_surface::Request::SetBufferTransform { .. } => {}
-									wl_surface::Request::SetBufferScale { .. } => {}
-									wl_surface::Request::DamageBuffer { .. } => {}
 									_ => {
 										log::warn!("Got unknown request for wl_surface");
 									}
@@ -747,15 +1782,30 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 									log::trace!("Destroying wl_surface");
 									let mut graphics_backend_state_lock = graphics_backend_destructor.lock().unwrap();
 									let surface_data = surface.get_synced::<SurfaceData<G>>();
-									graphics_backend_state_lock
-										.renderer
-										.destroy_surface_renderer_data(
-											surface_data.lock().unwrap().renderer_data.take().unwrap(),
-										)
-										.map_err(|e| log::error!("Failed to destroy surface: {}", e))
-										.unwrap();
+									let mut surface_data_lock = surface_data.lock().unwrap();
+									if let Some(renderer_data) = surface_data_lock.renderer_data.take() {
+										graphics_backend_state_lock
+											.renderer
+											.destroy_surface_renderer_data(renderer_data)
+											.map_err(|e| log::error!("Failed to destroy surface: {}", e))
+											.unwrap();
+									}
+									surface_data_lock.client_info.lock().unwrap().usage.surfaces -= 1;
+									drop(surface_data_lock);
 									let mut inner = inner_destructor.lock().unwrap();
+									// Clients should destroy the role first, but a surface destroyed along with its
+									// client may not have, so make sure it isn't left in the window manager
+									inner.unmap_window(&surface);
 									inner.trim_dead_clients();
+									// Once every surface is gone, like after the last client disconnects, only the
+									// renderer's own resources should be left
+									if inner.client_manager.surface_count() == 0 {
+										let leaked = graphics_backend_state_lock.renderer.surface_resource_counts();
+										if leaked != ResourceCounts::default() {
+											log::warn!("Renderer resources leaked by destroyed surfaces: {:?}", leaked);
+										}
+									}
+									inner.emit_surface_event(SurfaceEvent::Destroyed(surface));
 								},
 							));
 						}
@@ -769,30 +1819,20 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 		self.display
 			.create_global::<wl_compositor::WlCompositor, _>(4, compositor_filter);
 	}
-
-	fn setup_data_device_manager_global(&mut self) {
-		let data_device_manager_filter = Filter::new(
-			|(main, _num): (Main<wl_data_device_manager::WlDataDeviceManager>, u32), _filter, _dispatch_data| {
-				main.quick_assign(
-					|_main, request: wl_data_device_manager::Request, _dispatch_data| match request {
-						wl_data_device_manager::Request::CreateDataSource { id: _ } => {}
-						wl_data_device_manager::Request::GetDataDevice { id: _, seat: _ } => {}
-						_ => {
-							log::warn!("Got unknown request for wl_data_device_manager");
-						}
-					},
-				)
-			},
-		);
-		self.display
-			.create_global::<wl_data_device_manager::WlDataDeviceManager, _>(3, data_device_manager_filter);
-	}
 }
 
 impl<I: InputBackend, G: GraphicsBackend> Drop for Compositor<I, G> {
 	fn drop(&mut self) {
-		log::info!("Closing wayland socket");
-		fs::remove_file("/run/user/1000/wayland-0").unwrap();
+		if let Some(ref socket_path) = self.socket_path {
+			log::info!("Closing wayland socket");
+			if let Err(e) = fs::remove_file(socket_path) {
+				log::error!(
+					"Failed to remove the wayland socket at {}: {}",
+					socket_path.display(),
+					e
+				);
+			}
+		}
 	}
 }
 
@@ -802,4 +1842,34 @@ pub enum CompositorError<G: GraphicsBackend + 'static> {
 	SocketError(#[source] io::Error),
 	#[error("Failed to create a render target")]
 	RenderTargetError(#[source] G::Error),
+	#[error("Failed to initialize the renderer")]
+	RendererInitError(#[source] G::Error),
+	#[error("No outputs were found, check that a monitor is connected and turned on")]
+	NoOutputs,
+	#[error("Failed to create the control socket at {0}")]
+	IpcSocketError(PathBuf, #[source] io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_util::test_inner;
+
+	#[test]
+	fn active_output_follows_the_pointer() {
+		let mut display = Display::new();
+		let inner = test_inner(&mut display);
+		let first_output = Rect::new(0, 0, 100, 100);
+		let second_output = Rect::new(100, 0, 100, 100);
+		assert_eq!(inner.active_output().map(|output| output.viewport), Some(first_output));
+
+		inner.pointer.lock().unwrap().pos = (150.5, 20.0);
+		assert_eq!(inner.active_output().map(|output| output.viewport), Some(second_output));
+		assert_eq!(inner.work_area_at(Point::new(199, 99)), Some(second_output));
+		assert_eq!(inner.work_area_at(Point::new(99, 99)), Some(first_output));
+
+		// A pointer that somehow isn't on any output falls back to the first one
+		inner.pointer.lock().unwrap().pos = (-10.0, 500.0);
+		assert_eq!(inner.active_output().map(|output| output.viewport), Some(first_output));
+	}
 }
@@ -0,0 +1,110 @@
+//! A client can attach the same buffer to any number of surfaces at once. Every surface it's attached to holds on to
+//! it until the buffer is replaced and the frames that read it have finished, so a buffer is only released once none
+//! of them are using it anymore.
+
+use crate::compositor::prelude::*;
+
+/// The data of a wl_buffer, where `B` is the [`ShmBuffer`](GraphicsBackend::ShmBuffer) type of the graphics backend
+pub struct BufferData<B> {
+	pub shm_buffer: B,
+	/// The number of attachments and frames that are still using the buffer
+	holds: usize,
+}
+
+impl<B> BufferData<B> {
+	pub fn new(shm_buffer: B) -> Self {
+		Self { shm_buffer, holds: 0 }
+	}
+}
+
+/// Start another use of a buffer, which has to be ended with [`release_buffer`] once the buffer isn't needed for it
+/// anymore
+pub fn hold_buffer<B: 'static>(buffer: &wl_buffer::WlBuffer) {
+	buffer.get_synced::<BufferData<B>>().lock().unwrap().holds += 1;
+}
+
+/// End a use of a buffer, sending the client a release event if that was the last one
+pub fn release_buffer<B: 'static>(buffer: &wl_buffer::WlBuffer) {
+	let buffer_data = buffer.get_synced::<BufferData<B>>();
+	let mut buffer_data_lock = buffer_data.lock().unwrap();
+	match buffer_data_lock.holds {
+		0 => log::warn!(
+			"Tried to release wl_buffer@{} more times than it was held",
+			buffer.as_ref().id()
+		),
+		1 => {
+			buffer_data_lock.holds = 0;
+			buffer.release();
+		}
+		_ => buffer_data_lock.holds -= 1,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::os::unix::net::UnixStream;
+
+	use super::*;
+	use crate::test_util::{
+		received_events, test_buffer, test_client, test_client_info, test_surface, TestBackend, TestShmBuffer,
+	};
+
+	fn holds(buffer: &wl_buffer::WlBuffer) -> usize {
+		buffer.get_synced::<BufferData<()>>().lock().unwrap().holds
+	}
+
+	#[test]
+	fn buffer_holds_are_counted() {
+		let mut display = Display::new();
+		let (client, _socket) = test_client(&mut display);
+		let buffer = (*client.create_resource::<wl_buffer::WlBuffer>(1).unwrap()).clone();
+		let buffer_data = Arc::new(Mutex::new(BufferData::new(())));
+		buffer.as_ref().user_data().set_threadsafe(|| buffer_data);
+		assert_eq!(holds(&buffer), 0);
+		hold_buffer::<()>(&buffer);
+		hold_buffer::<()>(&buffer);
+		assert_eq!(holds(&buffer), 2);
+		release_buffer::<()>(&buffer);
+		assert_eq!(holds(&buffer), 1);
+		release_buffer::<()>(&buffer);
+		assert_eq!(holds(&buffer), 0);
+		// Releasing a buffer that isn't held is ignored instead of underflowing
+		release_buffer::<()>(&buffer);
+		assert_eq!(holds(&buffer), 0);
+		hold_buffer::<()>(&buffer);
+		assert_eq!(holds(&buffer), 1);
+	}
+
+	/// Attach and commit a buffer to a surface, like a client's wl_surface.attach and wl_surface.commit would
+	fn attach_and_commit(surface_data: &Synced<SurfaceData<TestBackend>>, buffer: &wl_buffer::WlBuffer) {
+		let mut surface_data_lock = surface_data.lock().unwrap();
+		hold_buffer::<TestShmBuffer>(buffer);
+		surface_data_lock.pending_state.attached_buffer = Some(Some((buffer.clone(), Point::new(0, 0))));
+		surface_data_lock.commit_pending_state();
+	}
+
+	fn was_released(display: &mut Display, socket: &mut UnixStream, buffer: &wl_buffer::WlBuffer) -> bool {
+		display.flush_clients(&mut ());
+		received_events(socket)
+			.iter()
+			.any(|(object, opcode, _)| *object == buffer.as_ref().id() && *opcode == 0)
+	}
+
+	#[test]
+	fn shared_buffer_is_released_once_every_surface_replaced_it() {
+		let mut display = Display::new();
+		let (client, mut socket) = test_client(&mut display);
+		let client_info = test_client_info(&client);
+		let (_a, a_data) = test_surface::<TestBackend>(&client, &client_info);
+		let (_b, b_data) = test_surface::<TestBackend>(&client, &client_info);
+		let shared = test_buffer(&client, 10, 10);
+		attach_and_commit(&a_data, &shared);
+		attach_and_commit(&b_data, &shared);
+		assert!(!was_released(&mut display, &mut socket, &shared));
+
+		attach_and_commit(&a_data, &test_buffer(&client, 10, 10));
+		assert!(!was_released(&mut display, &mut socket, &shared));
+		attach_and_commit(&b_data, &test_buffer(&client, 10, 10));
+		assert!(was_released(&mut display, &mut socket, &shared));
+	}
+}
@@ -0,0 +1,109 @@
+use std::path::PathBuf;
+
+use calloop::LoopHandle;
+
+use crate::{
+	behavior::{WindowManager, WindowManagerBehavior},
+	compositor::{
+		prelude::*, Compositor, CompositorError, CompositorInner, KeybindingAction, SurfaceEvent, SurfaceListener,
+	},
+	config::Config,
+	input::Keybinding,
+};
+
+type WindowManagerFactory<G> = Box<dyn FnOnce(Synced<PointerState>) -> Box<dyn WindowManagerBehavior<G>>>;
+
+/// Sets up a compositor for programs that embed wally instead of going through its binary
+pub struct CompositorBuilder<I: InputBackend, G: GraphicsBackend> {
+	input_backend: I,
+	graphics_backend: G,
+	config: Config,
+	window_manager: Option<WindowManagerFactory<G>>,
+	keybindings: Vec<(Keybinding, KeybindingAction<I, G>)>,
+	surface_listeners: Vec<SurfaceListener<I, G>>,
+}
+
+impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> CompositorBuilder<I, G> {
+	pub fn new(input_backend: I, graphics_backend: G) -> Self {
+		Self {
+			input_backend,
+			graphics_backend,
+			config: Config::default(),
+			window_manager: None,
+			keybindings: Vec::new(),
+			surface_listeners: Vec::new(),
+		}
+	}
+
+	/// Use the given options instead of the default ones
+	pub fn config(mut self, config: Config) -> Self {
+		self.config = config;
+		self
+	}
+
+	/// Set the name of the wayland socket, which is created in `$XDG_RUNTIME_DIR`. The first free `wayland-N` name is
+	/// used if this isn't called.
+	pub fn socket_name<S: Into<String>>(mut self, socket_name: S) -> Self {
+		self.config.socket_name = Some(socket_name.into());
+		self
+	}
+
+	/// Create a control socket at `path`, which other programs can use to list and manage windows. See
+	/// [`ipc`](crate::compositor::ipc) for the requests it understands.
+	pub fn ipc_socket<P: Into<PathBuf>>(mut self, path: P) -> Self {
+		self.config.ipc_socket = Some(path.into());
+		self
+	}
+
+	/// Replace the default window placement policy. The closure is given the compositor's pointer state and
+	/// returns the behavior that will manage the compositor's windows.
+	pub fn window_manager<F>(mut self, window_manager: F) -> Self
+	where
+		F: FnOnce(Synced<PointerState>) -> Box<dyn WindowManagerBehavior<G>> + 'static,
+	{
+		self.window_manager = Some(Box::new(window_manager));
+		self
+	}
+
+	/// Run `action` whenever `keybinding` is pressed. The key events of the binding aren't sent to clients.
+	pub fn keybinding<F>(mut self, keybinding: Keybinding, action: F) -> Self
+	where
+		F: FnMut(&mut CompositorInner<I, G>) + Send + 'static,
+	{
+		self.keybindings.push((keybinding, Box::new(action)));
+		self
+	}
+
+	/// Run `listener` whenever a surface is mapped, unmapped, resized, retitled, or destroyed
+	pub fn surface_listener<F>(mut self, listener: F) -> Self
+	where
+		F: FnMut(&mut CompositorInner<I, G>, &SurfaceEvent) + Send + 'static,
+	{
+		self.surface_listeners.push(Box::new(listener));
+		self
+	}
+
+	/// Create the compositor and its globals. The returned compositor is ready to be run with [`Compositor::start`].
+	pub fn build(
+		self,
+		event_loop_handle: LoopHandle<Compositor<I, G>>,
+	) -> Result<Compositor<I, G>, CompositorError<G>> {
+		let mut compositor = Compositor::new(
+			self.input_backend,
+			self.graphics_backend,
+			self.config,
+			event_loop_handle,
+		)?;
+		{
+			let mut inner = compositor.inner.lock().unwrap();
+			if let Some(window_manager) = self.window_manager {
+				let pointer = Arc::clone(&inner.pointer);
+				inner.window_manager = WindowManager::new(window_manager(pointer));
+			}
+			inner.keybindings.extend(self.keybindings);
+			inner.surface_listeners.extend(self.surface_listeners);
+		}
+		compositor.init();
+		Ok(compositor)
+	}
+}
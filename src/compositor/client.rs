@@ -1,8 +1,66 @@
-use wayland_server::{protocol::*, Client};
+use wayland_protocols::unstable::pointer_gestures::v1::server::{
+	zwp_pointer_gesture_pinch_v1::ZwpPointerGesturePinchV1, zwp_pointer_gesture_swipe_v1::ZwpPointerGestureSwipeV1,
+};
+use wayland_server::{protocol::*, Client, Interface, Resource};
+
+/// The resources a client currently holds, which are checked against the configured client limits whenever it
+/// creates a new object
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ClientUsage {
+	pub surfaces: usize,
+	pub shm_pools: usize,
+	pub buffers: usize,
+	/// The total size of the shm pools the client has mapped
+	pub shm_bytes: usize,
+}
 
 pub struct ClientInfo {
 	pub(crate) client: Client,
 	pub(crate) keyboards: Vec<wl_keyboard::WlKeyboard>,
 	pub(crate) pointers: Vec<wl_pointer::WlPointer>,
+	pub(crate) touches: Vec<wl_touch::WlTouch>,
 	pub(crate) outputs: Vec<wl_output::WlOutput>,
+	pub(crate) data_devices: Vec<wl_data_device::WlDataDevice>,
+	pub(crate) swipe_gestures: Vec<ZwpPointerGestureSwipeV1>,
+	pub(crate) pinch_gestures: Vec<ZwpPointerGesturePinchV1>,
+	pub(crate) usage: ClientUsage,
+	/// Whether the client failed to answer a ping in time and hasn't answered one since
+	pub(crate) unresponsive: bool,
+}
+
+impl ClientInfo {
+	/// Disconnect the client because it tried to go over one of its resource limits with a request sent to
+	/// `resource`. The client is told why with a protocol error, which disconnects it.
+	pub(crate) fn disconnect_over_limit<I>(&self, resource: &Resource<I>, limit: &str)
+	where
+		I: Interface + AsRef<Resource<I>> + From<Resource<I>>,
+	{
+		log::warn!("Client went over its limit of {}, disconnecting it", limit);
+		resource.post_error(
+			wl_display::Error::NoMemory as u32,
+			format!("Went over the limit of {}", limit),
+		);
+	}
+
+	/// The number of objects the compositor is tracking for this client, grouped by interface name. Only the
+	/// interfaces the compositor keeps track of are counted.
+	pub fn objects_by_interface(&self) -> Vec<(&'static str, usize)> {
+		vec![
+			(wl_surface::WlSurface::NAME, self.usage.surfaces),
+			(wl_shm_pool::WlShmPool::NAME, self.usage.shm_pools),
+			(wl_buffer::WlBuffer::NAME, self.usage.buffers),
+			(wl_keyboard::WlKeyboard::NAME, self.keyboards.len()),
+			(wl_pointer::WlPointer::NAME, self.pointers.len()),
+			(wl_touch::WlTouch::NAME, self.touches.len()),
+			(wl_output::WlOutput::NAME, self.outputs.len()),
+			(wl_data_device::WlDataDevice::NAME, self.data_devices.len()),
+			(ZwpPointerGestureSwipeV1::NAME, self.swipe_gestures.len()),
+			(ZwpPointerGesturePinchV1::NAME, self.pinch_gestures.len()),
+		]
+	}
+
+	/// The total number of objects the compositor is tracking for this client
+	pub fn object_count(&self) -> usize {
+		self.objects_by_interface().iter().map(|&(_, count)| count).sum()
+	}
 }
@@ -0,0 +1,152 @@
+use std::sync::{Arc, Mutex};
+
+use wayland_server::{protocol::*, Filter, Main};
+
+use crate::{
+	backend::{GraphicsBackend, InputBackend},
+	compositor::{client::ClientInfo, surface::SurfaceData, Compositor, CompositorInner, Synced, UserDataAccess},
+};
+
+/// The mime types a wl_data_source has offered, stored in its user data
+#[derive(Debug, Default)]
+pub struct DataSourceData {
+	pub mime_types: Vec<String>,
+}
+
+impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
+	pub(crate) fn setup_data_device_manager_global(&mut self) {
+		let inner = Arc::clone(&self.inner);
+		let data_device_manager_filter = Filter::new(
+			move |(main, _num): (Main<wl_data_device_manager::WlDataDeviceManager>, u32), _filter, _dispatch_data| {
+				let inner = Arc::clone(&inner);
+				main.quick_assign(move |_main, request: wl_data_device_manager::Request, _dispatch_data| {
+					match request {
+						wl_data_device_manager::Request::CreateDataSource { id } => {
+							let inner = Arc::clone(&inner);
+							let data_source_data: Synced<DataSourceData> =
+								Arc::new(Mutex::new(DataSourceData::default()));
+							id.as_ref().user_data().set_threadsafe(move || data_source_data);
+							id.quick_assign(|main, request, _dispatch_data| match request {
+								wl_data_source::Request::Offer { mime_type } => {
+									let data_source_data = main.get_synced::<DataSourceData>();
+									data_source_data.lock().unwrap().mime_types.push(mime_type);
+								}
+								wl_data_source::Request::SetActions { .. } => {
+									// TODO: drag and drop
+								}
+								wl_data_source::Request::Destroy => {}
+								_ => log::warn!("Got unknown request for wl_data_source"),
+							});
+							id.assign_destructor(Filter::new(
+								move |data_source: wl_data_source::WlDataSource, _filter, _dispatch_data| {
+									let mut inner_lock = inner.lock().unwrap();
+									if inner_lock.selection.as_ref() == Some(&data_source) {
+										inner_lock.selection = None;
+										inner_lock.offer_selection_to_focus();
+									}
+								},
+							));
+						}
+						wl_data_device_manager::Request::GetDataDevice { id, seat: _ } => {
+							let inner = Arc::clone(&inner);
+							let data_device = (*id).clone();
+							let mut inner_lock = inner.lock().unwrap();
+							let client_info = inner_lock
+								.client_manager
+								.get_client_info(data_device.as_ref().client().unwrap());
+							client_info.lock().unwrap().data_devices.push(data_device);
+							drop(inner_lock);
+							id.quick_assign(move |main, request, _dispatch_data| match request {
+								wl_data_device::Request::SetSelection { source, serial: _ } => {
+									let mut inner_lock = inner.lock().unwrap();
+									if let Some(old_source) = std::mem::replace(&mut inner_lock.selection, source) {
+										if inner_lock.selection.as_ref() != Some(&old_source) {
+											old_source.cancelled();
+										}
+									}
+									inner_lock.offer_selection_to_focus();
+								}
+								wl_data_device::Request::StartDrag { .. } => {
+									log::warn!("Drag and drop is not implemented");
+								}
+								wl_data_device::Request::Release => {
+									let mut inner_lock = inner.lock().unwrap();
+									let client_info = inner_lock
+										.client_manager
+										.get_client_info(main.as_ref().client().unwrap());
+									client_info
+										.lock()
+										.unwrap()
+										.data_devices
+										.retain(|data_device| *data_device != *main);
+								}
+								_ => log::warn!("Got unknown request for wl_data_device"),
+							});
+						}
+						_ => {
+							log::warn!("Got unknown request for wl_data_device_manager");
+						}
+					}
+				})
+			},
+		);
+		self.display
+			.create_global::<wl_data_device_manager::WlDataDeviceManager, _>(3, data_device_manager_filter);
+	}
+}
+
+impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> CompositorInner<I, G> {
+	/// Offer the current selection to the client that has keyboard focus
+	pub(crate) fn offer_selection_to_focus(&self) {
+		if let Some(ref focused) = self.keyboard_focus {
+			let surface_data = focused.get_synced::<SurfaceData<G>>();
+			let surface_data_lock = surface_data.lock().unwrap();
+			let client_info_lock = surface_data_lock.client_info.lock().unwrap();
+			offer_selection(self.selection.as_ref(), &client_info_lock);
+		}
+	}
+}
+
+/// Offer the current selection to every data device of a client, which is done whenever the client gains keyboard
+/// focus or the selection changes while it has focus
+pub(crate) fn offer_selection(selection: Option<&wl_data_source::WlDataSource>, client_info: &ClientInfo) {
+	for data_device in &client_info.data_devices {
+		let source = match selection {
+			Some(source) => source,
+			None => {
+				data_device.selection(None);
+				continue;
+			}
+		};
+		let offer = match client_info
+			.client
+			.create_resource::<wl_data_offer::WlDataOffer>(data_device.as_ref().version())
+		{
+			Some(offer) => offer,
+			None => continue,
+		};
+		let offer_source = source.clone();
+		offer.quick_assign(move |_main, request, _dispatch_data| match request {
+			wl_data_offer::Request::Receive { mime_type, fd } => {
+				// The source client writes the data into the file descriptor directly
+				if offer_source.as_ref().is_alive() {
+					offer_source.send(mime_type, fd);
+				}
+				if let Err(e) = nix::unistd::close(fd) {
+					log::error!("Failed to close data offer file descriptor: {}", e);
+				}
+			}
+			wl_data_offer::Request::Accept { .. } => {}
+			wl_data_offer::Request::Finish => {}
+			wl_data_offer::Request::SetActions { .. } => {}
+			wl_data_offer::Request::Destroy => {}
+			_ => log::warn!("Got unknown request for wl_data_offer"),
+		});
+		data_device.data_offer(&offer);
+		let data_source_data = source.get_synced::<DataSourceData>();
+		for mime_type in &data_source_data.lock().unwrap().mime_types {
+			offer.offer(mime_type.clone());
+		}
+		data_device.selection(Some(&offer));
+	}
+}
@@ -0,0 +1,155 @@
+use std::sync::Arc;
+
+use wayland_protocols::unstable::pointer_gestures::v1::server::{
+	zwp_pointer_gesture_pinch_v1, zwp_pointer_gesture_swipe_v1, zwp_pointer_gestures_v1,
+};
+use wayland_server::{protocol::*, Filter, Main};
+
+use crate::{
+	backend::{GesturePinch, GestureSwipe, GraphicsBackend, InputBackend},
+	compositor::{surface::SurfaceData, Compositor, CompositorInner, UserDataAccess},
+};
+
+impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
+	pub(crate) fn setup_pointer_gestures_global(&mut self) {
+		let inner = Arc::clone(&self.inner);
+		let pointer_gestures_filter = Filter::new(
+			move |(main, _num): (Main<zwp_pointer_gestures_v1::ZwpPointerGesturesV1>, u32), _filter, _dispatch_data| {
+				let inner = Arc::clone(&inner);
+				main.quick_assign(
+					move |_main, request: zwp_pointer_gestures_v1::Request, _dispatch_data| {
+						let inner = Arc::clone(&inner);
+						match request {
+							zwp_pointer_gestures_v1::Request::GetSwipeGesture { id, pointer: _ } => {
+								let swipe_gesture = (*id).clone();
+								let mut inner_lock = inner.lock().unwrap();
+								let client_info = inner_lock
+									.client_manager
+									.get_client_info(swipe_gesture.as_ref().client().unwrap());
+								client_info.lock().unwrap().swipe_gestures.push(swipe_gesture);
+								drop(inner_lock);
+								id.quick_assign(move |main, request, _dispatch_data| match request {
+									zwp_pointer_gesture_swipe_v1::Request::Destroy => {
+										let mut inner_lock = inner.lock().unwrap();
+										let client_info = inner_lock
+											.client_manager
+											.get_client_info(main.as_ref().client().unwrap());
+										client_info
+											.lock()
+											.unwrap()
+											.swipe_gestures
+											.retain(|swipe_gesture| *swipe_gesture != *main);
+									}
+									_ => log::warn!("Got unknown request for zwp_pointer_gesture_swipe_v1"),
+								});
+							}
+							zwp_pointer_gestures_v1::Request::GetPinchGesture { id, pointer: _ } => {
+								let pinch_gesture = (*id).clone();
+								let mut inner_lock = inner.lock().unwrap();
+								let client_info = inner_lock
+									.client_manager
+									.get_client_info(pinch_gesture.as_ref().client().unwrap());
+								client_info.lock().unwrap().pinch_gestures.push(pinch_gesture);
+								drop(inner_lock);
+								id.quick_assign(move |main, request, _dispatch_data| match request {
+									zwp_pointer_gesture_pinch_v1::Request::Destroy => {
+										let mut inner_lock = inner.lock().unwrap();
+										let client_info = inner_lock
+											.client_manager
+											.get_client_info(main.as_ref().client().unwrap());
+										client_info
+											.lock()
+											.unwrap()
+											.pinch_gestures
+											.retain(|pinch_gesture| *pinch_gesture != *main);
+									}
+									_ => log::warn!("Got unknown request for zwp_pointer_gesture_pinch_v1"),
+								});
+							}
+							_ => {
+								log::warn!("Got unknown request for zwp_pointer_gestures_v1");
+							}
+						}
+					},
+				)
+			},
+		);
+		self.display
+			.create_global::<zwp_pointer_gestures_v1::ZwpPointerGesturesV1, _>(1, pointer_gestures_filter);
+	}
+}
+
+impl<I: InputBackend, G: GraphicsBackend + 'static> CompositorInner<I, G> {
+	/// Forward a swipe to the surface it began on. A swipe begins on the surface with pointer focus and stays with
+	/// it until it ends, even if the pointer moves away in the meantime.
+	pub(crate) fn handle_gesture_swipe(&mut self, gesture_swipe: GestureSwipe) {
+		let surface = match self.gesture_surface(matches!(gesture_swipe, GestureSwipe::Begin { .. })) {
+			Some(surface) => surface,
+			None => return,
+		};
+		let surface_data = surface.get_synced::<SurfaceData<G>>();
+		let surface_data_lock = surface_data.lock().unwrap();
+		let client_info_lock = surface_data_lock.client_info.lock().unwrap();
+		for swipe_gesture in &client_info_lock.swipe_gestures {
+			match gesture_swipe {
+				GestureSwipe::Begin { serial, time, fingers } => swipe_gesture.begin(serial, time, &surface, fingers),
+				GestureSwipe::Update { time, dx, dy } => swipe_gesture.update(time, dx, dy),
+				GestureSwipe::End {
+					serial,
+					time,
+					cancelled,
+				} => swipe_gesture.end(serial, time, cancelled as i32),
+			}
+		}
+		if let GestureSwipe::End { .. } = gesture_swipe {
+			self.gesture_focus = None;
+		}
+	}
+
+	/// Forward a pinch to the surface it began on, the same way as
+	/// [`handle_gesture_swipe`](CompositorInner::handle_gesture_swipe)
+	pub(crate) fn handle_gesture_pinch(&mut self, gesture_pinch: GesturePinch) {
+		let surface = match self.gesture_surface(matches!(gesture_pinch, GesturePinch::Begin { .. })) {
+			Some(surface) => surface,
+			None => return,
+		};
+		let surface_data = surface.get_synced::<SurfaceData<G>>();
+		let surface_data_lock = surface_data.lock().unwrap();
+		let client_info_lock = surface_data_lock.client_info.lock().unwrap();
+		for pinch_gesture in &client_info_lock.pinch_gestures {
+			match gesture_pinch {
+				GesturePinch::Begin { serial, time, fingers } => pinch_gesture.begin(serial, time, &surface, fingers),
+				GesturePinch::Update {
+					time,
+					dx,
+					dy,
+					scale,
+					rotation,
+				} => pinch_gesture.update(time, dx, dy, scale, rotation),
+				GesturePinch::End {
+					serial,
+					time,
+					cancelled,
+				} => pinch_gesture.end(serial, time, cancelled as i32),
+			}
+		}
+		if let GesturePinch::End { .. } = gesture_pinch {
+			self.gesture_focus = None;
+		}
+	}
+
+	/// Get the surface a gesture event should be sent to, first moving the gesture focus to the pointer focus if the
+	/// event begins a new gesture
+	fn gesture_surface(&mut self, begin: bool) -> Option<wl_surface::WlSurface> {
+		if begin {
+			self.gesture_focus = self.pointer_focus.clone();
+		}
+		match self.gesture_focus.clone() {
+			Some(surface) if surface.as_ref().is_alive() => Some(surface),
+			_ => {
+				self.gesture_focus = None;
+				None
+			}
+		}
+	}
+}
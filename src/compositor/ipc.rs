@@ -0,0 +1,409 @@
+//! A control socket that lets other programs query and manage the compositor's windows. Every request is a single
+//! line, and is answered with zero or more lines of output followed by a line that is either `ok` or
+//! `error: MESSAGE`. The requests are:
+//!
+//! - `windows`: list every mapped window from the bottom of the stack to the top, one per line, as the tab separated
+//!   fields `ID GEOMETRY FOCUSED APP_ID TITLE`. The geometry is written as `X,Y WIDTHxHEIGHT`, and values a window
+//!   doesn't have are written as `-`.
+//! - `focus WINDOW`, `raise WINDOW`, and `close WINDOW`
+//! - `snap WINDOW POSITION`, where the position is one of `left-half`, `right-half`, `top-left`, `top-right`,
+//!   `bottom-left`, `bottom-right`, or `maximized`
+//! - `stop`: stop the compositor
+//!
+//! `WINDOW` is either the id of a window as listed by `windows`, or `focused` for the window with keyboard focus.
+
+use std::{
+	fmt, fs,
+	io::{self, Read, Write},
+	mem,
+	os::unix::{
+		io::{AsRawFd, RawFd},
+		net::{UnixListener, UnixStream},
+	},
+	path::{Path, PathBuf},
+	str::FromStr,
+};
+
+use calloop::{
+	generic::{Event, EventedRawFd, Generic},
+	mio, LoopHandle, Source,
+};
+use thiserror::Error;
+
+use crate::{
+	behavior::SnapPosition,
+	compositor::{
+		prelude::*,
+		window::{WindowId, WindowInfo},
+		Compositor, CompositorInner,
+	},
+};
+
+/// Connections that send this much without ending a line are disconnected
+const MAX_REQUEST_LENGTH: usize = 4096;
+
+/// The listening control socket and every connection to it
+pub struct IpcServer<I: InputBackend, G: GraphicsBackend> {
+	path: PathBuf,
+	listener: UnixListener,
+	connections: Vec<IpcConnection>,
+	event_loop_handle: LoopHandle<Compositor<I, G>>,
+	_listener_event_source: Source<Generic<EventedRawFd>>,
+}
+
+struct IpcConnection {
+	stream: UnixStream,
+	/// What has been read from the connection that doesn't make up a whole line yet
+	buffer: Vec<u8>,
+	/// Whether the connection was closed by the client or failed. Connections can't remove their own event source
+	/// while it's being dispatched, so closed connections are removed after the event loop is done dispatching.
+	closed: bool,
+	event_source: Source<Generic<EventedRawFd>>,
+}
+
+impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> IpcServer<I, G> {
+	/// Create the control socket at `path` and start accepting connections to it in the event loop. A socket left
+	/// behind at `path` by a compositor that didn't exit cleanly is replaced.
+	pub fn bind(path: PathBuf, event_loop_handle: LoopHandle<Compositor<I, G>>) -> io::Result<Self> {
+		let listener = bind_listener(&path)?;
+		listener.set_nonblocking(true)?;
+		let listener_event_source = event_loop_handle
+			.insert_source(
+				readable_source(listener.as_raw_fd()),
+				|_event: Event<EventedRawFd>, compositor: &mut Compositor<I, G>| compositor.accept_ipc_connections(),
+			)
+			.expect("Failed to insert the control socket in the event loop");
+		log::info!("Listening for control connections at {}", path.display());
+		Ok(Self {
+			path,
+			listener,
+			connections: Vec::new(),
+			event_loop_handle,
+			_listener_event_source: listener_event_source,
+		})
+	}
+
+	fn add_connection(&mut self, stream: UnixStream) {
+		if let Err(e) = stream.set_nonblocking(true) {
+			log::error!("Failed to make a control connection nonblocking: {}", e);
+			return;
+		}
+		let fd = stream.as_raw_fd();
+		let event_source = self
+			.event_loop_handle
+			.insert_source(
+				readable_source(fd),
+				move |_event: Event<EventedRawFd>, compositor: &mut Compositor<I, G>| {
+					compositor.handle_ipc_connection(fd)
+				},
+			)
+			.expect("Failed to insert a control connection in the event loop");
+		self.connections.push(IpcConnection {
+			stream,
+			buffer: Vec::new(),
+			closed: false,
+			event_source,
+		});
+	}
+
+	pub(crate) fn remove_closed_connections(&mut self) {
+		let (closed, open): (Vec<_>, Vec<_>) = mem::take(&mut self.connections)
+			.into_iter()
+			.partition(|connection| connection.closed);
+		self.connections = open;
+		for connection in closed {
+			connection.event_source.remove();
+		}
+	}
+}
+
+impl<I: InputBackend, G: GraphicsBackend> Drop for IpcServer<I, G> {
+	fn drop(&mut self) {
+		if let Err(e) = fs::remove_file(&self.path) {
+			log::error!("Failed to remove the control socket at {}: {}", self.path.display(), e);
+		}
+	}
+}
+
+impl IpcConnection {
+	/// Read everything the client has sent so far, returning every complete line
+	fn read_requests(&mut self) -> Vec<String> {
+		let mut buf = [0; 1024];
+		loop {
+			match self.stream.read(&mut buf) {
+				Ok(0) => {
+					self.closed = true;
+					break;
+				}
+				Ok(len) => self.buffer.extend_from_slice(&buf[..len]),
+				Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+				Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+				Err(e) => {
+					log::warn!("Failed to read from a control connection: {}", e);
+					self.closed = true;
+					break;
+				}
+			}
+		}
+		let mut requests = Vec::new();
+		while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+			let line = self.buffer.drain(..=end).collect::<Vec<_>>();
+			let line = String::from_utf8_lossy(&line).trim().to_owned();
+			if !line.is_empty() {
+				requests.push(line);
+			}
+		}
+		if self.buffer.len() > MAX_REQUEST_LENGTH {
+			log::warn!("Closing a control connection that sent a request that was too long");
+			self.closed = true;
+		}
+		requests
+	}
+
+	fn send(&mut self, response: &str) {
+		if let Err(e) = self.stream.write_all(response.as_bytes()) {
+			log::warn!("Failed to respond to a control connection: {}", e);
+			self.closed = true;
+		}
+	}
+}
+
+impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
+	fn accept_ipc_connections(&mut self) {
+		let ipc_server = match self.ipc_server.as_mut() {
+			Some(ipc_server) => ipc_server,
+			None => return,
+		};
+		loop {
+			match ipc_server.listener.accept() {
+				Ok((stream, _addr)) => ipc_server.add_connection(stream),
+				Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+				Err(e) => {
+					log::error!("Failed to accept a control connection: {}", e);
+					break;
+				}
+			}
+		}
+	}
+
+	fn handle_ipc_connection(&mut self, fd: RawFd) {
+		let inner = Arc::clone(&self.inner);
+		let connection = match self.ipc_server.as_mut().and_then(|ipc_server| {
+			ipc_server
+				.connections
+				.iter_mut()
+				.find(|connection| connection.stream.as_raw_fd() == fd)
+		}) {
+			Some(connection) => connection,
+			None => return,
+		};
+		for line in connection.read_requests() {
+			let result = line
+				.parse::<IpcRequest>()
+				.and_then(|request| inner.lock().unwrap().handle_ipc_request(request));
+			connection.send(&format_response(result));
+		}
+	}
+}
+
+/// A request sent to the control socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpcRequest {
+	Windows,
+	Focus(WindowTarget),
+	Raise(WindowTarget),
+	Close(WindowTarget),
+	Snap(WindowTarget, SnapPosition),
+	Stop,
+}
+
+/// The window a request acts on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowTarget {
+	Id(WindowId),
+	/// The window with keyboard focus
+	Focused,
+}
+
+impl FromStr for IpcRequest {
+	type Err = IpcError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut args = s.split_whitespace();
+		let command = args.next().unwrap_or("");
+		let mut window = || {
+			args.next()
+				.ok_or(IpcError::MissingArgument("a window"))
+				.and_then(str::parse)
+		};
+		let request = match command {
+			"windows" => IpcRequest::Windows,
+			"focus" => IpcRequest::Focus(window()?),
+			"raise" => IpcRequest::Raise(window()?),
+			"close" => IpcRequest::Close(window()?),
+			"snap" => {
+				let window = window()?;
+				let position = args.next().ok_or(IpcError::MissingArgument("a snap position"))?;
+				IpcRequest::Snap(window, parse_snap_position(position)?)
+			}
+			"stop" => IpcRequest::Stop,
+			_ => return Err(IpcError::UnknownCommand(String::from(command))),
+		};
+		match args.next() {
+			Some(arg) => Err(IpcError::UnexpectedArgument(String::from(arg))),
+			None => Ok(request),
+		}
+	}
+}
+
+impl FromStr for WindowTarget {
+	type Err = IpcError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"focused" => Ok(WindowTarget::Focused),
+			_ => s
+				.parse()
+				.map(|id| WindowTarget::Id(WindowId(id)))
+				.map_err(|_| IpcError::InvalidWindow(String::from(s))),
+		}
+	}
+}
+
+impl fmt::Display for WindowTarget {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			WindowTarget::Id(id) => write!(f, "{}", id.0),
+			WindowTarget::Focused => write!(f, "focused"),
+		}
+	}
+}
+
+fn parse_snap_position(s: &str) -> Result<SnapPosition, IpcError> {
+	match s {
+		"left-half" => Ok(SnapPosition::LeftHalf),
+		"right-half" => Ok(SnapPosition::RightHalf),
+		"top-left" => Ok(SnapPosition::TopLeft),
+		"top-right" => Ok(SnapPosition::TopRight),
+		"bottom-left" => Ok(SnapPosition::BottomLeft),
+		"bottom-right" => Ok(SnapPosition::BottomRight),
+		"maximized" => Ok(SnapPosition::Maximized),
+		_ => Err(IpcError::InvalidSnapPosition(String::from(s))),
+	}
+}
+
+impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> CompositorInner<I, G> {
+	/// Carry out a control socket request, returning the lines of output it produced
+	pub fn handle_ipc_request(&mut self, request: IpcRequest) -> Result<Vec<String>, IpcError> {
+		match request {
+			IpcRequest::Windows => return Ok(self.windows().iter().map(format_window).collect()),
+			IpcRequest::Focus(target) => {
+				let id = self.resolve_window(target)?;
+				self.focus_window(id);
+			}
+			IpcRequest::Raise(target) => {
+				let id = self.resolve_window(target)?;
+				self.raise_window(id);
+			}
+			IpcRequest::Close(target) => {
+				let id = self.resolve_window(target)?;
+				self.close_window(id);
+			}
+			IpcRequest::Snap(target, position) => {
+				let id = self.resolve_window(target)?;
+				if let Some(surface) = self.find_window(id) {
+					self.snap_surface(&surface, position);
+				}
+			}
+			IpcRequest::Stop => self.stop(),
+		}
+		Ok(Vec::new())
+	}
+
+	fn resolve_window(&self, target: WindowTarget) -> Result<WindowId, IpcError> {
+		let id = match target {
+			WindowTarget::Id(id) => Some(id),
+			WindowTarget::Focused => self
+				.keyboard_focus
+				.as_ref()
+				.map(|focused| focused.get_synced::<SurfaceData<G>>().lock().unwrap().window_id),
+		};
+		id.filter(|&id| self.find_window(id).is_some())
+			.ok_or(IpcError::NoSuchWindow(target))
+	}
+}
+
+fn format_response(result: Result<Vec<String>, IpcError>) -> String {
+	match result {
+		Ok(lines) => {
+			let mut response = String::new();
+			for line in lines {
+				response.push_str(&line);
+				response.push('\n');
+			}
+			response.push_str("ok\n");
+			response
+		}
+		Err(e) => format!("error: {}\n", e),
+	}
+}
+
+fn format_window(window: &WindowInfo) -> String {
+	let geometry = window
+		.geometry
+		.map(|geometry| format!("{},{} {}x{}", geometry.x, geometry.y, geometry.width, geometry.height))
+		.unwrap_or_else(|| String::from("-"));
+	format!(
+		"{}\t{}\t{}\t{}\t{}",
+		window.id.0,
+		geometry,
+		if window.focused { "focused" } else { "-" },
+		format_field(window.app_id.as_deref()),
+		format_field(window.title.as_deref()),
+	)
+}
+
+/// Format a string set by a client as a single field. Clients can put anything in their titles, including the tabs
+/// and newlines that separate fields and lines.
+fn format_field(value: Option<&str>) -> String {
+	match value {
+		Some(value) if !value.is_empty() => value.chars().map(|c| if c.is_control() { ' ' } else { c }).collect(),
+		_ => String::from("-"),
+	}
+}
+
+/// Bind a listener at `path`. If something is already there but nothing is accepting connections on it, it's left
+/// over from a compositor that didn't clean up, so it's replaced.
+fn bind_listener(path: &Path) -> io::Result<UnixListener> {
+	match UnixListener::bind(path) {
+		Err(ref e) if e.kind() == io::ErrorKind::AddrInUse && UnixStream::connect(path).is_err() => {
+			log::info!("Replacing the stale control socket at {}", path.display());
+			fs::remove_file(path)?;
+			UnixListener::bind(path)
+		}
+		result => result,
+	}
+}
+
+fn readable_source(fd: RawFd) -> Generic<EventedRawFd> {
+	let mut source = Generic::from_raw_fd(fd);
+	source.set_interest(mio::Ready::readable());
+	source.set_pollopts(mio::PollOpt::edge());
+	source
+}
+
+#[derive(Debug, Error)]
+pub enum IpcError {
+	#[error("Unknown command '{0}'")]
+	UnknownCommand(String),
+	#[error("Expected {0}")]
+	MissingArgument(&'static str),
+	#[error("Unexpected argument '{0}'")]
+	UnexpectedArgument(String),
+	#[error("Invalid window '{0}', expected a window id or 'focused'")]
+	InvalidWindow(String),
+	#[error("Invalid snap position '{0}'")]
+	InvalidSnapPosition(String),
+	#[error("No window matches '{0}'")]
+	NoSuchWindow(WindowTarget),
+}
@@ -0,0 +1,97 @@
+//! Clients normally repeat held keys themselves, at the rate and delay they're sent in wl_keyboard.repeat_info. With
+//! server side repeat, the compositor sends a held key to the surface it was pressed on again and again until it's
+//! released instead, and clients are told not to repeat keys themselves.
+
+use std::time::Duration;
+
+use calloop::timer::Timeout;
+
+use crate::{
+	backend::get_time_ms,
+	compositor::{get_input_serial, prelude::*, surface::same_surface, CompositorInner},
+};
+
+/// A held key that the compositor is repeating
+pub(crate) struct KeyRepeat {
+	key: u32,
+	/// The surface that had keyboard focus when the key was pressed
+	surface: wl_surface::WlSurface,
+	timeout: Timeout,
+}
+
+impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> CompositorInner<I, G> {
+	/// Start repeating a key that was just sent to the surface with keyboard focus, if server side repeat is enabled
+	/// and the key repeats in the keymap. The key that was repeating before stops, like it does when clients repeat
+	/// keys.
+	pub(crate) fn start_key_repeat(&mut self, key: u32) {
+		self.stop_key_repeat();
+		if !self.config.server_side_repeat {
+			return;
+		}
+		let surface = match self.keyboard_focus.clone() {
+			Some(surface) => surface,
+			None => return,
+		};
+		let keyboard_state_lock = self.keyboard_state.lock().unwrap();
+		// xkb keycodes are offset from evdev ones by 8
+		if keyboard_state_lock.repeat_rate <= 0 || !keyboard_state_lock.keymap.key_repeats(key + 8) {
+			return;
+		}
+		let delay = Duration::from_millis(keyboard_state_lock.repeat_delay.max(0) as u64);
+		drop(keyboard_state_lock);
+		let timeout = self.key_repeat_timer.add_timeout(delay, ());
+		self.key_repeat = Some(KeyRepeat { key, surface, timeout });
+	}
+
+	/// Stop repeating a key if it's the one being repeated, for when it's released
+	pub(crate) fn release_repeated_key(&mut self, key: u32) {
+		if self
+			.key_repeat
+			.as_ref()
+			.map(|key_repeat| key_repeat.key == key)
+			.unwrap_or(false)
+		{
+			self.stop_key_repeat();
+		}
+	}
+
+	pub(crate) fn stop_key_repeat(&mut self) {
+		if let Some(key_repeat) = self.key_repeat.take() {
+			self.key_repeat_timer.cancel_timeout(&key_repeat.timeout);
+		}
+	}
+
+	/// Send the repeated key again and schedule its next repeat. The key stops repeating once the surface it was
+	/// pressed on loses keyboard focus.
+	pub(crate) fn repeat_key(&mut self) {
+		let key_repeat = match self.key_repeat.take() {
+			Some(key_repeat) => key_repeat,
+			// Timeouts are cancelled when their key stops repeating, so this shouldn't happen
+			None => return,
+		};
+		let still_focused = self
+			.keyboard_focus
+			.as_ref()
+			.map(|focus| same_surface::<G>(focus, &key_repeat.surface))
+			.unwrap_or(false);
+		if !still_focused || !key_repeat.surface.as_ref().is_alive() {
+			return;
+		}
+
+		let surface_data = key_repeat.surface.get_synced::<SurfaceData<G>>();
+		let surface_data_lock = surface_data.lock().unwrap();
+		let client_info_lock = surface_data_lock.client_info.lock().unwrap();
+		let serial = get_input_serial();
+		let time = get_time_ms();
+		for keyboard in &client_info_lock.keyboards {
+			keyboard.key(serial, time, key_repeat.key, wl_keyboard::KeyState::Pressed);
+		}
+		drop(client_info_lock);
+		drop(surface_data_lock);
+
+		let repeat_rate = self.keyboard_state.lock().unwrap().repeat_rate;
+		let interval = Duration::from_millis(1000 / repeat_rate.max(1) as u64);
+		let timeout = self.key_repeat_timer.add_timeout(interval, ());
+		self.key_repeat = Some(KeyRepeat { timeout, ..key_repeat });
+	}
+}
@@ -1,10 +1,12 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use wayland_server::{protocol::*, Filter, Main};
 
 use crate::{
-	backend::{GraphicsBackend, InputBackend},
-	compositor::Compositor,
+	backend::{GraphicsBackend, InputBackend, OutputResized},
+	behavior::SnapPosition,
+	compositor::{prelude::*, Compositor, CompositorInner},
+	renderer::Output,
 };
 
 impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
@@ -13,6 +15,8 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 		let outputs = graphics_backend_state_lock.renderer.outputs();
 		for output in outputs {
 			let inner = Arc::clone(&self.inner);
+			let output = Arc::new(Mutex::new(output));
+			let output_clone = Arc::clone(&output);
 			let output_filter = Filter::new(
 				move |(main, _num): (Main<wl_output::WlOutput>, u32), _filter, _dispatch_data| {
 					let inner = Arc::clone(&inner);
@@ -23,23 +27,15 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 						.get_client_info(output_interface.as_ref().client().unwrap());
 					let mut client_info_lock = client_info.lock().unwrap();
 					client_info_lock.outputs.push(output_interface.clone());
-					output_interface.as_ref().user_data().set_threadsafe(|| output);
-					output_interface.geometry(
-						output.viewport.x,
-						output.viewport.y,
-						0,
-						0,
-						wl_output::Subpixel::HorizontalBgr,
-						String::from("<unknown>"),
-						String::from("<unknown>"),
-						wl_output::Transform::Normal,
-					);
-					// TODO: don't hardcode
-					output_interface.mode(wl_output::Mode::Current | wl_output::Mode::Preferred, 1920, 1080, 75);
-					if output_interface.as_ref().version() >= 2 {
-						output_interface.scale(1);
-					}
-					output_interface.done();
+					let output_data = Arc::clone(&output_clone);
+					output_interface
+						.as_ref()
+						.user_data()
+						.set_threadsafe(move || output_data);
+					send_output_info(output_interface, &*output_clone.lock().unwrap());
+					drop(client_info_lock);
+					// Surfaces that are already on this output have to be told about it
+					inner_lock.update_all_surface_outputs();
 					main.quick_assign(move |_main, request, _dispatch_data| match request {
 						wl_output::Request::Release => {}
 						_ => log::warn!("Got unknown request for wl_output"),
@@ -51,4 +47,263 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 			inner_lock.output_globals.push((output_global, output));
 		}
 	}
+
+	/// Remove the outputs the graphics backend doesn't have anymore, along with their wl_output globals. This is
+	/// called once per frame.
+	pub(crate) fn remove_disconnected_outputs(&mut self) {
+		let disconnected = self
+			.graphics_backend_state
+			.lock()
+			.unwrap()
+			.renderer
+			.disconnected_outputs();
+		// Remove the last ones first, so the indices of the others stay the same
+		for index in disconnected.into_iter().rev() {
+			log::info!("Output {} was disconnected, removing it", index);
+			self.remove_output(index);
+		}
+	}
+
+	/// Remove the output at `index` from the renderer along with its wl_output global, so the two stay in the same
+	/// order. Destroying the global sends wl_registry::global_remove to every client, and the wl_outputs that were
+	/// bound to it are forgotten so they don't get any more events.
+	pub(crate) fn remove_output(&mut self, index: usize) {
+		let mut graphics_backend_state_lock = self.graphics_backend_state.lock().unwrap();
+		if let Err(e) = graphics_backend_state_lock.renderer.remove_output(index) {
+			log::error!("Failed to destroy the render target of a removed output: {}", e);
+		}
+		drop(graphics_backend_state_lock);
+
+		let mut inner_lock = self.inner.lock().unwrap();
+		if index >= inner_lock.output_globals.len() {
+			log::warn!("Tried to remove nonexistent output global {}", index);
+			return;
+		}
+		let (output_global, output) = inner_lock.output_globals.remove(index);
+		output_global.destroy();
+		for client_info in &inner_lock.client_manager.clients {
+			let mut client_info_lock = client_info.lock().unwrap();
+			client_info_lock.outputs.retain(|output_interface| {
+				let output_data = output_interface.get_synced::<Output<G>>();
+				!Arc::ptr_eq(&output_data, &output)
+			});
+		}
+		drop(inner_lock);
+		// The outputs after the removed one moved over, so their globals and the windows snapped to them are updated
+		self.sync_outputs();
+	}
+
+	pub(crate) fn handle_output_resized(&mut self, output_resized: OutputResized) {
+		let size = Size::new(output_resized.width, output_resized.height);
+		let mut graphics_backend_state_lock = self.graphics_backend_state.lock().unwrap();
+		// Windowed backends only ever have a single output, which is the one that gets resized
+		if let Err(e) = graphics_backend_state_lock.renderer.resize_output(0, size) {
+			log::error!("Failed to resize output: {}", e);
+			return;
+		}
+		drop(graphics_backend_state_lock);
+		self.sync_outputs();
+	}
+
+	/// Copy the renderer's outputs to the output globals after their mode, scale, or transform changed, and send
+	/// the new properties of every output that changed to the wl_outputs bound to it. Resizing one output can move
+	/// the others, so all of them are checked.
+	pub(crate) fn sync_outputs(&mut self) {
+		let outputs = self.graphics_backend_state.lock().unwrap().renderer.outputs();
+		let inner_lock = self.inner.lock().unwrap();
+		for ((_output_global, output_data), output) in inner_lock.output_globals.iter().zip(outputs) {
+			let mut output_data_lock = output_data.lock().unwrap();
+			let changed = output_data_lock.viewport != output.viewport
+				|| output_data_lock.scale != output.scale
+				|| output_data_lock.transform != output.transform;
+			*output_data_lock = output;
+			drop(output_data_lock);
+			if changed {
+				inner_lock.broadcast_output_info(output_data);
+			}
+		}
+		inner_lock.resnap_all_windows();
+		inner_lock.update_all_surface_outputs();
+	}
+}
+
+impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> CompositorInner<I, G> {
+	/// Fit every snapped or maximized window to the current work area of the output it's on, which has to be done
+	/// whenever outputs change size
+	pub(crate) fn resnap_all_windows(&self) {
+		for surface in self.window_manager.manager_impl.surfaces_ascending() {
+			let surface_data = surface.get_synced::<SurfaceData<G>>();
+			let mut surface_data_lock = surface_data.lock().unwrap();
+			let snap = match surface_data_lock.snap {
+				Some(snap) => snap,
+				None => continue,
+			};
+			// Fullscreen windows cover the whole output, not just its work area
+			let area = surface_data_lock.try_get_window_center().and_then(|center| {
+				if snap.position == SnapPosition::Fullscreen {
+					self.output_at(center).map(|output| output.viewport)
+				} else {
+					self.work_area_at(center)
+				}
+			});
+			if let Some(area) = area {
+				surface_data_lock.resnap_window(area);
+			}
+		}
+	}
+
+	/// Send the current properties of an output to every wl_output bound to it, each followed by a done event so
+	/// clients apply them together
+	pub(crate) fn broadcast_output_info(&self, output: &Synced<Output<G>>) {
+		let output_lock = output.lock().unwrap();
+		for client_info in &self.client_manager.clients {
+			let client_info_lock = client_info.lock().unwrap();
+			for output_interface in &client_info_lock.outputs {
+				if Arc::ptr_eq(&output_interface.get_synced::<Output<G>>(), output) {
+					send_output_info(output_interface, &*output_lock);
+				}
+			}
+		}
+	}
+
+	/// Update the outputs every surface has entered, which has to be done whenever outputs change
+	pub(crate) fn update_all_surface_outputs(&self) {
+		for surface in self.window_manager.manager_impl.surfaces_ascending() {
+			let surface_data = surface.get_synced::<SurfaceData<G>>();
+			let mut surface_data_lock = surface_data.lock().unwrap();
+			update_surface_outputs(surface, &mut surface_data_lock);
+		}
+	}
+}
+
+/// Send wl_surface enter and leave events so that the outputs a surface has entered are exactly the bound
+/// wl_outputs its geometry intersects. An output whose scale changed since the surface entered it is left and
+/// entered again, which is how clients learn that they should render their buffers at a different scale.
+pub(crate) fn update_surface_outputs<G: GraphicsBackend + 'static>(
+	surface: &wl_surface::WlSurface,
+	surface_data: &mut SurfaceData<G>,
+) {
+	let surface_geometry = surface_data.try_get_surface_geometry();
+	let client_info = Arc::clone(&surface_data.client_info);
+	let client_info_lock = client_info.lock().unwrap();
+
+	// Leave the outputs whose globals have been removed
+	let entered_outputs = &mut surface_data.entered_outputs;
+	entered_outputs.retain(|(entered, _scale)| {
+		let bound = client_info_lock.outputs.contains(entered);
+		if !bound && entered.as_ref().is_alive() {
+			surface.leave(entered);
+		}
+		bound
+	});
+
+	for output_interface in &client_info_lock.outputs {
+		let output = *output_interface.get_synced::<Output<G>>().lock().unwrap();
+		let intersects = surface_geometry
+			.map(|geometry| geometry.intersects(output.viewport))
+			.unwrap_or(false);
+		let entered = entered_outputs
+			.iter()
+			.position(|(entered, _scale)| entered == output_interface);
+		match (entered, intersects) {
+			(Some(i), true) => {
+				if entered_outputs[i].1 != output.scale {
+					surface.leave(output_interface);
+					surface.enter(output_interface);
+					entered_outputs[i].1 = output.scale;
+				}
+			}
+			(Some(i), false) => {
+				surface.leave(output_interface);
+				entered_outputs.remove(i);
+			}
+			(None, true) => {
+				surface.enter(output_interface);
+				entered_outputs.push((output_interface.clone(), output.scale));
+			}
+			(None, false) => {}
+		}
+	}
+}
+
+/// Send the current geometry and mode of an output to a bound wl_output, followed by a done event
+pub(crate) fn send_output_info<G: GraphicsBackend>(output_interface: &wl_output::WlOutput, output: &Output<G>) {
+	output_interface.geometry(
+		output.viewport.x,
+		output.viewport.y,
+		0,
+		0,
+		wl_output::Subpixel::HorizontalBgr,
+		String::from("<unknown>"),
+		String::from("<unknown>"),
+		output.transform,
+	);
+	// TODO: don't hardcode the refresh rate
+	let mode_size = output.mode_size();
+	output_interface.mode(
+		wl_output::Mode::Current | wl_output::Mode::Preferred,
+		mode_size.width as i32,
+		mode_size.height as i32,
+		75,
+	);
+	if output_interface.as_ref().version() >= 2 {
+		output_interface.scale(output.scale);
+	}
+	output_interface.done();
+}
+
+#[cfg(test)]
+mod tests {
+	use wayland_protocols::xdg_shell::server::{xdg_surface, xdg_toplevel};
+
+	use super::*;
+	use crate::{
+		compositor::xdg::{XdgSurfaceData, XdgSurfaceRole, XdgToplevelData},
+		test_util::{received_events, test_client, test_client_info, test_inner, test_surface, TestBackend},
+	};
+
+	#[test]
+	fn maximized_windows_are_resized_with_their_output() {
+		let mut display = Display::new();
+		let mut inner = test_inner(&mut display);
+		let (client, mut socket) = test_client(&mut display);
+		let client_info = test_client_info(&client);
+		let (surface, surface_data) = test_surface::<TestBackend>(&client, &client_info);
+		let xdg_surface = (*client.create_resource::<xdg_surface::XdgSurface>(1).unwrap()).clone();
+		let xdg_toplevel = (*client.create_resource::<xdg_toplevel::XdgToplevel>(1).unwrap()).clone();
+		xdg_toplevel
+			.as_ref()
+			.user_data()
+			.set_threadsafe(|| Arc::new(Mutex::new(XdgToplevelData::new())));
+		let mut xdg_surface_data = XdgSurfaceData::new();
+		xdg_surface_data.xdg_surface_role = Some(XdgSurfaceRole::XdgToplevel(xdg_toplevel.clone()));
+		xdg_surface
+			.as_ref()
+			.user_data()
+			.set_threadsafe(|| Arc::new(Mutex::new(xdg_surface_data)));
+		surface_data.lock().unwrap().role = Some(Role::XdgSurface(xdg_surface));
+
+		let first_output = Rect::new(0, 0, 100, 100);
+		inner
+			.window_manager
+			.manager_impl
+			.add_surface(surface, Some(first_output));
+		surface_data
+			.lock()
+			.unwrap()
+			.snap_window(SnapPosition::Maximized, first_output);
+		inner.output_globals[0].1.lock().unwrap().viewport = Rect::new(0, 0, 200, 150);
+		inner.resnap_all_windows();
+
+		display.flush_clients(&mut ());
+		let toplevel_id = xdg_toplevel.as_ref().id();
+		let (_, _, args) = received_events(&mut socket)
+			.into_iter()
+			.filter(|(object, opcode, _)| *object == toplevel_id && *opcode == 0)
+			.last()
+			.expect("The toplevel was never configured");
+		// The configure carries the width, the height and then the states as an array
+		assert_eq!(&args[..2], &[200, 150]);
+		assert_eq!(&args[3..], &[xdg_toplevel::State::Maximized as u32]);
+	}
 }
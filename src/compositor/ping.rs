@@ -0,0 +1,84 @@
+//! Clients are pinged through every xdg_wm_base they bind every few seconds. A client that doesn't answer a ping in
+//! time is marked unresponsive, and its surfaces are drawn dimmed until it answers again.
+
+use std::time::{Duration, Instant};
+
+use wayland_protocols::xdg_shell::server::xdg_wm_base;
+
+use crate::compositor::{get_input_serial, prelude::*, CompositorInner};
+
+/// How long to wait after a pong before pinging the client again
+const PING_INTERVAL: Duration = Duration::from_secs(5);
+/// How long a client has to answer a ping before it's considered unresponsive
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The ping state of a bound xdg_wm_base
+pub(crate) struct XdgWmBasePing {
+	xdg_wm_base: xdg_wm_base::XdgWmBase,
+	client_info: Synced<ClientInfo>,
+	/// The serial and send time of the ping that hasn't been answered yet
+	pending: Option<(u32, Instant)>,
+	/// When the last ping was answered, or when the xdg_wm_base was bound if no ping has been yet
+	last_pong: Instant,
+}
+
+impl XdgWmBasePing {
+	pub(crate) fn new(xdg_wm_base: xdg_wm_base::XdgWmBase, client_info: Synced<ClientInfo>) -> Self {
+		Self {
+			xdg_wm_base,
+			client_info,
+			pending: None,
+			last_pong: Instant::now(),
+		}
+	}
+}
+
+impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> CompositorInner<I, G> {
+	/// Ping every xdg_wm_base that is due for a ping, and mark the clients that didn't answer their last ping in
+	/// time as unresponsive. This is called once per frame.
+	pub(crate) fn ping_clients(&mut self) {
+		self.xdg_wm_base_pings
+			.retain(|ping| ping.xdg_wm_base.as_ref().is_alive());
+		for ping in &mut self.xdg_wm_base_pings {
+			match ping.pending {
+				Some((serial, sent)) => {
+					let mut client_info_lock = ping.client_info.lock().unwrap();
+					if sent.elapsed() >= PING_TIMEOUT && !client_info_lock.unresponsive {
+						log::warn!("Client didn't answer ping {} in time, marking it unresponsive", serial);
+						client_info_lock.unresponsive = true;
+					}
+				}
+				None if ping.last_pong.elapsed() >= PING_INTERVAL => {
+					let serial = get_input_serial();
+					ping.xdg_wm_base.ping(serial);
+					ping.pending = Some((serial, Instant::now()));
+				}
+				None => {}
+			}
+		}
+	}
+
+	/// Handle a pong from a client, which marks it responsive again if it answers the latest ping
+	pub(crate) fn handle_pong(&mut self, xdg_wm_base: &xdg_wm_base::XdgWmBase, serial: u32) {
+		let ping = match self
+			.xdg_wm_base_pings
+			.iter_mut()
+			.find(|ping| ping.xdg_wm_base == *xdg_wm_base)
+		{
+			Some(ping) => ping,
+			None => return,
+		};
+		match ping.pending {
+			Some((pending_serial, _sent)) if pending_serial == serial => {
+				ping.pending = None;
+				ping.last_pong = Instant::now();
+				let mut client_info_lock = ping.client_info.lock().unwrap();
+				if client_info_lock.unresponsive {
+					log::info!("Unresponsive client answered ping {}", serial);
+					client_info_lock.unresponsive = false;
+				}
+			}
+			_ => log::debug!("Ignoring pong with unexpected serial {}", serial),
+		}
+	}
+}
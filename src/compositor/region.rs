@@ -0,0 +1,57 @@
+use festus::geometry::*;
+
+use crate::geometry::rect_contains;
+
+/// The area described by a wl_region, built up by adding and subtracting rectangles. The operations are kept in
+/// the order the client sent them, so later operations take precedence over earlier ones where they overlap.
+#[derive(Debug, Clone, Default)]
+pub struct RegionData {
+	operations: Vec<RegionOperation>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum RegionOperation {
+	Add(Rect),
+	Subtract(Rect),
+}
+
+impl RegionData {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn add(&mut self, rect: Rect) {
+		self.operations.push(RegionOperation::Add(rect));
+	}
+
+	pub fn subtract(&mut self, rect: Rect) {
+		self.operations.push(RegionOperation::Subtract(rect));
+	}
+
+	/// Check whether a point is inside the region, using the last operation whose rectangle contains it
+	pub fn contains_point(&self, point: Point) -> bool {
+		self.operations
+			.iter()
+			.rev()
+			.find_map(|operation| match *operation {
+				RegionOperation::Add(rect) if rect.contains_point(point) => Some(true),
+				RegionOperation::Subtract(rect) if rect.contains_point(point) => Some(false),
+				_ => None,
+			})
+			.unwrap_or(false)
+	}
+
+	/// Check whether the region covers all of `rect`. This only recognizes a rectangle that was added in one piece
+	/// and not cut into afterwards, so a region pieced together from smaller rectangles isn't treated as covering
+	/// it, which is fine for deciding what can be skipped when drawing.
+	pub fn covers(&self, rect: Rect) -> bool {
+		for operation in self.operations.iter().rev() {
+			match *operation {
+				RegionOperation::Subtract(subtracted) if subtracted.intersects(rect) => return false,
+				RegionOperation::Add(added) if rect_contains(added, rect) => return true,
+				_ => {}
+			}
+		}
+		false
+	}
+}
@@ -2,17 +2,23 @@ use std::fmt;
 
 use wayland_protocols::xdg_shell::server::*;
 
-use crate::compositor::{prelude::*, xdg::XdgSurfaceData};
+use crate::compositor::{
+	prelude::*,
+	xdg::{XdgPopupData, XdgSurfaceData, XdgSurfaceRole, XdgToplevelData},
+};
 
 #[derive(Clone)]
 pub enum Role {
 	XdgSurface(xdg_surface::XdgSurface),
+	/// The surface is drawn as the pointer's cursor, which is given to it with wl_pointer.set_cursor
+	Cursor,
 }
 
 impl Role {
 	pub fn destroy(&mut self) {
 		match *self {
 			Role::XdgSurface(ref _xdg_surface) => {}
+			Role::Cursor => {}
 		}
 	}
 
@@ -23,23 +29,137 @@ impl Role {
 				let mut xdg_surface_data_lock = xdg_surface_data.lock().unwrap();
 				xdg_surface_data_lock.commit_pending_state();
 			}
+			Role::Cursor => {}
 		}
 	}
 
-	pub fn resize_window(&mut self, size: Size) {
+	/// Check that committing the pending state is allowed by the role, sending the client a protocol error and
+	/// returning false if it isn't
+	pub fn validate_commit(&self, attaches_buffer: bool) -> bool {
+		match self {
+			Role::XdgSurface(ref xdg_surface) => {
+				let xdg_surface_data = xdg_surface.get_synced::<XdgSurfaceData>();
+				let xdg_surface_data_lock = xdg_surface_data.lock().unwrap();
+				if attaches_buffer && !xdg_surface_data_lock.configured {
+					xdg_surface.as_ref().post_error(
+						xdg_surface::Error::UnconfiguredBuffer as u32,
+						String::from("Buffer committed to an xdg_surface before acknowledging a configure"),
+					);
+					return false;
+				}
+				true
+			}
+			Role::Cursor => true,
+		}
+	}
+
+	/// Ask the client to resize the window, returning the size it was asked for, which can differ from `size` if
+	/// the window limits its size
+	pub fn resize_window(&mut self, size: Size) -> Size {
 		match self {
 			Role::XdgSurface(ref xdg_surface) => {
 				let xdg_surface_data = xdg_surface.get_synced::<XdgSurfaceData>();
 				let mut xdg_surface_data_lock = xdg_surface_data.lock().unwrap();
-				xdg_surface_data_lock.resize_window(size);
+				let size = xdg_surface_data_lock.resize_window(size);
 				xdg_surface.configure(42);
+				size
 			}
+			Role::Cursor => size,
 		}
 	}
 
 	pub fn set_surface_size(&mut self, _size: Size) {
 		match self {
 			Role::XdgSurface(ref _xdg_surface) => log::warn!("Set surface size not fully implemented"),
+			Role::Cursor => {}
+		}
+	}
+
+	/// Whether this role makes the surface a window that should be managed once it's mapped
+	pub fn is_window(&self) -> bool {
+		matches!(self.xdg_surface_role(), Some(XdgSurfaceRole::XdgToplevel(_)))
+	}
+
+	/// Get the xdg_surface of the popup's parent and the popup's geometry relative to the parent's window geometry,
+	/// if this role makes the surface a popup
+	pub fn popup_placement(&self) -> Option<(xdg_surface::XdgSurface, Rect)> {
+		match self.xdg_surface_role() {
+			Some(XdgSurfaceRole::XdgPopup(xdg_popup)) => {
+				let xdg_popup_data = xdg_popup.get_synced::<XdgPopupData>();
+				let xdg_popup_data_lock = xdg_popup_data.lock().unwrap();
+				Some((xdg_popup_data_lock.parent.clone(), xdg_popup_data_lock.geometry))
+			}
+			_ => None,
+		}
+	}
+
+	/// Get the xdg_toplevel, if this role makes the surface a toplevel
+	pub fn xdg_toplevel(&self) -> Option<xdg_toplevel::XdgToplevel> {
+		match self.xdg_surface_role() {
+			Some(XdgSurfaceRole::XdgToplevel(xdg_toplevel)) => Some(xdg_toplevel),
+			_ => None,
+		}
+	}
+
+	/// Get the toplevel that the client set as the parent of this toplevel
+	pub fn toplevel_parent(&self) -> Option<xdg_toplevel::XdgToplevel> {
+		self.xdg_toplevel().and_then(|xdg_toplevel| {
+			xdg_toplevel
+				.get_synced::<XdgToplevelData>()
+				.lock()
+				.unwrap()
+				.parent
+				.clone()
+		})
+	}
+
+	/// Whether this is the role given to a surface by `xdg_surface`
+	pub fn is_xdg_surface(&self, xdg_surface: &xdg_surface::XdgSurface) -> bool {
+		match self {
+			Role::XdgSurface(ref role_xdg_surface) => role_xdg_surface == xdg_surface,
+			Role::Cursor => false,
+		}
+	}
+
+	/// Ask the client to close the window, if this role makes the surface a window
+	pub fn close(&self) {
+		if let Some(xdg_surface_role) = self.xdg_surface_role() {
+			xdg_surface_role.close();
+		}
+	}
+
+	/// Set whether the window is maximized. The client is told along with the next resize.
+	pub fn set_maximized(&mut self, maximized: bool) {
+		if let Some(XdgSurfaceRole::XdgToplevel(xdg_toplevel)) = self.xdg_surface_role() {
+			xdg_toplevel.get_synced::<XdgToplevelData>().lock().unwrap().maximized = maximized;
+		}
+	}
+
+	/// Set whether the window is fullscreen. The client is told along with the next resize.
+	pub fn set_fullscreen(&mut self, fullscreen: bool) {
+		if let Some(XdgSurfaceRole::XdgToplevel(xdg_toplevel)) = self.xdg_surface_role() {
+			xdg_toplevel.get_synced::<XdgToplevelData>().lock().unwrap().fullscreen = fullscreen;
+		}
+	}
+
+	pub fn title(&self) -> Option<String> {
+		self.xdg_surface_role()
+			.and_then(|xdg_surface_role| xdg_surface_role.title())
+	}
+
+	pub fn app_id(&self) -> Option<String> {
+		self.xdg_surface_role()
+			.and_then(|xdg_surface_role| xdg_surface_role.app_id())
+	}
+
+	fn xdg_surface_role(&self) -> Option<XdgSurfaceRole> {
+		match self {
+			Role::XdgSurface(ref xdg_surface) => {
+				let xdg_surface_data = xdg_surface.get_synced::<XdgSurfaceData>();
+				let xdg_surface_data_lock = xdg_surface_data.lock().unwrap();
+				xdg_surface_data_lock.xdg_surface_role.clone()
+			}
+			Role::Cursor => None,
 		}
 	}
 
@@ -50,6 +170,7 @@ impl Role {
 				let xdg_surface_data_lock = xdg_surface_data.lock().unwrap();
 				xdg_surface_data_lock.solid_window_geometry
 			}
+			Role::Cursor => None,
 		}
 	}
 }
@@ -62,6 +183,7 @@ impl fmt::Debug for Role {
 				let xdg_surface_data_lock = xdg_surface_data.lock().unwrap();
 				fmt::Debug::fmt(&*xdg_surface_data_lock, f)
 			}
+			Role::Cursor => f.write_str("Cursor"),
 		}
 	}
 }
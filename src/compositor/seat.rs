@@ -1,12 +1,24 @@
 use std::sync::Arc;
 
+use festus::geometry::Point;
 use wayland_server::{protocol::*, Filter, Main};
 
 use crate::{
 	backend::{GraphicsBackend, InputBackend},
-	compositor::Compositor,
+	compositor::{role::Role, surface::SurfaceData, Compositor, CompositorInner, CustomCursor, UserDataAccess},
 };
 
+/// The name advertised for the seat. It is stable so clients can use it to identify the seat across sessions.
+pub const SEAT_NAME: &str = "seat0";
+
+/// End the group of events just sent to a pointer, so the client handles them as one logical event. Frames were
+/// added in version 5 of wl_pointer, so older pointers aren't sent them.
+pub(crate) fn send_pointer_frame(pointer: &wl_pointer::WlPointer) {
+	if pointer.as_ref().version() >= 5 {
+		pointer.frame();
+	}
+}
+
 impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 	pub fn setup_seat_global(&mut self) {
 		let inner = Arc::clone(&self.inner);
@@ -15,9 +27,11 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 				let inner = Arc::clone(&inner);
 				let seat = &*main;
 				if version >= 2 {
-					seat.name(String::from("WallySeat"));
+					seat.name(String::from(SEAT_NAME));
 				}
-				seat.capabilities(wl_seat::Capability::Pointer | wl_seat::Capability::Keyboard);
+				seat.capabilities(
+					wl_seat::Capability::Pointer | wl_seat::Capability::Keyboard | wl_seat::Capability::Touch,
+				);
 				main.quick_assign(move |_main, request: wl_seat::Request, _dispatch_data| {
 					let inner = Arc::clone(&inner);
 					let mut inner_lock = inner.lock().unwrap();
@@ -25,6 +39,7 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 						wl_seat::Request::GetPointer { id } => {
 							let pointer = (*id).clone();
 							let resource = pointer.as_ref().clone();
+							let pointer_inner = Arc::clone(&inner);
 							inner_lock
 								.client_manager
 								.get_client_info(resource.client().unwrap())
@@ -32,8 +47,19 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 								.unwrap()
 								.pointers
 								.push(pointer);
-							id.quick_assign(|_main, request, _dispatch_data| match request {
-								wl_pointer::Request::SetCursor { .. } => {}
+							id.quick_assign(move |main, request, _dispatch_data| match request {
+								wl_pointer::Request::SetCursor {
+									serial,
+									surface,
+									hotspot_x,
+									hotspot_y,
+								} => {
+									let hotspot = Point::new(hotspot_x, hotspot_y);
+									pointer_inner
+										.lock()
+										.unwrap()
+										.set_cursor(&main, serial, surface, hotspot);
+								}
 								wl_pointer::Request::Release => {}
 								_ => {
 									log::warn!("Got unknown request for wl_pointer");
@@ -50,6 +76,16 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 								keyboard_state_lock.fd,
 								keyboard_state_lock.keymap_string.as_bytes().len() as u32,
 							);
+							// A rate of 0 tells clients not to repeat keys themselves, for when the compositor repeats
+							// them
+							if keyboard.as_ref().version() >= 4 {
+								let repeat_rate = if inner_lock.config.server_side_repeat {
+									0
+								} else {
+									keyboard_state_lock.repeat_rate
+								};
+								keyboard.repeat_info(repeat_rate, keyboard_state_lock.repeat_delay);
+							}
 							drop(keyboard_state_lock);
 							resource.user_data().set(move || keyboard_state);
 							inner_lock
@@ -70,7 +106,27 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 								}
 							})
 						}
-						wl_seat::Request::GetTouch { .. } => {}
+						wl_seat::Request::GetTouch { id } => {
+							let touch = (*id).clone();
+							inner_lock
+								.client_manager
+								.get_client_info(touch.as_ref().client().unwrap())
+								.lock()
+								.unwrap()
+								.touches
+								.push(touch);
+							let touch_inner = Arc::clone(&inner);
+							id.quick_assign(move |main, request, _dispatch_data| match request {
+								wl_touch::Request::Release => {
+									let mut inner_lock = touch_inner.lock().unwrap();
+									let client_info = inner_lock
+										.client_manager
+										.get_client_info(main.as_ref().client().unwrap());
+									client_info.lock().unwrap().touches.retain(|touch| *touch != *main);
+								}
+								_ => log::warn!("Got unknown request for wl_touch"),
+							})
+						}
 						wl_seat::Request::Release => {}
 						_ => {
 							log::warn!("Got unknown request for wl_seat");
@@ -82,3 +138,43 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 		self.display.create_global::<wl_seat::WlSeat, _>(6, seat_filter);
 	}
 }
+
+impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> CompositorInner<I, G> {
+	/// Draw a client's surface as the cursor, with its hotspot at the pointer position, until the pointer leaves the
+	/// client's surface. A null surface goes back to the default cursor. The request is ignored unless its serial is
+	/// the one of the enter event the client was sent for the surface with pointer focus.
+	pub(crate) fn set_cursor(
+		&mut self,
+		pointer: &wl_pointer::WlPointer,
+		serial: u32,
+		surface: Option<wl_surface::WlSurface>,
+		hotspot: Point,
+	) {
+		let focused_by_client = self
+			.pointer_focus
+			.as_ref()
+			.map(|focus| focus.as_ref().same_client_as(pointer.as_ref()))
+			.unwrap_or(false);
+		if !focused_by_client || self.pointer_enter_serial != Some(serial) {
+			log::debug!("Ignoring wl_pointer.set_cursor with stale serial {}", serial);
+			return;
+		}
+		if let Some(ref surface) = surface {
+			// A surface can only ever have one role, and giving it the cursor role again is fine
+			let surface_data = surface.get_synced::<SurfaceData<G>>();
+			let mut surface_data_lock = surface_data.lock().unwrap();
+			match surface_data_lock.role {
+				None => surface_data_lock.role = Some(Role::Cursor),
+				Some(Role::Cursor) => {}
+				Some(_) => {
+					pointer.as_ref().post_error(
+						wl_pointer::Error::Role as u32,
+						String::from("Cursor surface already has another role"),
+					);
+					return;
+				}
+			}
+		}
+		self.pointer.lock().unwrap().custom_cursor = surface.map(|surface| CustomCursor { surface, hotspot });
+	}
+}
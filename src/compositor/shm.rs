@@ -1,11 +1,17 @@
 use std::convert::TryFrom;
+use std::os::unix::io::RawFd;
 use std::sync::{Arc, Mutex};
 
+use nix::{
+	fcntl::{self, FcntlArg, SealFlag},
+	sys::stat,
+	unistd,
+};
 use wayland_server::{protocol::*, Filter, Global, Main};
 
 use crate::{
-	backend::{GraphicsBackend, InputBackend},
-	compositor::Compositor,
+	backend::{shm_format_bytes_per_pixel, GraphicsBackend, InputBackend, SUPPORTED_SHM_FORMATS},
+	compositor::{buffer::BufferData, Compositor, Synced},
 };
 
 /* #[derive(Debug)]
@@ -37,31 +43,175 @@ impl<G: GraphicsBackend> ShmBuffer<G> {
 	} */
 } */
 
+/// The largest width or height a shm buffer can have, which every Vulkan implementation supports for textures
+const MAX_BUFFER_DIMENSION: i32 = 16384;
+
+/// The parameters of a shm buffer once they've been checked, in the types the graphics backend takes
+struct BufferParams {
+	offset: usize,
+	width: u32,
+	height: u32,
+	stride: u32,
+}
+
+/// Check that a buffer with the given parameters fits in a pool of `pool_size` bytes and can be turned into a
+/// texture, returning the protocol error to send if it can't
+fn check_buffer_params(
+	pool_size: usize,
+	offset: i32,
+	width: i32,
+	height: i32,
+	stride: i32,
+	format: wl_shm::Format,
+) -> Result<BufferParams, (wl_shm::Error, String)> {
+	let bytes_per_pixel = match shm_format_bytes_per_pixel(format) {
+		Some(bytes_per_pixel) => bytes_per_pixel,
+		None => return Err((wl_shm::Error::InvalidFormat, format!("Unsupported format {:?}", format))),
+	};
+	if width <= 0 || height <= 0 || width > MAX_BUFFER_DIMENSION || height > MAX_BUFFER_DIMENSION {
+		return Err((
+			wl_shm::Error::InvalidStride,
+			format!("Invalid buffer size {}x{}", width, height),
+		));
+	}
+	// Rows are copied to textures in whole pixels, so the stride can't end partway through one
+	if offset < 0
+		|| stride < 0
+		|| i64::from(stride) < i64::from(width) * i64::from(bytes_per_pixel)
+		|| stride as u32 % bytes_per_pixel != 0
+	{
+		return Err((
+			wl_shm::Error::InvalidStride,
+			format!("Invalid offset {} or stride {} for width {}", offset, stride, width),
+		));
+	}
+	let end = i64::from(offset) + i64::from(stride) * i64::from(height);
+	if end > pool_size as i64 {
+		return Err((
+			wl_shm::Error::InvalidStride,
+			format!("Buffer ends at byte {} but the pool is only {} bytes", end, pool_size),
+		));
+	}
+	// None of the values are negative at this point
+	Ok(BufferParams {
+		offset: offset as usize,
+		width: width as u32,
+		height: height as u32,
+		stride: stride as u32,
+	})
+}
+
+/// Check that the file behind a shm pool is at least `size` bytes long and, if `require_sealed` is set, that it has
+/// been sealed against shrinking. The compositor reads from its mapping of the pool at any time, so a file that is
+/// truncated after the fact would cause a SIGBUS. Unsealed files are only warned about otherwise.
+fn check_pool_fd(fd: RawFd, size: usize, require_sealed: bool) -> Result<(), String> {
+	let file_stat = stat::fstat(fd).map_err(|e| format!("failed to stat the pool fd: {}", e))?;
+	if (file_stat.st_size as usize) < size {
+		return Err(format!(
+			"the pool fd is {} bytes long but the pool is {} bytes",
+			file_stat.st_size, size
+		));
+	}
+	let sealed = fcntl::fcntl(fd, FcntlArg::F_GET_SEALS)
+		.map(|seals| SealFlag::from_bits_truncate(seals).contains(SealFlag::F_SEAL_SHRINK))
+		.unwrap_or(false);
+	if !sealed {
+		if require_sealed {
+			return Err(String::from("the pool fd isn't sealed against shrinking"));
+		}
+		log::warn!("Accepting a shm pool that isn't sealed against shrinking, which the client could crash wally with");
+	}
+	Ok(())
+}
+
 impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 	pub(crate) fn setup_shm_global(&mut self) -> Global<wl_shm::WlShm> {
+		let inner = Arc::clone(&self.inner);
 		let graphics_backend_state = Arc::clone(&self.graphics_backend_state);
 		let shm_filter = Filter::new(
 			move |(main, _num): (Main<wl_shm::WlShm>, u32), _filter, _dispatch_data| {
+				let inner = Arc::clone(&inner);
 				let graphics_backend_state = Arc::clone(&graphics_backend_state);
 				let shm_interface = &*main;
-				shm_interface.format(wl_shm::Format::Argb8888);
-				shm_interface.format(wl_shm::Format::Xrgb8888);
-				main.quick_assign(move |_main, request, _dispatch_data| {
+				for &(format, _bytes_per_pixel) in SUPPORTED_SHM_FORMATS {
+					shm_interface.format(format);
+				}
+				main.quick_assign(move |main, request, _dispatch_data| {
 					let graphics_backend_state = Arc::clone(&graphics_backend_state);
 					match request {
 						wl_shm::Request::CreatePool { id, fd, size } => {
+							let mut inner_lock = inner.lock().unwrap();
+							let limits = inner_lock.config.client_limits.clone();
+							let require_sealed = inner_lock.config.require_sealed_shm;
+							let client_info = inner_lock
+								.client_manager
+								.get_client_info(main.as_ref().client().unwrap());
+							drop(inner_lock);
+							let size = match usize::try_from(size) {
+								Ok(size) if size > 0 => size,
+								_ => {
+									main.as_ref().post_error(
+										wl_shm::Error::InvalidStride as u32,
+										format!("Invalid pool size {}", size),
+									);
+									let _ = unistd::close(fd);
+									return;
+								}
+							};
+							// The fd is owned by the pool once it's created, and has to be closed here until then
+							if let Err(e) = check_pool_fd(fd, size, require_sealed) {
+								log::warn!("Rejecting shm pool: {}", e);
+								main.as_ref().post_error(wl_shm::Error::InvalidFd as u32, e);
+								let _ = unistd::close(fd);
+								return;
+							}
+							{
+								let mut client_info_lock = client_info.lock().unwrap();
+								if client_info_lock.usage.shm_pools >= limits.max_shm_pools {
+									client_info_lock.disconnect_over_limit(main.as_ref(), "shm pools");
+									let _ = unistd::close(fd);
+									return;
+								}
+								let shm_bytes = match client_info_lock.usage.shm_bytes.checked_add(size) {
+									Some(shm_bytes) if shm_bytes <= limits.max_shm_bytes => shm_bytes,
+									_ => {
+										client_info_lock.disconnect_over_limit(main.as_ref(), "mapped shm bytes");
+										let _ = unistd::close(fd);
+										return;
+									}
+								};
+								client_info_lock.usage.shm_pools += 1;
+								client_info_lock.usage.shm_bytes = shm_bytes;
+							}
 							let mut graphics_backend_state_lock = graphics_backend_state.lock().unwrap();
-							let shm_pool = graphics_backend_state_lock
-								.renderer
-								.create_shm_pool(fd, size as usize)
-								.map_err(|e| log::error!("Failed to create shm pool: {}", e))
-								.unwrap();
+							let result = graphics_backend_state_lock.renderer.create_shm_pool(fd, size);
 							drop(graphics_backend_state_lock);
+							// The graphics backend closes the fd if the pool can't be created
+							let shm_pool = match result {
+								Ok(shm_pool) => shm_pool,
+								Err(e) => {
+									log::error!("Failed to create shm pool: {}", e);
+									let mut client_info_lock = client_info.lock().unwrap();
+									client_info_lock.usage.shm_pools -= 1;
+									client_info_lock.usage.shm_bytes -= size;
+									drop(client_info_lock);
+									main.as_ref().post_error(
+										wl_shm::Error::InvalidFd as u32,
+										format!("Failed to map the pool: {}", e),
+									);
+									return;
+								}
+							};
 							let shm_pool = Arc::new(Mutex::new(shm_pool));
+							let pool_size: Synced<usize> = Arc::new(Mutex::new(size));
+							let pool_client_info = Arc::clone(&client_info);
+							let pool_size_destructor = Arc::clone(&pool_size);
+							let client_info_destructor = Arc::clone(&client_info);
 							id.quick_assign(
-								move |_main: Main<wl_shm_pool::WlShmPool>, request: wl_shm_pool::Request, _| {
+								move |main: Main<wl_shm_pool::WlShmPool>, request: wl_shm_pool::Request, _| {
 									let graphics_backend_state = Arc::clone(&graphics_backend_state);
 									let shm_pool = Arc::clone(&shm_pool);
+									let client_info = Arc::clone(&pool_client_info);
 									match request {
 										wl_shm_pool::Request::CreateBuffer {
 											id,
@@ -71,27 +221,54 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 											stride,
 											format,
 										} => {
+											let buffer_params = check_buffer_params(
+												*pool_size.lock().unwrap(),
+												offset,
+												width,
+												height,
+												stride,
+												format,
+											);
+											let buffer_params = match buffer_params {
+												Ok(buffer_params) => buffer_params,
+												Err((error, message)) => {
+													main.as_ref().post_error(error as u32, message);
+													return;
+												}
+											};
+											{
+												let mut client_info_lock = client_info.lock().unwrap();
+												if client_info_lock.usage.buffers >= limits.max_buffers {
+													client_info_lock.disconnect_over_limit(main.as_ref(), "buffers");
+													return;
+												}
+												client_info_lock.usage.buffers += 1;
+											}
 											// TODO this doesn't need to be in a Mutex I'm pretty sure because it can't be changed
 											let mut graphics_backend_state_lock =
 												graphics_backend_state.lock().unwrap();
 											let mut shm_pool_lock = shm_pool.lock().unwrap();
-											let offset = usize::try_from(offset).unwrap();
-											let width = u32::try_from(width).unwrap();
-											let height = u32::try_from(height).unwrap();
-											let stride = u32::try_from(stride).unwrap();
-											let shm_buffer: G::ShmBuffer = graphics_backend_state_lock
-												.renderer
-												.create_shm_buffer(
-													&mut *shm_pool_lock,
-													offset,
-													width,
-													height,
-													stride,
-													format,
-												)
-												.unwrap();
-											let shm_buffer = Arc::new(Mutex::new(shm_buffer));
-											id.as_ref().user_data().set_threadsafe(|| Arc::clone(&shm_buffer));
+											let result = graphics_backend_state_lock.renderer.create_shm_buffer(
+												&mut *shm_pool_lock,
+												buffer_params.offset,
+												buffer_params.width,
+												buffer_params.height,
+												buffer_params.stride,
+												format,
+											);
+											let shm_buffer: G::ShmBuffer = match result {
+												Ok(shm_buffer) => shm_buffer,
+												Err(e) => {
+													client_info.lock().unwrap().usage.buffers -= 1;
+													main.as_ref().post_error(
+														wl_shm::Error::InvalidFd as u32,
+														format!("Failed to create the buffer: {}", e),
+													);
+													return;
+												}
+											};
+											let buffer_data = Arc::new(Mutex::new(BufferData::new(shm_buffer)));
+											id.as_ref().user_data().set_threadsafe(|| Arc::clone(&buffer_data));
 											id.quick_assign(
 												|_main: Main<wl_buffer::WlBuffer>,
 												 request: wl_buffer::Request,
@@ -104,22 +281,78 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 													}
 												},
 											);
+											id.assign_destructor(Filter::new(
+												move |_buffer: wl_buffer::WlBuffer, _filter, _dispatch_data| {
+													client_info.lock().unwrap().usage.buffers -= 1;
+												},
+											));
 										}
 										wl_shm_pool::Request::Resize { size } => {
+											let size = match usize::try_from(size) {
+												Ok(size) => size,
+												Err(_) => {
+													main.as_ref().post_error(
+														wl_shm::Error::InvalidStride as u32,
+														format!("Invalid pool size {}", size),
+													);
+													return;
+												}
+											};
+											let mut pool_size_lock = pool_size.lock().unwrap();
+											if size < *pool_size_lock {
+												main.as_ref().post_error(
+													wl_shm::Error::InvalidStride as u32,
+													String::from("shm pools can't be shrunk"),
+												);
+												return;
+											}
+											// Pools can grow, so the file has to be checked again before mapping more of it
+											if let Err(e) = check_pool_fd(fd, size, require_sealed) {
+												main.as_ref().post_error(wl_shm::Error::InvalidFd as u32, e);
+												return;
+											}
+											{
+												let mut client_info_lock = client_info.lock().unwrap();
+												// The pool's current size is part of the total, so this can't underflow
+												let shm_bytes = (client_info_lock.usage.shm_bytes - *pool_size_lock)
+													.checked_add(size)
+													.filter(|&shm_bytes| shm_bytes <= limits.max_shm_bytes);
+												match shm_bytes {
+													Some(shm_bytes) => client_info_lock.usage.shm_bytes = shm_bytes,
+													None => {
+														client_info_lock
+															.disconnect_over_limit(main.as_ref(), "mapped shm bytes");
+														return;
+													}
+												}
+											}
+											*pool_size_lock = size;
 											let mut graphics_backend_state_lock =
 												graphics_backend_state.lock().unwrap();
 											let mut shm_pool_lock = shm_pool.lock().unwrap();
-											graphics_backend_state_lock
+											let result = graphics_backend_state_lock
 												.renderer
-												.resize_shm_pool(&mut *shm_pool_lock, size as usize)
-												.unwrap();
+												.resize_shm_pool(&mut *shm_pool_lock, size);
+											if let Err(e) = result {
+												main.as_ref().post_error(
+													wl_shm::Error::InvalidFd as u32,
+													format!("Failed to map the resized pool: {}", e),
+												);
+											}
 										}
 										_ => {
 											log::warn!("Got unknown request for wl_shm_pool");
 										}
 									}
 								},
-							)
+							);
+							id.assign_destructor(Filter::new(
+								move |_shm_pool: wl_shm_pool::WlShmPool, _filter, _dispatch_data| {
+									let mut client_info_lock = client_info_destructor.lock().unwrap();
+									client_info_lock.usage.shm_pools -= 1;
+									client_info_lock.usage.shm_bytes -= *pool_size_destructor.lock().unwrap();
+								},
+							));
 						}
 						_ => {
 							log::warn!("Got unknown request for wl_shm");
@@ -133,3 +366,20 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 		shm_global
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use std::os::unix::io::AsRawFd;
+
+	use super::*;
+
+	#[test]
+	fn unsealed_pools_are_only_rejected_when_sealing_is_required() {
+		let file = tempfile::tempfile().unwrap();
+		file.set_len(64).unwrap();
+		assert!(check_pool_fd(file.as_raw_fd(), 64, false).is_ok());
+		assert!(check_pool_fd(file.as_raw_fd(), 64, true).is_err());
+		// A pool can't be bigger than its file, sealed or not
+		assert!(check_pool_fd(file.as_raw_fd(), 128, false).is_err());
+	}
+}
@@ -1,8 +1,32 @@
-use crate::{backend::ShmBuffer, compositor::prelude::*, renderer::SurfaceRendererData};
+use crate::{
+	backend::ShmBuffer,
+	behavior::SnapPosition,
+	compositor::{
+		buffer::{self, BufferData},
+		prelude::*,
+		region::RegionData,
+		window::WindowId,
+	},
+	geometry::{rect_intersection, PointExt},
+	renderer::SurfaceRendererData,
+};
+
+/// The most damage rectangles a surface keeps. Damage is only drained when a newly committed buffer is drawn, so
+/// beyond this it's replaced with damage to the whole buffer instead of growing forever.
+const MAX_DAMAGE_RECTS: usize = 64;
 
 pub struct PendingState {
 	pub attached_buffer: Option<Option<(wl_buffer::WlBuffer, Point)>>,
-	pub input_region: Option<Rect>,
+	/// A copy of the region set with wl_surface.set_input_region, or `Some(None)` if it was reset to be infinite
+	pub input_region: Option<Option<RegionData>>,
+	/// A copy of the region set with wl_surface.set_opaque_region, or `Some(None)` if it was reset to be empty
+	pub opaque_region: Option<Option<RegionData>>,
+	/// Damage requested with wl_surface.damage since the last commit, in surface local coordinates
+	pub surface_damage: Vec<Rect>,
+	/// Damage requested with wl_surface.damage_buffer since the last commit, in buffer coordinates
+	pub buffer_damage: Vec<Rect>,
+	pub buffer_scale: Option<i32>,
+	pub buffer_transform: Option<wl_output::Transform>,
 }
 
 impl PendingState {
@@ -10,7 +34,79 @@ impl PendingState {
 		Self {
 			attached_buffer: None,
 			input_region: None,
+			opaque_region: None,
+			surface_damage: Vec::new(),
+			buffer_damage: Vec::new(),
+			buffer_scale: None,
+			buffer_transform: None,
+		}
+	}
+}
+
+/// Clip a rectangle to the area from the origin to `size`, returning `None` if nothing is left
+fn clip_rect(rect: Rect, size: Size) -> Option<Rect> {
+	rect_intersection(rect, Rect::new(0, 0, size.width, size.height))
+}
+
+/// Convert a damage rectangle from surface local coordinates to the coordinates of a buffer of the given size that
+/// was rendered at `scale` with `transform` applied to its contents
+fn surface_damage_to_buffer(
+	rect: Rect,
+	scale: i32,
+	transform: wl_output::Transform,
+	buffer_size: Size,
+) -> Option<Rect> {
+	use wl_output::Transform;
+	let rotated = match transform {
+		Transform::_90 | Transform::_270 | Transform::Flipped90 | Transform::Flipped270 => true,
+		_ => false,
+	};
+	// The size of the buffer as it is displayed, after the transform and before scaling down to surface size
+	let transformed_size = if rotated {
+		Size::new(buffer_size.height, buffer_size.width)
+	} else {
+		buffer_size
+	};
+	let scale = i64::from(scale.max(1));
+	let scaled = Rect::new(
+		(i64::from(rect.x) * scale)
+			.max(i64::from(i32::MIN))
+			.min(i64::from(i32::MAX)) as i32,
+		(i64::from(rect.y) * scale)
+			.max(i64::from(i32::MIN))
+			.min(i64::from(i32::MAX)) as i32,
+		(i64::from(rect.width) * scale).min(i64::from(u32::MAX)) as u32,
+		(i64::from(rect.height) * scale).min(i64::from(u32::MAX)) as u32,
+	);
+	let r = clip_rect(scaled, transformed_size)?;
+	let (w, h) = (transformed_size.width as i32, transformed_size.height as i32);
+	let (rw, rh) = (r.width as i32, r.height as i32);
+	// Undo the transform the client applied, which maps the transformed rectangle back onto the buffer
+	let (x, y) = match transform {
+		Transform::Normal => (r.x, r.y),
+		Transform::_90 => (r.y, w - r.x - rw),
+		Transform::_180 => (w - r.x - rw, h - r.y - rh),
+		Transform::_270 => (h - r.y - rh, r.x),
+		Transform::Flipped => (w - r.x - rw, r.y),
+		Transform::Flipped90 => (r.y, r.x),
+		Transform::Flipped180 => (r.x, h - r.y - rh),
+		Transform::Flipped270 => (h - r.y - rh, w - r.x - rw),
+		_ => (r.x, r.y),
+	};
+	if rotated {
+		Some(Rect::new(x, y, r.height, r.width))
+	} else {
+		Some(Rect::new(x, y, r.width, r.height))
+	}
+}
+
+/// Clamp the window geometry a client set to its buffer, falling back to the whole buffer if they don't overlap
+fn clamp_window_geometry(geometry: Rect, buffer_size: Option<Size>) -> Rect {
+	match buffer_size {
+		Some(buffer_size) => {
+			clip_rect(geometry, buffer_size).unwrap_or_else(|| Rect::new(0, 0, buffer_size.width, buffer_size.height))
 		}
+		None => geometry,
 	}
 }
 
@@ -55,7 +151,18 @@ pub struct SurfaceData<G: GraphicsBackend> {
 	pub committed_buffer: Option<(wl_buffer::WlBuffer, Point)>,
 	/// This field is updated whenever a new buffer is committed to avoid re-locking the ShmBuffer mutex
 	pub buffer_size: Option<Size>,
-	pub input_region: Option<Rect>,
+	/// The damage committed since the surface's texture was last uploaded, in buffer coordinates
+	pub damage: Vec<Rect>,
+	/// The scale the client renders its buffers at
+	pub buffer_scale: i32,
+	/// The transform the client has applied to its buffers' contents
+	pub buffer_transform: wl_output::Transform,
+	/// The part of the surface that accepts pointer input, in surface local coordinates. None means the whole
+	/// surface accepts input.
+	pub input_region: Option<RegionData>,
+	/// The part of the surface the client guarantees is fully opaque, in surface local coordinates. None means no
+	/// part of it is.
+	pub opaque_region: Option<RegionData>,
 	pub callback: Option<wl_callback::WlCallback>,
 	pub role: Option<Role>,
 	/// The data that is necessary for the specific graphics backend to render this surface
@@ -66,6 +173,28 @@ pub struct SurfaceData<G: GraphicsBackend> {
 	/// The current size of this surface as dictated by the window manager. None means the surface
 	/// has no known size and, as such, will not be displayed.
 	size: Option<Size>,
+	/// The outputs this surface has been sent an enter event for, along with the scale each output had then
+	pub entered_outputs: Vec<(wl_output::WlOutput, i32)>,
+	/// Where the window is snapped to, if it is snapped
+	pub snap: Option<Snap>,
+	/// The id the surface is known by while it's a window, which stays the same for as long as the surface exists
+	pub window_id: WindowId,
+}
+
+/// The snapping state of a window, including the geometry to restore when it's unsnapped
+#[derive(Debug, Clone, Copy)]
+pub struct Snap {
+	pub position: SnapPosition,
+	restore_position: Option<Point>,
+	restore_size: Option<Size>,
+	/// Where a fullscreen window was snapped before it became fullscreen, which it returns to afterwards
+	restore_snap: Option<SnapPosition>,
+}
+
+/// Check whether two handles refer to the same surface. Handles of destroyed surfaces never compare equal, so this
+/// compares the surfaces' data instead, which they keep until every handle is dropped.
+pub fn same_surface<G: GraphicsBackend + 'static>(a: &wl_surface::WlSurface, b: &wl_surface::WlSurface) -> bool {
+	Arc::ptr_eq(&a.get_synced::<SurfaceData<G>>(), &b.get_synced::<SurfaceData<G>>())
 }
 
 impl<G: GraphicsBackend + 'static> SurfaceData<G> {
@@ -75,22 +204,137 @@ impl<G: GraphicsBackend + 'static> SurfaceData<G> {
 			pending_state: PendingState::new(),
 			committed_buffer: None,
 			buffer_size: None,
+			damage: Vec::new(),
+			buffer_scale: 1,
+			buffer_transform: wl_output::Transform::Normal,
 			input_region: None,
+			opaque_region: None,
 			callback: None,
 			role: None,
 			renderer_data: Some(renderer_data),
 			position: None,
 			size: None,
+			entered_outputs: Vec::new(),
+			snap: None,
+			window_id: WindowId::next(),
+		}
+	}
+
+	/// Move and resize the window to cover the given part of `work_area`. Snapping a window to the position it's
+	/// already snapped to unsnaps it instead.
+	pub fn snap_window(&mut self, position: SnapPosition, work_area: Rect) {
+		let snap = match self.snap {
+			Some(snap) if snap.position == position => {
+				self.unsnap_window();
+				return;
+			}
+			// Moving between snap positions keeps the geometry from before the window was first snapped
+			Some(snap) => Snap {
+				position,
+				restore_snap: None,
+				..snap
+			},
+			None => self.new_snap(position),
+		};
+		self.snap = Some(snap);
+		self.resnap_window(work_area);
+	}
+
+	/// Move and resize the window to cover all of `output_area`. A window that was snapped before returns to where
+	/// it was snapped once it leaves fullscreen.
+	pub fn fullscreen_window(&mut self, output_area: Rect) {
+		let snap = match self.snap {
+			Some(snap) if snap.position == SnapPosition::Fullscreen => snap,
+			Some(snap) => Snap {
+				position: SnapPosition::Fullscreen,
+				restore_snap: Some(snap.position),
+				..snap
+			},
+			None => self.new_snap(SnapPosition::Fullscreen),
+		};
+		self.snap = Some(snap);
+		self.resnap_window(output_area);
+	}
+
+	/// Return a fullscreen window to where it was snapped before, in `work_area`, or to the geometry it had before
+	/// if it wasn't snapped
+	pub fn unfullscreen_window(&mut self, work_area: Rect) {
+		match self.snap {
+			Some(snap) if snap.position == SnapPosition::Fullscreen => match snap.restore_snap {
+				Some(position) => {
+					self.snap = Some(Snap {
+						position,
+						restore_snap: None,
+						..snap
+					});
+					self.resnap_window(work_area);
+				}
+				None => self.unsnap_window(),
+			},
+			_ => {}
+		}
+	}
+
+	/// Start snapping the window to `position`, remembering its current geometry
+	fn new_snap(&self, position: SnapPosition) -> Snap {
+		Snap {
+			position,
+			restore_position: self.position,
+			restore_size: self.try_get_window_geometry().map(|geometry| geometry.size()),
+			restore_snap: None,
+		}
+	}
+
+	/// Move and resize a snapped window to cover its snap position in `work_area` again. This reconfigures snapped
+	/// and maximized windows when the output they're on changes size.
+	pub fn resnap_window(&mut self, work_area: Rect) {
+		if let Some(snap) = self.snap {
+			let area = snap.position.area(work_area);
+			if let Some(ref mut role) = self.role {
+				role.set_maximized(snap.position == SnapPosition::Maximized);
+				role.set_fullscreen(snap.position == SnapPosition::Fullscreen);
+			}
+			self.set_window_position(Point::new(area.x, area.y));
+			self.resize_window(area.size());
+		}
+	}
+
+	/// Restore the geometry the window had before it was snapped
+	pub fn unsnap_window(&mut self) {
+		if let Some(snap) = self.snap.take() {
+			if let Some(ref mut role) = self.role {
+				role.set_maximized(false);
+				role.set_fullscreen(false);
+			}
+			if let Some(restore_position) = snap.restore_position {
+				self.position = Some(restore_position);
+			}
+			if let Some(restore_size) = snap.restore_size {
+				self.resize_window(restore_size);
+			}
+		}
+	}
+
+	/// Get the window geometry in surface local coordinates. A client can set a geometry that is bigger than its
+	/// buffer or starts at a negative offset, which would place and size the window by area the client never draws,
+	/// so the geometry is clamped to the buffer once one is committed. A geometry that misses the buffer entirely is
+	/// replaced by the whole buffer.
+	fn window_geometry(&self) -> Option<Rect> {
+		let solid_window_geometry = self.role.as_ref().and_then(|role| role.get_solid_window_geometry())?;
+		Some(clamp_window_geometry(solid_window_geometry, self.buffer_size))
+	}
+
+	/// Move the surface by `offset`, if it has a position
+	pub fn translate(&mut self, offset: Point) {
+		if let Some(position) = self.position {
+			self.position = Some(position.plus(offset));
 		}
 	}
 
 	/// Set the position of the surface in order for the window geometry to be at the given position
 	pub fn set_window_position(&mut self, position: Point) {
-		if let Some(solid_window_geometry) = self.role.as_ref().and_then(|role| role.get_solid_window_geometry()) {
-			self.position = Some(Point::new(
-				position.x - solid_window_geometry.x,
-				position.y - solid_window_geometry.y,
-			));
+		if let Some(solid_window_geometry) = self.window_geometry() {
+			self.position = Some(position.offset(-solid_window_geometry.x, -solid_window_geometry.y));
 		} else {
 			self.position = Some(position)
 		}
@@ -98,7 +342,7 @@ impl<G: GraphicsBackend + 'static> SurfaceData<G> {
 
 	pub fn resize_window(&mut self, size: Size) {
 		if let Some(ref mut role) = self.role {
-			role.resize_window(size);
+			let size = role.resize_window(size);
 			if let Some(solid_window_geometry) = role.get_solid_window_geometry() {
 				self.size = Some(Size::new(
 					size.width + solid_window_geometry.width * 2,
@@ -112,19 +356,21 @@ impl<G: GraphicsBackend + 'static> SurfaceData<G> {
 		}
 	}
 
+	/// Returns the center of the window geometry if both a position and size are set
+	pub fn try_get_window_center(&self) -> Option<Point> {
+		self.try_get_window_geometry().map(|geometry| {
+			Point::new(
+				geometry.x + geometry.width as i32 / 2,
+				geometry.y + geometry.height as i32 / 2,
+			)
+		})
+	}
+
 	/// Returns the geometry of the window if both a position and size are set
 	pub fn try_get_window_geometry(&self) -> Option<Rect> {
 		// woah
 		self.position
-			.and_then(|position| {
-				self.role
-					.as_ref()
-					.and_then(|role| {
-						role.get_solid_window_geometry()
-							.map(|solid_window_geometry| solid_window_geometry.size())
-					})
-					.map(|size| (position, size))
-			})
+			.and_then(|position| self.window_geometry().map(|geometry| (position, geometry.size())))
 			.map(Rect::from)
 	}
 
@@ -141,15 +387,38 @@ impl<G: GraphicsBackend + 'static> SurfaceData<G> {
 		}
 	}
 
+	/// Check whether a point in global coordinates is on the surface and inside its input region
+	pub fn accepts_input_at(&self, point: Point) -> bool {
+		self.try_get_surface_geometry()
+			.map(|geometry| geometry.contains_point(point))
+			.unwrap_or(false)
+			&& self.input_region_contains(point)
+	}
+
+	/// Check whether a point in global coordinates is inside the surface's input region, ignoring whether it's on
+	/// the surface at all
+	pub fn input_region_contains(&self, point: Point) -> bool {
+		match (self.input_region.as_ref(), self.try_get_surface_position()) {
+			(None, _) => true,
+			(Some(input_region), Some(position)) => input_region.contains_point(point.minus(position)),
+			(Some(_), None) => false,
+		}
+	}
+
+	/// Check whether the whole surface is fully opaque, so anything beneath it doesn't have to be drawn
+	pub fn is_opaque(&self) -> bool {
+		match (self.opaque_region.as_ref(), self.buffer_size) {
+			(Some(opaque_region), Some(size)) => opaque_region.covers(Rect::new(0, 0, size.width, size.height)),
+			_ => false,
+		}
+	}
+
 	/// Returns the position of this
 	pub fn try_get_surface_position(&self) -> Option<Point> {
 		if let Some(window_position) = self.position {
 			// Offset the window position by the solid window geometry coordinates to get the surface position
-			if let Some(solid_window_geometry) = self.role.as_ref().and_then(|role| role.get_solid_window_geometry()) {
-				Some(Point::new(
-					window_position.x - solid_window_geometry.x,
-					window_position.y - solid_window_geometry.y,
-				))
+			if let Some(solid_window_geometry) = self.window_geometry() {
+				Some(window_position.offset(-solid_window_geometry.x, -solid_window_geometry.y))
 			} else {
 				Some(window_position)
 			}
@@ -158,45 +427,202 @@ impl<G: GraphicsBackend + 'static> SurfaceData<G> {
 		}
 	}
 
+	/// Check that the pending state can be committed under this surface's role, sending the client a protocol error
+	/// and returning false if it can't
+	pub fn validate_commit(&self) -> bool {
+		let attaches_buffer = matches!(self.pending_state.attached_buffer, Some(Some(_)));
+		self.role
+			.as_ref()
+			.map_or(true, |role| role.validate_commit(attaches_buffer))
+	}
+
 	/// Commit all pending state to this surface
 	pub fn commit_pending_state(&mut self) {
 		if let Some(new_buffer) = self.pending_state.attached_buffer.take() {
 			if let Some(new_buffer) = new_buffer.as_ref() {
-				let committed_buffer_data = new_buffer.0.get_synced::<G::ShmBuffer>();
+				let committed_buffer_data = new_buffer.0.get_synced::<BufferData<G::ShmBuffer>>();
 				let committed_buffer_data_lock = committed_buffer_data.lock().unwrap();
+				let shm_buffer = &committed_buffer_data_lock.shm_buffer;
 				if let Some(role) = self.role.as_mut() {
-					role.set_surface_size(Size::new(
-						committed_buffer_data_lock.width() as u32,
-						committed_buffer_data_lock.height() as u32,
-					));
+					role.set_surface_size(Size::new(shm_buffer.width() as u32, shm_buffer.height() as u32));
 				}
-				self.buffer_size = Some(Size::new(
-					committed_buffer_data_lock.width() as u32,
-					committed_buffer_data_lock.height() as u32,
-				))
+				self.buffer_size = Some(Size::new(shm_buffer.width() as u32, shm_buffer.height() as u32))
 			} else {
 				self.buffer_size = None;
 			}
 			if let Some(old_buffer) = std::mem::replace(&mut self.committed_buffer, new_buffer) {
-				// Release the previously attached buffer if it hasn't been committed yet
-				old_buffer.0.release();
+				// Release the previously committed buffer if it hasn't been drawn yet
+				buffer::release_buffer::<G::ShmBuffer>(&old_buffer.0);
 			}
 		}
 		if let Some(new_input_region) = self.pending_state.input_region.take() {
-			self.input_region = Some(new_input_region);
+			self.input_region = new_input_region;
+		}
+		if let Some(new_opaque_region) = self.pending_state.opaque_region.take() {
+			self.opaque_region = new_opaque_region;
+		}
+		if let Some(buffer_scale) = self.pending_state.buffer_scale.take() {
+			self.buffer_scale = buffer_scale;
+		}
+		if let Some(buffer_transform) = self.pending_state.buffer_transform.take() {
+			self.buffer_transform = buffer_transform;
+		}
+		// Both kinds of damage are collected in buffer coordinates, since that's what textures are uploaded from.
+		// Surface damage has to be converted with the scale and transform of this commit.
+		let surface_damage = std::mem::take(&mut self.pending_state.surface_damage);
+		let buffer_damage = std::mem::take(&mut self.pending_state.buffer_damage);
+		if let Some(buffer_size) = self.buffer_size {
+			for rect in surface_damage {
+				if let Some(rect) =
+					surface_damage_to_buffer(rect, self.buffer_scale, self.buffer_transform, buffer_size)
+				{
+					self.add_damage(rect, buffer_size);
+				}
+			}
+			for rect in buffer_damage {
+				if let Some(rect) = clip_rect(rect, buffer_size) {
+					self.add_damage(rect, buffer_size);
+				}
+			}
+		}
+	}
+
+	/// Add a rectangle in buffer coordinates to the committed damage, falling back to damaging the whole buffer once
+	/// there are too many rectangles
+	fn add_damage(&mut self, rect: Rect, buffer_size: Size) {
+		if self.damage.len() < MAX_DAMAGE_RECTS {
+			self.damage.push(rect);
+		} else {
+			self.damage.clear();
+			self.damage.push(Rect::new(0, 0, buffer_size.width, buffer_size.height));
 		}
 	}
 
 	pub fn destroy(&mut self) {
 		// TODO: does this need to destroy the SurfaceRenderData too?
 		if let Some((buffer, _)) = self.pending_state.attached_buffer.take().and_then(|opt| opt) {
-			buffer.release();
+			buffer::release_buffer::<G::ShmBuffer>(&buffer);
 		}
 		if let Some((buffer, _)) = self.committed_buffer.take() {
-			buffer.release();
+			buffer::release_buffer::<G::ShmBuffer>(&buffer);
 		}
 		if let Some(mut role) = self.role.take() {
 			role.destroy();
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parts(rect: Rect) -> (i32, i32, u32, u32) {
+		(rect.x, rect.y, rect.width, rect.height)
+	}
+
+	#[test]
+	fn clip_rect_inside() {
+		let rect = clip_rect(Rect::new(10, 20, 30, 40), Size::new(100, 100)).unwrap();
+		assert_eq!(parts(rect), (10, 20, 30, 40));
+	}
+
+	#[test]
+	fn clip_rect_partially_outside() {
+		let rect = clip_rect(Rect::new(-10, 90, 30, 40), Size::new(100, 100)).unwrap();
+		assert_eq!(parts(rect), (0, 90, 20, 10));
+	}
+
+	#[test]
+	fn clip_rect_huge() {
+		let rect = clip_rect(Rect::new(0, 0, u32::MAX, u32::MAX), Size::new(200, 100)).unwrap();
+		assert_eq!(parts(rect), (0, 0, 200, 100));
+		let rect = clip_rect(Rect::new(i32::MAX, i32::MAX, u32::MAX, u32::MAX), Size::new(200, 100));
+		assert!(rect.is_none());
+	}
+
+	#[test]
+	fn clip_rect_outside() {
+		assert!(clip_rect(Rect::new(100, 0, 10, 10), Size::new(100, 100)).is_none());
+		assert!(clip_rect(Rect::new(0, 0, 0, 10), Size::new(100, 100)).is_none());
+	}
+
+	#[test]
+	fn surface_damage_scaled() {
+		let rect = surface_damage_to_buffer(
+			Rect::new(10, 10, 5, 5),
+			2,
+			wl_output::Transform::Normal,
+			Size::new(200, 100),
+		)
+		.unwrap();
+		assert_eq!(parts(rect), (20, 20, 10, 10));
+	}
+
+	#[test]
+	fn surface_damage_clipped_to_buffer() {
+		let rect = surface_damage_to_buffer(
+			Rect::new(90, 40, 50, 50),
+			2,
+			wl_output::Transform::Normal,
+			Size::new(200, 100),
+		)
+		.unwrap();
+		assert_eq!(parts(rect), (180, 80, 20, 20));
+		let rect = surface_damage_to_buffer(
+			Rect::new(0, 0, i32::MAX as u32, i32::MAX as u32),
+			2,
+			wl_output::Transform::Normal,
+			Size::new(200, 100),
+		)
+		.unwrap();
+		assert_eq!(parts(rect), (0, 0, 200, 100));
+	}
+
+	#[test]
+	fn surface_damage_rotated() {
+		// The buffer is displayed as 100x200, so the surface's top left corner is the buffer's bottom left corner
+		let rect = surface_damage_to_buffer(
+			Rect::new(0, 0, 10, 20),
+			1,
+			wl_output::Transform::_90,
+			Size::new(200, 100),
+		)
+		.unwrap();
+		assert_eq!(parts(rect), (0, 90, 20, 10));
+	}
+
+	#[test]
+	fn surface_damage_outside_buffer() {
+		let rect = surface_damage_to_buffer(
+			Rect::new(100, 0, 10, 10),
+			2,
+			wl_output::Transform::Normal,
+			Size::new(200, 100),
+		);
+		assert!(rect.is_none());
+	}
+
+	#[test]
+	fn window_geometry_inside_buffer() {
+		let geometry = clamp_window_geometry(Rect::new(10, 10, 180, 80), Some(Size::new(200, 100)));
+		assert_eq!(parts(geometry), (10, 10, 180, 80));
+	}
+
+	#[test]
+	fn window_geometry_clamped_to_buffer() {
+		let geometry = clamp_window_geometry(Rect::new(-10, 50, 400, 400), Some(Size::new(200, 100)));
+		assert_eq!(parts(geometry), (0, 50, 200, 50));
+	}
+
+	#[test]
+	fn window_geometry_outside_buffer() {
+		let geometry = clamp_window_geometry(Rect::new(300, 300, 10, 10), Some(Size::new(200, 100)));
+		assert_eq!(parts(geometry), (0, 0, 200, 100));
+	}
+
+	#[test]
+	fn window_geometry_without_buffer() {
+		let geometry = clamp_window_geometry(Rect::new(300, 300, 10, 10), None);
+		assert_eq!(parts(geometry), (300, 300, 10, 10));
+	}
+}
@@ -0,0 +1,177 @@
+//! Touch points are sent to the surface under them when they go down, and every later event of a touch point goes to
+//! that same surface, even once the touch point has moved off of it. Touchscreens aren't mapped to outputs yet, so
+//! touch positions are taken to be on the first output.
+
+use std::sync::Arc;
+
+use crate::{
+	backend::Touch,
+	compositor::{prelude::*, CompositorInner},
+};
+
+/// A touch point that is down on a surface
+pub(crate) struct TouchPoint {
+	slot: u32,
+	pub(crate) surface: wl_surface::WlSurface,
+}
+
+impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> CompositorInner<I, G> {
+	/// Send a touch event to the clients of the surfaces it affects. Clients are sent a frame once every touch point
+	/// that changed at the same time has been sent to them.
+	pub(crate) fn handle_touch(&mut self, touch: Touch) {
+		match touch {
+			Touch::Down {
+				serial,
+				time,
+				slot,
+				x,
+				y,
+			} => {
+				let position = match self.touch_position(x, y) {
+					Some(position) => position,
+					None => return,
+				};
+				let surface = match self.surface_under_touch(x, y) {
+					Some(surface) => surface,
+					None => return,
+				};
+				let surface_data = surface.get_synced::<SurfaceData<G>>();
+				let surface_data_lock = surface_data.lock().unwrap();
+				let (local_x, local_y) = match surface_local_position(&surface_data_lock, position) {
+					Some(local_position) => local_position,
+					None => return,
+				};
+				for wl_touch in &surface_data_lock.client_info.lock().unwrap().touches {
+					wl_touch.down(serial, time, &surface, slot as i32, local_x, local_y);
+				}
+				self.add_touch_frame_client(&surface_data_lock.client_info);
+				drop(surface_data_lock);
+				self.touch_points.retain(|touch_point| touch_point.slot != slot);
+				self.touch_points.push(TouchPoint { slot, surface });
+			}
+			Touch::Motion { time, slot, x, y } => {
+				let surface = match self.touch_points.iter().find(|touch_point| touch_point.slot == slot) {
+					Some(touch_point) => touch_point.surface.clone(),
+					None => return,
+				};
+				let position = match self.touch_position(x, y) {
+					Some(position) => position,
+					None => return,
+				};
+				let surface_data = surface.get_synced::<SurfaceData<G>>();
+				let surface_data_lock = surface_data.lock().unwrap();
+				let (local_x, local_y) = match surface_local_position(&surface_data_lock, position) {
+					Some(local_position) => local_position,
+					None => return,
+				};
+				for wl_touch in &surface_data_lock.client_info.lock().unwrap().touches {
+					wl_touch.motion(time, slot as i32, local_x, local_y);
+				}
+				self.add_touch_frame_client(&surface_data_lock.client_info);
+			}
+			Touch::Up { serial, time, slot } => {
+				let index = match self
+					.touch_points
+					.iter()
+					.position(|touch_point| touch_point.slot == slot)
+				{
+					Some(index) => index,
+					None => return,
+				};
+				let touch_point = self.touch_points.remove(index);
+				let surface_data = touch_point.surface.get_synced::<SurfaceData<G>>();
+				let surface_data_lock = surface_data.lock().unwrap();
+				for wl_touch in &surface_data_lock.client_info.lock().unwrap().touches {
+					wl_touch.up(serial, time, slot as i32);
+				}
+				self.add_touch_frame_client(&surface_data_lock.client_info);
+			}
+			Touch::Cancel { slot, .. } => {
+				// Cancelling is done for every touch point of a client at once, so the client forgets all of them
+				let surface = match self.touch_points.iter().find(|touch_point| touch_point.slot == slot) {
+					Some(touch_point) => touch_point.surface.clone(),
+					None => return,
+				};
+				let surface_data = surface.get_synced::<SurfaceData<G>>();
+				let client_info = Arc::clone(&surface_data.lock().unwrap().client_info);
+				for wl_touch in &client_info.lock().unwrap().touches {
+					wl_touch.cancel();
+				}
+				self.touch_points.retain(|touch_point| {
+					let touch_point_data = touch_point.surface.get_synced::<SurfaceData<G>>();
+					let touch_point_client_info = Arc::clone(&touch_point_data.lock().unwrap().client_info);
+					!Arc::ptr_eq(&touch_point_client_info, &client_info)
+				});
+				self.touch_frame_clients
+					.retain(|frame_client_info| !Arc::ptr_eq(frame_client_info, &client_info));
+			}
+			Touch::Frame { .. } => {
+				for client_info in self.touch_frame_clients.drain(..) {
+					for wl_touch in &client_info.lock().unwrap().touches {
+						wl_touch.frame();
+					}
+				}
+			}
+		}
+	}
+
+	/// Check whether a touch event should be turned into pointer events while pointer emulation is enabled. Touch
+	/// points that go down on a surface of a client with a wl_touch are sent to it as touch events, since the client
+	/// handles touch itself. Frames are never emulated, because only clients sent touch events need them.
+	pub(crate) fn should_emulate_touch(&self, touch: &Touch) -> bool {
+		match *touch {
+			Touch::Down { x, y, .. } => match self.surface_under_touch(x, y) {
+				Some(surface) => {
+					let surface_data = surface.get_synced::<SurfaceData<G>>();
+					let surface_data_lock = surface_data.lock().unwrap();
+					let client_info_lock = surface_data_lock.client_info.lock().unwrap();
+					client_info_lock.touches.is_empty()
+				}
+				None => true,
+			},
+			Touch::Motion { slot, .. } | Touch::Up { slot, .. } | Touch::Cancel { slot, .. } => {
+				self.emulated_touch_slot == Some(slot)
+			}
+			Touch::Frame { .. } => false,
+		}
+	}
+
+	/// Get the window under a touch position given as fractions of the width and height of the touchscreen
+	fn surface_under_touch(&self, x: f64, y: f64) -> Option<wl_surface::WlSurface> {
+		let position = self.touch_position(x, y)?;
+		let point = Point::new(position.0.round() as i32, position.1.round() as i32);
+		self.window_manager.get_window_under_point(point)
+	}
+
+	/// Get the global position of a touch position given as fractions of the width and height of the touchscreen
+	pub(crate) fn touch_position(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+		let viewport = self.output_globals.first()?.1.lock().unwrap().viewport;
+		Some((
+			f64::from(viewport.x) + x * f64::from(viewport.width),
+			f64::from(viewport.y) + y * f64::from(viewport.height),
+		))
+	}
+
+	/// Remember to send a frame to a client once the current group of touch events is done
+	fn add_touch_frame_client(&mut self, client_info: &Synced<ClientInfo>) {
+		if !self
+			.touch_frame_clients
+			.iter()
+			.any(|frame_client_info| Arc::ptr_eq(frame_client_info, client_info))
+		{
+			self.touch_frame_clients.push(Arc::clone(client_info));
+		}
+	}
+}
+
+/// Get a global position relative to a surface, if the surface has a position
+fn surface_local_position<G: GraphicsBackend + 'static>(
+	surface_data: &SurfaceData<G>,
+	position: (f64, f64),
+) -> Option<(f64, f64)> {
+	let surface_position = surface_data.try_get_surface_position()?;
+	Some((
+		position.0 - f64::from(surface_position.x),
+		position.1 - f64::from(surface_position.y),
+	))
+}
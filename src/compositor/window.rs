@@ -0,0 +1,132 @@
+use std::{
+	fmt,
+	sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::compositor::{prelude::*, surface::same_surface, Compositor, CompositorInner};
+
+static NEXT_WINDOW_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A handle to a window that stays valid for as long as its surface exists. Unlike wayland object ids, window ids
+/// are never reused, so a handle to a window that has gone away can't end up referring to a different window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WindowId(pub u64);
+
+impl WindowId {
+	pub(crate) fn next() -> Self {
+		WindowId(NEXT_WINDOW_ID.fetch_add(1, Ordering::Relaxed))
+	}
+}
+
+impl fmt::Display for WindowId {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "window#{}", self.0)
+	}
+}
+
+/// A snapshot of the state of a mapped window
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowInfo {
+	pub id: WindowId,
+	pub title: Option<String>,
+	pub app_id: Option<String>,
+	/// The window geometry in global compositor coordinates, if the window has been placed
+	pub geometry: Option<Rect>,
+	/// Whether the window has keyboard focus
+	pub focused: bool,
+}
+
+impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> CompositorInner<I, G> {
+	/// List every mapped window from the bottom of the stack to the top
+	pub fn windows(&self) -> Vec<WindowInfo> {
+		self.window_manager
+			.manager_impl
+			.surfaces_ascending()
+			.filter_map(|surface| {
+				let surface_data = surface.get_synced::<SurfaceData<G>>();
+				let surface_data_lock = surface_data.lock().unwrap();
+				let role = surface_data_lock.role.as_ref().filter(|role| role.is_window())?;
+				Some(WindowInfo {
+					id: surface_data_lock.window_id,
+					title: role.title(),
+					app_id: role.app_id(),
+					geometry: surface_data_lock.try_get_window_geometry(),
+					focused: self
+						.keyboard_focus
+						.as_ref()
+						.map(|focused| same_surface::<G>(focused, surface))
+						.unwrap_or(false),
+				})
+			})
+			.collect()
+	}
+
+	/// Get the surface of a mapped window
+	pub fn find_window(&self, id: WindowId) -> Option<wl_surface::WlSurface> {
+		self.window_manager
+			.manager_impl
+			.surfaces_ascending()
+			.find(|surface| surface.get_synced::<SurfaceData<G>>().lock().unwrap().window_id == id)
+			.cloned()
+	}
+
+	/// Give a window keyboard focus. Returns false if there is no such window.
+	pub fn focus_window(&mut self, id: WindowId) -> bool {
+		match self.find_window(id) {
+			Some(surface) => {
+				self.focus_surface(&surface);
+				true
+			}
+			None => false,
+		}
+	}
+
+	/// Move a window to the top of the stack. Returns false if there is no such window.
+	pub fn raise_window(&mut self, id: WindowId) -> bool {
+		match self.find_window(id) {
+			Some(surface) => {
+				self.window_manager.manager_impl.raise_surface(&surface);
+				true
+			}
+			None => false,
+		}
+	}
+
+	/// Ask a window to close. The client decides whether and when it actually does, so the window may stay around
+	/// for a while, or for good. Returns false if there is no such window.
+	pub fn close_window(&mut self, id: WindowId) -> bool {
+		match self.find_window(id) {
+			Some(surface) => {
+				let surface_data = surface.get_synced::<SurfaceData<G>>();
+				let surface_data_lock = surface_data.lock().unwrap();
+				if let Some(ref role) = surface_data_lock.role {
+					role.close();
+				}
+				true
+			}
+			None => false,
+		}
+	}
+}
+
+impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
+	/// List every mapped window from the bottom of the stack to the top. See [`CompositorInner::windows`].
+	pub fn windows(&self) -> Vec<WindowInfo> {
+		self.inner.lock().unwrap().windows()
+	}
+
+	/// Give a window keyboard focus. See [`CompositorInner::focus_window`].
+	pub fn focus_window(&mut self, id: WindowId) -> bool {
+		self.inner.lock().unwrap().focus_window(id)
+	}
+
+	/// Move a window to the top of the stack. See [`CompositorInner::raise_window`].
+	pub fn raise_window(&mut self, id: WindowId) -> bool {
+		self.inner.lock().unwrap().raise_window(id)
+	}
+
+	/// Ask a window to close. See [`CompositorInner::close_window`].
+	pub fn close_window(&mut self, id: WindowId) -> bool {
+		self.inner.lock().unwrap().close_window(id)
+	}
+}
@@ -8,8 +8,10 @@ use wayland_server::{Filter, Main};
 
 use crate::{
 	backend::{GraphicsBackend, InputBackend},
-	compositor::{prelude::*, role::Role, surface::SurfaceData, Compositor},
-	renderer::Output,
+	compositor::{
+		get_input_serial, output, ping::XdgWmBasePing, prelude::*, role::Role, surface::SurfaceData, Compositor,
+		CompositorInner, GraphicsBackendState, SurfaceEvent,
+	},
 };
 
 #[derive(Debug, Default, Clone)]
@@ -24,19 +26,74 @@ pub struct XdgSurfaceData {
 	pub pending_state: XdgSurfacePendingState,
 	pub solid_window_geometry: Option<Rect>,
 	pub xdg_surface_role: Option<XdgSurfaceRole>,
+	/// Whether the client has acknowledged a configure yet, which it has to do before committing a buffer
+	pub configured: bool,
 }
 
 #[derive(Clone)]
 pub enum XdgSurfaceRole {
 	XdgToplevel(xdg_toplevel::XdgToplevel),
+	XdgPopup(xdg_popup::XdgPopup),
 }
 
 impl XdgSurfaceRole {
-	pub fn resize_window(&self, size: Size) {
+	/// Ask the client to resize the window, returning the size it was asked for after clamping `size` to the
+	/// window's size limits. Popups are sized by their positioner, so they're never asked to resize.
+	pub fn resize_window(&self, size: Size) -> Size {
 		match *self {
 			XdgSurfaceRole::XdgToplevel(ref xdg_toplevel) => {
-				xdg_toplevel.configure(size.width as i32, size.height as i32, Vec::new());
+				let toplevel_data = xdg_toplevel.get_synced::<XdgToplevelData>();
+				let toplevel_data_lock = toplevel_data.lock().unwrap();
+				let size = toplevel_data_lock.clamp_size(size);
+				xdg_toplevel.configure(size.width as i32, size.height as i32, toplevel_data_lock.states());
+				size
 			}
+			XdgSurfaceRole::XdgPopup(_) => size,
+		}
+	}
+
+	pub fn commit_pending_state(&self) {
+		match *self {
+			XdgSurfaceRole::XdgToplevel(ref xdg_toplevel) => {
+				xdg_toplevel
+					.get_synced::<XdgToplevelData>()
+					.lock()
+					.unwrap()
+					.commit_pending_state();
+			}
+			XdgSurfaceRole::XdgPopup(_) => {}
+		}
+	}
+
+	/// Ask the client to close the window, or dismiss the popup
+	pub fn close(&self) {
+		match *self {
+			XdgSurfaceRole::XdgToplevel(ref xdg_toplevel) => xdg_toplevel.close(),
+			XdgSurfaceRole::XdgPopup(ref xdg_popup) => xdg_popup.popup_done(),
+		}
+	}
+
+	pub fn title(&self) -> Option<String> {
+		match *self {
+			XdgSurfaceRole::XdgToplevel(ref xdg_toplevel) => xdg_toplevel
+				.get_synced::<XdgToplevelData>()
+				.lock()
+				.unwrap()
+				.title
+				.clone(),
+			XdgSurfaceRole::XdgPopup(_) => None,
+		}
+	}
+
+	pub fn app_id(&self) -> Option<String> {
+		match *self {
+			XdgSurfaceRole::XdgToplevel(ref xdg_toplevel) => xdg_toplevel
+				.get_synced::<XdgToplevelData>()
+				.lock()
+				.unwrap()
+				.app_id
+				.clone(),
+			XdgSurfaceRole::XdgPopup(_) => None,
 		}
 	}
 }
@@ -47,6 +104,7 @@ impl XdgSurfaceData {
 			pending_state: XdgSurfacePendingState::default(),
 			solid_window_geometry: None,
 			xdg_surface_role: None,
+			configured: false,
 		}
 	}
 
@@ -54,12 +112,17 @@ impl XdgSurfaceData {
 		if let Some(solid_window_geometry) = self.pending_state.solid_window_geometry.take() {
 			self.solid_window_geometry = Some(solid_window_geometry);
 		}
+		if let Some(ref xdg_surface_role) = self.xdg_surface_role {
+			xdg_surface_role.commit_pending_state();
+		}
 	}
 
-	pub fn resize_window(&mut self, size: Size) {
-		self.xdg_surface_role
-			.as_mut()
-			.map(|xdg_surface_role| xdg_surface_role.resize_window(size));
+	/// Ask the client to resize the window, returning the size it was asked for
+	pub fn resize_window(&mut self, size: Size) -> Size {
+		match self.xdg_surface_role {
+			Some(ref xdg_surface_role) => xdg_surface_role.resize_window(size),
+			None => size,
+		}
 	}
 }
 
@@ -70,6 +133,14 @@ impl fmt::Debug for XdgSurfaceRole {
 				.debug_struct("XdgSurfaceRole::XdgToplevel")
 				.field("XdgToplevel", &"<XdgToplevel>")
 				.finish(),
+			XdgSurfaceRole::XdgPopup(ref xdg_popup) => f
+				.debug_struct("XdgSurfaceRole::XdgPopup")
+				.field("XdgPopup", &"<XdgPopup>")
+				.field(
+					"geometry",
+					&xdg_popup.get_synced::<XdgPopupData>().lock().unwrap().geometry,
+				)
+				.finish(),
 		}
 	}
 }
@@ -77,38 +148,357 @@ impl fmt::Debug for XdgSurfaceRole {
 #[derive(Debug, Clone)]
 pub struct XdgToplevelData {
 	pub title: Option<String>,
+	pub app_id: Option<String>,
+	/// The smallest size the window can be resized to. A dimension of 0 means there is no limit.
+	pub min_size: Size,
+	/// The largest size the window can be resized to. A dimension of 0 means there is no limit.
+	pub max_size: Size,
+	/// Size limits that take effect on the next commit
+	pending_min_size: Option<Size>,
+	pending_max_size: Option<Size>,
+	/// Whether the window is maximized or fullscreen, which clients are told about in every configure
+	pub maximized: bool,
+	pub fullscreen: bool,
+	/// The toplevel the window belongs to, like the main window of a dialog
+	pub parent: Option<xdg_toplevel::XdgToplevel>,
 }
 
 impl XdgToplevelData {
 	pub fn new() -> Self {
-		Self { title: None }
+		Self {
+			title: None,
+			app_id: None,
+			min_size: Size::new(0, 0),
+			max_size: Size::new(0, 0),
+			pending_min_size: None,
+			pending_max_size: None,
+			maximized: false,
+			fullscreen: false,
+			parent: None,
+		}
+	}
+
+	/// Get the states to send in a configure, encoded as an array of 32 bit values
+	pub fn states(&self) -> Vec<u8> {
+		let mut states = Vec::new();
+		if self.maximized {
+			states.extend_from_slice(&(xdg_toplevel::State::Maximized as u32).to_ne_bytes());
+		}
+		if self.fullscreen {
+			states.extend_from_slice(&(xdg_toplevel::State::Fullscreen as u32).to_ne_bytes());
+		}
+		states
+	}
+
+	/// Set the minimum size that takes effect on the next commit
+	pub fn set_min_size(&mut self, width: i32, height: i32) {
+		match size_limit(width, height) {
+			Some(min_size) => self.pending_min_size = Some(min_size),
+			None => log::warn!("Ignoring negative min size {}x{}", width, height),
+		}
+	}
+
+	/// Set the maximum size that takes effect on the next commit
+	pub fn set_max_size(&mut self, width: i32, height: i32) {
+		match size_limit(width, height) {
+			Some(max_size) => self.pending_max_size = Some(max_size),
+			None => log::warn!("Ignoring negative max size {}x{}", width, height),
+		}
+	}
+
+	pub fn commit_pending_state(&mut self) {
+		if let Some(min_size) = self.pending_min_size.take() {
+			self.min_size = min_size;
+		}
+		if let Some(max_size) = self.pending_max_size.take() {
+			self.max_size = max_size;
+		}
+	}
+
+	/// Clamp a size to the window's size limits. If the limits contradict each other, the minimum size wins.
+	pub fn clamp_size(&self, size: Size) -> Size {
+		let clamp = |value: u32, min: u32, max: u32| {
+			let value = if max > 0 { value.min(max) } else { value };
+			value.max(min)
+		};
+		Size::new(
+			clamp(size.width, self.min_size.width, self.max_size.width),
+			clamp(size.height, self.min_size.height, self.max_size.height),
+		)
+	}
+}
+
+/// The placement rules of an xdg_positioner, which popups are placed with relative to their parent
+#[derive(Debug, Clone)]
+pub struct XdgPositionerData {
+	pub size: Option<Size>,
+	/// The rectangle the popup is anchored to, relative to the parent's window geometry
+	pub anchor_rect: Option<Rect>,
+	pub anchor: xdg_positioner::Anchor,
+	pub gravity: xdg_positioner::Gravity,
+	/// How the popup may be moved or resized to keep it on screen. This is stored but not applied yet.
+	pub constraint_adjustment: u32,
+	pub offset: Point,
+}
+
+impl XdgPositionerData {
+	pub fn new() -> Self {
+		Self {
+			size: None,
+			anchor_rect: None,
+			anchor: xdg_positioner::Anchor::None,
+			gravity: xdg_positioner::Gravity::None,
+			constraint_adjustment: 0,
+			offset: Point::new(0, 0),
+		}
+	}
+
+	/// Update the placement rules from a request, or return an error message if the request is invalid
+	fn handle_request(&mut self, request: xdg_positioner::Request) -> Result<(), &'static str> {
+		match request {
+			xdg_positioner::Request::Destroy => {}
+			xdg_positioner::Request::SetSize { width, height } => {
+				if width <= 0 || height <= 0 {
+					return Err("the popup size must be positive");
+				}
+				self.size = Some(Size::new(width as u32, height as u32));
+			}
+			xdg_positioner::Request::SetAnchorRect { x, y, width, height } => {
+				if width <= 0 || height <= 0 {
+					return Err("the anchor rect size must be positive");
+				}
+				self.anchor_rect = Some(Rect::new(x, y, width as u32, height as u32));
+			}
+			xdg_positioner::Request::SetAnchor { anchor } => self.anchor = anchor,
+			xdg_positioner::Request::SetGravity { gravity } => self.gravity = gravity,
+			xdg_positioner::Request::SetConstraintAdjustment { constraint_adjustment } => {
+				self.constraint_adjustment = constraint_adjustment;
+			}
+			xdg_positioner::Request::SetOffset { x, y } => self.offset = Point::new(x, y),
+			_ => log::warn!("Got unknown request for xdg_positioner"),
+		}
+		Ok(())
+	}
+
+	/// Get the geometry of a popup placed with these rules, relative to the parent's window geometry, or None if
+	/// the size or the anchor rect hasn't been set. The anchor picks a point on the anchor rect, and the gravity
+	/// picks the direction the popup extends in from that point.
+	pub fn popup_geometry(&self) -> Option<Rect> {
+		use xdg_positioner::{Anchor, Gravity};
+
+		let size = self.size?;
+		let anchor_rect = self.anchor_rect?;
+		// Every value comes from the client, so the arithmetic saturates instead of overflowing. The sizes were
+		// checked to be positive i32s when they were set.
+		let (width, height) = (size.width as i32, size.height as i32);
+		let (anchor_width, anchor_height) = (anchor_rect.width as i32, anchor_rect.height as i32);
+		let anchor_x = match self.anchor {
+			Anchor::Left | Anchor::TopLeft | Anchor::BottomLeft => anchor_rect.x,
+			Anchor::Right | Anchor::TopRight | Anchor::BottomRight => anchor_rect.x.saturating_add(anchor_width),
+			_ => anchor_rect.x.saturating_add(anchor_width / 2),
+		};
+		let anchor_y = match self.anchor {
+			Anchor::Top | Anchor::TopLeft | Anchor::TopRight => anchor_rect.y,
+			Anchor::Bottom | Anchor::BottomLeft | Anchor::BottomRight => anchor_rect.y.saturating_add(anchor_height),
+			_ => anchor_rect.y.saturating_add(anchor_height / 2),
+		};
+		let x = match self.gravity {
+			Gravity::Left | Gravity::TopLeft | Gravity::BottomLeft => anchor_x.saturating_sub(width),
+			Gravity::Right | Gravity::TopRight | Gravity::BottomRight => anchor_x,
+			_ => anchor_x.saturating_sub(width / 2),
+		};
+		let y = match self.gravity {
+			Gravity::Top | Gravity::TopLeft | Gravity::TopRight => anchor_y.saturating_sub(height),
+			Gravity::Bottom | Gravity::BottomLeft | Gravity::BottomRight => anchor_y,
+			_ => anchor_y.saturating_sub(height / 2),
+		};
+		Some(Rect::new(
+			x.saturating_add(self.offset.x),
+			y.saturating_add(self.offset.y),
+			size.width,
+			size.height,
+		))
+	}
+}
+
+/// The data of an xdg_popup
+#[derive(Debug, Clone)]
+pub struct XdgPopupData {
+	/// The xdg_surface of the surface the popup is placed relative to
+	pub parent: xdg_surface::XdgSurface,
+	/// The geometry of the popup relative to the parent's window geometry
+	pub geometry: Rect,
+}
+
+/// Convert the size limit sent by a client to a size, or None if it has a negative dimension
+fn size_limit(width: i32, height: i32) -> Option<Size> {
+	if width < 0 || height < 0 {
+		return None;
+	}
+	Some(Size::new(width as u32, height as u32))
+}
+
+impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> CompositorInner<I, G> {
+	/// Stack a toplevel above the toplevel it set as its parent, and stack the toplevels that set it as their parent
+	/// above it. This is done whenever a toplevel sets its parent or is mapped, since either toplevel might not be
+	/// mapped yet when the parent is set.
+	pub(crate) fn update_toplevel_parents(&mut self, surface: &wl_surface::WlSurface) {
+		let role = match surface.get_synced::<SurfaceData<G>>().lock().unwrap().role.clone() {
+			Some(role) => role,
+			None => return,
+		};
+		let xdg_toplevel = match role.xdg_toplevel() {
+			Some(xdg_toplevel) => xdg_toplevel,
+			None => return,
+		};
+		let parent = role
+			.toplevel_parent()
+			.and_then(|parent| self.find_toplevel_surface(&parent));
+		self.window_manager.manager_impl.set_parent(surface, parent.as_ref());
+		let children = self
+			.window_manager
+			.manager_impl
+			.surfaces_ascending()
+			.filter(|candidate| {
+				let candidate_data = candidate.get_synced::<SurfaceData<G>>();
+				let candidate_data_lock = candidate_data.lock().unwrap();
+				candidate_data_lock
+					.role
+					.as_ref()
+					.and_then(Role::toplevel_parent)
+					.as_ref() == Some(&xdg_toplevel)
+			})
+			.cloned()
+			.collect::<Vec<_>>();
+		for child in children {
+			self.window_manager.manager_impl.set_parent(&child, Some(surface));
+		}
+	}
+
+	/// Get the mapped surface whose role is `xdg_toplevel`
+	fn find_toplevel_surface(&self, xdg_toplevel: &xdg_toplevel::XdgToplevel) -> Option<wl_surface::WlSurface> {
+		self.window_manager
+			.manager_impl
+			.surfaces_ascending()
+			.find(|candidate| {
+				let candidate_data = candidate.get_synced::<SurfaceData<G>>();
+				let candidate_data_lock = candidate_data.lock().unwrap();
+				candidate_data_lock.role.as_ref().and_then(Role::xdg_toplevel).as_ref() == Some(xdg_toplevel)
+			})
+			.cloned()
+	}
+
+	/// Show a popup directly above its parent once a buffer is committed to it, at `geometry` relative to the
+	/// parent's window geometry. Popups aren't windows, so they aren't focused when they're shown.
+	pub(crate) fn map_popup(
+		&mut self,
+		surface: &wl_surface::WlSurface,
+		parent: &xdg_surface::XdgSurface,
+		geometry: Rect,
+	) {
+		let parent_surface = self
+			.window_manager
+			.manager_impl
+			.surfaces_ascending()
+			.find(|candidate| {
+				let candidate_data = candidate.get_synced::<SurfaceData<G>>();
+				let candidate_data_lock = candidate_data.lock().unwrap();
+				candidate_data_lock
+					.role
+					.as_ref()
+					.map(|role| role.is_xdg_surface(parent))
+					.unwrap_or(false)
+			})
+			.cloned();
+		let parent_surface = match parent_surface {
+			Some(parent_surface) => parent_surface,
+			None => {
+				log::warn!(
+					"Not showing popup surface@{} because its parent isn't shown",
+					surface.as_ref().id()
+				);
+				return;
+			}
+		};
+		let parent_origin = parent_surface
+			.get_synced::<SurfaceData<G>>()
+			.lock()
+			.unwrap()
+			.try_get_window_geometry()
+			.map(|parent_geometry| Point::new(parent_geometry.x, parent_geometry.y))
+			.unwrap_or(Point::new(0, 0));
+		let surface_data = surface.get_synced::<SurfaceData<G>>();
+		let mut surface_data_lock = surface_data.lock().unwrap();
+		surface_data_lock.set_window_position(Point::new(
+			parent_origin.x.saturating_add(geometry.x),
+			parent_origin.y.saturating_add(geometry.y),
+		));
+		output::update_surface_outputs(surface, &mut surface_data_lock);
+		drop(surface_data_lock);
+		self.window_manager
+			.manager_impl
+			.add_popup(surface.clone(), &parent_surface);
+	}
+}
+
+/// Clean up after a destroyed xdg_toplevel or xdg_popup. The surface stops being managed and stops showing its last
+/// buffer, but it can be given a new role afterwards.
+fn destroy_xdg_surface_role<I: InputBackend + 'static, G: GraphicsBackend + 'static>(
+	inner: &Mutex<CompositorInner<I, G>>,
+	graphics_backend_state: &Mutex<GraphicsBackendState<G>>,
+	xdg_surface_data: &Mutex<XdgSurfaceData>,
+	surface: &wl_surface::WlSurface,
+) {
+	xdg_surface_data.lock().unwrap().xdg_surface_role = None;
+	let mut graphics_backend_state_lock = graphics_backend_state.lock().unwrap();
+	let surface_data = surface.get_synced::<SurfaceData<G>>();
+	let mut surface_data_lock = surface_data.lock().unwrap();
+	if let Some(renderer_data) = surface_data_lock.renderer_data.as_mut() {
+		graphics_backend_state_lock
+			.renderer
+			.release_surface_plane(renderer_data)
+			.map_err(|e| log::error!("Failed to release the plane of a destroyed toplevel: {}", e))
+			.unwrap();
 	}
+	drop(surface_data_lock);
+	drop(graphics_backend_state_lock);
+	inner.lock().unwrap().unmap_window(surface);
 }
 
 impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 	pub(crate) fn setup_xdg_wm_base_global(&mut self) {
 		let inner = Arc::clone(&self.inner);
+		let graphics_backend_state = Arc::clone(&self.graphics_backend_state);
 		let xdg_wm_base_filter = Filter::new(
 			move |(main, _num): (Main<xdg_wm_base::XdgWmBase>, u32), _filter, _dispatch_data| {
 				let inner = Arc::clone(&inner);
-				main.quick_assign(move |_main, request: xdg_wm_base::Request, _| {
+				let graphics_backend_state = Arc::clone(&graphics_backend_state);
+				let mut inner_lock = inner.lock().unwrap();
+				let client_info = inner_lock
+					.client_manager
+					.get_client_info(main.as_ref().client().unwrap());
+				inner_lock
+					.xdg_wm_base_pings
+					.push(XdgWmBasePing::new((*main).clone(), client_info));
+				drop(inner_lock);
+				main.quick_assign(move |main, request: xdg_wm_base::Request, _| {
 					let inner = Arc::clone(&inner);
+					let graphics_backend_state = Arc::clone(&graphics_backend_state);
 					match request {
 						xdg_wm_base::Request::Destroy => {}
 						xdg_wm_base::Request::CreatePositioner { id } => {
+							let positioner_data = Arc::new(Mutex::new(XdgPositionerData::new()));
+							let positioner_data_clone = Arc::clone(&positioner_data);
+							id.as_ref().user_data().set_threadsafe(move || positioner_data_clone);
 							id.quick_assign(
-								|_main: Main<xdg_positioner::XdgPositioner>, request: xdg_positioner::Request, _| {
-									match request {
-										xdg_positioner::Request::Destroy => {}
-										xdg_positioner::Request::SetSize { .. } => {}
-										xdg_positioner::Request::SetAnchorRect { .. } => {}
-										xdg_positioner::Request::SetAnchor { .. } => {}
-										xdg_positioner::Request::SetGravity { .. } => {}
-										xdg_positioner::Request::SetConstraintAdjustment { .. } => {}
-										xdg_positioner::Request::SetOffset { .. } => {}
-										_ => {
-											log::warn!("Got unknown request for xdg_positioner");
-										}
+								move |main: Main<xdg_positioner::XdgPositioner>,
+								      request: xdg_positioner::Request,
+								      _| {
+									if let Err(message) = positioner_data.lock().unwrap().handle_request(request) {
+										main.as_ref().post_error(
+											xdg_positioner::Error::InvalidInput as u32,
+											String::from(message),
+										);
 									}
 								},
 							);
@@ -118,6 +508,17 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 							surface,
 						} => {
 							log::trace!("Creating xdg_surface");
+							let is_cursor = matches!(
+								surface.get_synced::<SurfaceData<G>>().lock().unwrap().role,
+								Some(Role::Cursor)
+							);
+							if is_cursor {
+								main.as_ref().post_error(
+									xdg_wm_base::Error::Role as u32,
+									String::from("Surface is already used as a cursor"),
+								);
+								return;
+							}
 							let xdg_surface = (*xdg_surface_id).clone();
 							let xdg_surface_data = Arc::new(Mutex::new(XdgSurfaceData::new()));
 							let xdg_surface_data_clone = Arc::clone(&xdg_surface_data);
@@ -125,10 +526,22 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 								.as_ref()
 								.user_data()
 								.set_threadsafe(move || xdg_surface_data_clone);
+							let xdg_wm_base = (*main).clone();
+							let destructor_surface = surface.clone();
 							xdg_surface_id.quick_assign(
 								move |_main: Main<xdg_surface::XdgSurface>, request: xdg_surface::Request, _| {
 									let inner = Arc::clone(&inner);
+									let graphics_backend_state = Arc::clone(&graphics_backend_state);
 									match request {
+										xdg_surface::Request::Destroy => {
+											// The role object has to be destroyed before the xdg_surface
+											if xdg_surface_data.lock().unwrap().xdg_surface_role.is_some() {
+												xdg_wm_base.as_ref().post_error(
+													xdg_wm_base::Error::Role as u32,
+													String::from("xdg_surface was destroyed before its role object"),
+												);
+											}
+										}
 										xdg_surface::Request::GetToplevel { id: xdg_toplevel_id } => {
 											// Set the xdg toplevel data
 											let xdg_toplevel = (*xdg_toplevel_id).clone();
@@ -149,54 +562,111 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 												Some(XdgSurfaceRole::XdgToplevel(xdg_toplevel.clone()));
 											drop(xdg_surface_data_lock);
 
-											let mut inner_lock = inner.lock().unwrap();
-											inner_lock.window_manager.manager_impl.add_surface(surface.clone());
+											// The toplevel only becomes a window once a buffer is committed to
+											// it. Until then it picks its own size, so it can attach a buffer.
+											xdg_toplevel.configure(0, 0, Vec::new());
+											xdg_surface.configure(get_input_serial());
 
-											// Send output enter events for every output viewport this surface intersects
-											// TODO: handle surface moves and possibly output viewport changes
-											let surface_data = surface.get_synced::<SurfaceData<G>>();
-											let surface_data_lock = surface_data.lock().unwrap();
-											let client_info = inner_lock
-												.client_manager
-												.get_client_info(xdg_toplevel.as_ref().client().unwrap());
-											let client_info_lock = client_info.lock().unwrap();
-											for output in &client_info_lock.outputs {
-												let output_data = output.get::<Output<G>>();
-												if let Some(surface_geometry) =
-													surface_data_lock.try_get_surface_geometry()
-												{
-													if surface_geometry.intersects(output_data.viewport) {
-														surface.enter(output);
-													}
-												}
-											}
+											let destructor_inner = Arc::clone(&inner);
+											let destructor_xdg_surface_data = Arc::clone(&xdg_surface_data);
+											let toplevel_surface = surface.clone();
+											xdg_toplevel_id.assign_destructor(Filter::new(
+												move |_: xdg_toplevel::XdgToplevel, _filter, _dispatch_data| {
+													destroy_xdg_surface_role(
+														&destructor_inner,
+														&graphics_backend_state,
+														&destructor_xdg_surface_data,
+														&toplevel_surface,
+													)
+												},
+											));
 
+											let toplevel_inner = Arc::clone(&inner);
+											let toplevel_surface = surface.clone();
 											xdg_toplevel_id.quick_assign(
 												move |_main, request: xdg_toplevel::Request, _| {
 													let toplevel_data = Arc::clone(&xdg_toplevel_data);
 													match request {
-														xdg_toplevel::Request::SetParent { .. } => {}
+														xdg_toplevel::Request::Destroy => {
+															// Handled by destructor
+														}
+														xdg_toplevel::Request::SetParent { parent } => {
+															toplevel_data.lock().unwrap().parent = parent;
+															toplevel_inner
+																.lock()
+																.unwrap()
+																.update_toplevel_parents(&toplevel_surface);
+														}
 														xdg_toplevel::Request::SetTitle { title } => {
 															let mut toplevel_data_lock = toplevel_data.lock().unwrap();
-															toplevel_data_lock.title = Some(title);
+															toplevel_data_lock.title = Some(title.clone());
+															drop(toplevel_data_lock);
+															toplevel_inner.lock().unwrap().emit_surface_event(
+																SurfaceEvent::TitleChanged(
+																	toplevel_surface.clone(),
+																	title,
+																),
+															);
+														}
+														xdg_toplevel::Request::SetAppId { app_id } => {
+															toplevel_data.lock().unwrap().app_id = Some(app_id);
 														}
-														xdg_toplevel::Request::SetAppId { .. } => {}
 														xdg_toplevel::Request::ShowWindowMenu { .. } => {}
-														xdg_toplevel::Request::Move {
-															seat: _seat,
-															serial: _serial,
-														} => {}
+														xdg_toplevel::Request::Move { seat: _seat, serial } => {
+															let mut inner = toplevel_inner.lock().unwrap();
+															if !inner.validate_pointer_button_serial(serial) {
+																log::debug!(
+																	"Ignoring move with stale serial {}",
+																	serial
+																);
+																return;
+															}
+															inner.start_move(&toplevel_surface);
+														}
 														xdg_toplevel::Request::Resize {
 															seat: _seat,
-															serial: _serail,
+															serial,
 															edges: _edges,
-														} => {}
-														xdg_toplevel::Request::SetMaxSize { .. } => {}
-														xdg_toplevel::Request::SetMinSize { .. } => {}
-														xdg_toplevel::Request::SetMaximized => {}
-														xdg_toplevel::Request::UnsetMaximized => {}
-														xdg_toplevel::Request::SetFullscreen { .. } => {}
-														xdg_toplevel::Request::UnsetFullscreen => {}
+														} => {
+															if !toplevel_inner.lock().unwrap().validate_serial(serial) {
+																log::debug!(
+																	"Ignoring resize with stale serial {}",
+																	serial
+																);
+																return;
+															}
+															log::warn!("Interactive resizes are not implemented");
+														}
+														xdg_toplevel::Request::SetMaxSize { width, height } => {
+															toplevel_data.lock().unwrap().set_max_size(width, height);
+														}
+														xdg_toplevel::Request::SetMinSize { width, height } => {
+															toplevel_data.lock().unwrap().set_min_size(width, height);
+														}
+														xdg_toplevel::Request::SetMaximized => {
+															toplevel_inner
+																.lock()
+																.unwrap()
+																.maximize_surface(&toplevel_surface);
+														}
+														xdg_toplevel::Request::UnsetMaximized => {
+															toplevel_inner
+																.lock()
+																.unwrap()
+																.unmaximize_surface(&toplevel_surface);
+														}
+														xdg_toplevel::Request::SetFullscreen { output } => {
+															toplevel_inner
+																.lock()
+																.unwrap()
+																.fullscreen_surface(&toplevel_surface, output.as_ref());
+														}
+														xdg_toplevel::Request::UnsetFullscreen => {
+															toplevel_inner
+																.lock()
+																.unwrap()
+																.unfullscreen_surface(&toplevel_surface);
+														}
 														xdg_toplevel::Request::SetMinimized => {}
 														_ => {
 															log::warn!("Got unknown request for xdg_toplevel");
@@ -205,30 +675,115 @@ impl<I: InputBackend + 'static, G: GraphicsBackend + 'static> Compositor<I, G> {
 												},
 											);
 										}
-										xdg_surface::Request::GetPopup {
-											id,
-											parent: _parent,
-											positioner: _positioner,
-										} => id.quick_assign(
-											move |_main, request: xdg_popup::Request, _| match request {
-												xdg_popup::Request::Destroy => {}
-												xdg_popup::Request::Grab { .. } => {}
-												xdg_popup::Request::Reposition { .. } => {}
-												_ => log::warn!("Got unknown request for xdg_popup"),
-											},
-										),
+										xdg_surface::Request::GetPopup { id, parent, positioner } => {
+											// Popups without a parent can only be placed by other protocols, which
+											// aren't supported
+											let parent = match parent {
+												Some(parent) => parent,
+												None => {
+													xdg_wm_base.as_ref().post_error(
+														xdg_wm_base::Error::InvalidPopupParent as u32,
+														String::from("xdg_popup must have a parent"),
+													);
+													return;
+												}
+											};
+											let positioner_data = positioner.get_synced::<XdgPositionerData>();
+											let geometry = match positioner_data.lock().unwrap().popup_geometry() {
+												Some(geometry) => geometry,
+												None => {
+													xdg_wm_base.as_ref().post_error(
+														xdg_wm_base::Error::InvalidPositioner as u32,
+														String::from("xdg_positioner needs a size and an anchor rect"),
+													);
+													return;
+												}
+											};
+											let xdg_popup = (*id).clone();
+											let popup_data = Arc::new(Mutex::new(XdgPopupData { parent, geometry }));
+											xdg_popup.as_ref().user_data().set_threadsafe(move || popup_data);
+
+											let surface_data = surface.get_synced::<SurfaceData<G>>();
+											let mut surface_data_lock = surface_data.lock().unwrap();
+											surface_data_lock.role = Some(Role::XdgSurface(xdg_surface.clone()));
+											drop(surface_data_lock);
+											xdg_surface_data.lock().unwrap().xdg_surface_role =
+												Some(XdgSurfaceRole::XdgPopup(xdg_popup.clone()));
+
+											// Like a toplevel, the popup is shown once a buffer is committed to it
+											xdg_popup.configure(
+												geometry.x,
+												geometry.y,
+												geometry.width as i32,
+												geometry.height as i32,
+											);
+											xdg_surface.configure(get_input_serial());
+
+											let destructor_inner = Arc::clone(&inner);
+											let destructor_xdg_surface_data = Arc::clone(&xdg_surface_data);
+											let popup_surface = surface.clone();
+											id.assign_destructor(Filter::new(
+												move |_: xdg_popup::XdgPopup, _filter, _dispatch_data| {
+													destroy_xdg_surface_role(
+														&destructor_inner,
+														&graphics_backend_state,
+														&destructor_xdg_surface_data,
+														&popup_surface,
+													)
+												},
+											));
+											id.quick_assign(
+												move |main, request: xdg_popup::Request, _| match request {
+													xdg_popup::Request::Destroy => {}
+													xdg_popup::Request::Grab { seat: _seat, serial } => {
+														// A popup that can't be granted its grab is dismissed right away
+														if !inner.lock().unwrap().validate_serial(serial) {
+															log::debug!(
+																"Dismissing popup grab with stale serial {}",
+																serial
+															);
+															main.popup_done();
+														}
+													}
+													xdg_popup::Request::Reposition { .. } => {}
+													_ => log::warn!("Got unknown request for xdg_popup"),
+												},
+											);
+										}
 										xdg_surface::Request::SetWindowGeometry { x, y, width, height } => {
+											if width <= 0 || height <= 0 {
+												log::warn!("Ignoring a window geometry of {}x{}", width, height);
+												return;
+											}
 											let solid_window_geometry = Rect::new(x, y, width as u32, height as u32);
 											let mut xdg_surface_data_lock = xdg_surface_data.lock().unwrap();
 											xdg_surface_data_lock.solid_window_geometry = Some(solid_window_geometry);
 										}
-										xdg_surface::Request::AckConfigure { .. } => {}
+										xdg_surface::Request::AckConfigure { .. } => {
+											let mut xdg_surface_data_lock = xdg_surface_data.lock().unwrap();
+											xdg_surface_data_lock.configured = true;
+										}
 										_ => log::warn!("Got unknown request for xdg_surface"),
 									}
 								},
 							);
+							xdg_surface_id.assign_destructor(Filter::new(
+								move |xdg_surface: xdg_surface::XdgSurface, _filter, _dispatch_data| {
+									let surface_data = destructor_surface.get_synced::<SurfaceData<G>>();
+									let mut surface_data_lock = surface_data.lock().unwrap();
+									let is_role = match surface_data_lock.role {
+										Some(Role::XdgSurface(ref role_xdg_surface)) => {
+											role_xdg_surface.as_ref().equals(xdg_surface.as_ref())
+										}
+										Some(Role::Cursor) | None => false,
+									};
+									if is_role {
+										surface_data_lock.role = None;
+									}
+								},
+							));
 						}
-						xdg_wm_base::Request::Pong { .. } => {}
+						xdg_wm_base::Request::Pong { serial } => inner.lock().unwrap().handle_pong(&main, serial),
 						_ => {
 							log::warn!("Got unknown request for xdg_wm_base");
 						}
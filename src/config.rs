@@ -0,0 +1,181 @@
+use std::{
+	convert::TryFrom,
+	env, fs, io,
+	path::{Path, PathBuf},
+	str::FromStr,
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+use wayland_server::protocol::wl_output;
+
+/// Options that can be set in the config file at `$XDG_CONFIG_HOME/wally/config`. The file is written in TOML, and
+/// every option is optional. Options given on the command line override the ones in the file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+	/// The backend to use, either "winit", "drm", or "vk_display"
+	pub backend: Option<String>,
+	/// The name of the wayland socket in `$XDG_RUNTIME_DIR`. If not set, the first of "wayland-0", "wayland-1", and so
+	/// on that isn't taken by another compositor is used.
+	pub socket_name: Option<String>,
+	pub profile: bool,
+	/// The highest rate to render frames at, which is only limited by the backend if not set
+	pub max_fps: Option<u32>,
+	/// Render this many frames of a synthetic scene and print how long they took instead of running the compositor.
+	/// This can only be set on the command line.
+	#[serde(skip)]
+	pub bench: Option<u32>,
+	pub debug: bool,
+	/// Hide the cursor while typing or touching the screen, and show it again as soon as the pointer moves
+	pub hide_cursor_while_typing: bool,
+	/// Move the pointer and press its left button with the first finger on a touchscreen, for clients that only
+	/// support pointers. Touch events are sent to clients instead if this isn't set.
+	pub emulate_pointer_with_touch: bool,
+	/// Reject shm pools whose file can still be shrunk by the client. A client that truncates such a file can crash
+	/// the compositor, but many clients don't seal their pools, so they're only warned about if this isn't set.
+	pub require_sealed_shm: bool,
+	/// Which new windows take keyboard focus when they're mapped, "same-client" if not set
+	pub new_window_focus: NewWindowFocus,
+	/// The longest time between two presses of a pointer button that still counts as a double click, in
+	/// milliseconds, 400 if not set
+	pub double_click_time: Option<u32>,
+	/// How many times per second a held key repeats, 25 if not set. Setting it to 0 disables key repeat.
+	pub repeat_rate: Option<u32>,
+	/// How long a key has to be held before it starts repeating, in milliseconds, 600 if not set
+	pub repeat_delay: Option<u32>,
+	/// Repeat held keys in the compositor instead of leaving it to clients, for clients that don't repeat keys
+	/// themselves
+	pub server_side_repeat: bool,
+	/// The path to create a control socket at, which other programs can use to list and manage windows. No socket is
+	/// created if this isn't set.
+	pub ipc_socket: Option<PathBuf>,
+	/// The transforms of outputs that are physically rotated or flipped, which also rotate what is presented to them
+	pub output_transforms: Vec<OutputTransform>,
+	pub client_limits: ClientLimits,
+}
+
+/// Which new windows are given keyboard focus when they're mapped. Windows that aren't focused have to be clicked
+/// before they get keyboard input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NewWindowFocus {
+	/// New windows never take focus
+	Never,
+	/// Every new window takes focus
+	Always,
+	/// New windows take focus if nothing has focus or the client they belong to already has it, so a client in
+	/// the background can't take the keyboard away from the one the user is using
+	SameClient,
+}
+
+impl Default for NewWindowFocus {
+	fn default() -> Self {
+		NewWindowFocus::SameClient
+	}
+}
+
+/// The transform of a single output, written as `OUTPUT:TRANSFORM` where `OUTPUT` is the index of the output and
+/// `TRANSFORM` is one of `normal`, `90`, `180`, `270`, `flipped`, `flipped-90`, `flipped-180`, or `flipped-270`.
+/// Rotations are counter-clockwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct OutputTransform {
+	pub output: usize,
+	pub transform: wl_output::Transform,
+}
+
+impl FromStr for OutputTransform {
+	type Err = ConfigError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let invalid = || ConfigError::InvalidOutputTransform(String::from(s));
+		let mut parts = s.splitn(2, ':');
+		let output = parts
+			.next()
+			.and_then(|output| output.parse().ok())
+			.ok_or_else(invalid)?;
+		let transform = match parts.next().ok_or_else(invalid)? {
+			"normal" => wl_output::Transform::Normal,
+			"90" => wl_output::Transform::_90,
+			"180" => wl_output::Transform::_180,
+			"270" => wl_output::Transform::_270,
+			"flipped" => wl_output::Transform::Flipped,
+			"flipped-90" => wl_output::Transform::Flipped90,
+			"flipped-180" => wl_output::Transform::Flipped180,
+			"flipped-270" => wl_output::Transform::Flipped270,
+			_ => return Err(invalid()),
+		};
+		Ok(Self { output, transform })
+	}
+}
+
+impl TryFrom<String> for OutputTransform {
+	type Error = ConfigError;
+
+	fn try_from(s: String) -> Result<Self, Self::Error> {
+		s.parse()
+	}
+}
+
+/// The resources a single client is allowed to use. Clients that try to go over a limit are disconnected.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ClientLimits {
+	pub max_surfaces: usize,
+	pub max_shm_pools: usize,
+	pub max_buffers: usize,
+	/// The maximum total size of the shm pools a client can have mapped at once, in bytes
+	pub max_shm_bytes: usize,
+}
+
+impl Default for ClientLimits {
+	fn default() -> Self {
+		Self {
+			max_surfaces: 1024,
+			max_shm_pools: 1024,
+			max_buffers: 4096,
+			max_shm_bytes: 1 << 30,
+		}
+	}
+}
+
+impl Config {
+	/// Get the path of the config file, which is `$XDG_CONFIG_HOME/wally/config`, or `~/.config/wally/config` if
+	/// `XDG_CONFIG_HOME` isn't set
+	pub fn path() -> Option<PathBuf> {
+		env::var_os("XDG_CONFIG_HOME")
+			.map(PathBuf::from)
+			.or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+			.map(|config_dir| config_dir.join("wally").join("config"))
+	}
+
+	/// Load the config file, returning the default config if it doesn't exist
+	pub fn load() -> Result<Self, ConfigError> {
+		let path = match Self::path() {
+			Some(path) => path,
+			None => {
+				log::warn!("Couldn't determine the config file location, using the default config");
+				return Ok(Self::default());
+			}
+		};
+		match fs::read_to_string(&path) {
+			Ok(contents) => {
+				log::info!("Loading config from {}", path.display());
+				toml::from_str(&contents).map_err(|e| ConfigError::ParseError(path, e))
+			}
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+			Err(e) => Err(ConfigError::ReadError(path, e)),
+		}
+	}
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+	#[error("Failed to read the config file at {0}")]
+	ReadError(PathBuf, #[source] io::Error),
+	#[error("Failed to parse the config file at {0}")]
+	ParseError(PathBuf, #[source] toml::de::Error),
+	#[error("Invalid output transform '{0}', expected OUTPUT:TRANSFORM, such as 0:90")]
+	InvalidOutputTransform(String),
+}
@@ -1,82 +1,165 @@
-use crate::math::*;
+//! Helpers for festus' geometry types. Rectangles can be enormous, like damage covering a whole surface, so edges are
+//! computed in 64 bit arithmetic to avoid overflowing.
 
-#[derive(Debug, Clone, Copy)]
-pub struct Point {
-	pub x: i32,
-	pub y: i32,
+use festus::geometry::*;
+
+/// Coordinate math for festus' `Point`, for converting points between window, surface and output spaces
+pub trait PointExt {
+	/// Scale both coordinates by the given factors, rounding to the nearest integer
+	fn scale(self, cx: f64, cy: f64) -> Self;
+	/// Move the point by the given distances
+	fn offset(self, dx: i32, dy: i32) -> Self;
+	/// Add the coordinates of another point
+	fn plus(self, other: Point) -> Self;
+	/// Subtract the coordinates of another point
+	fn minus(self, other: Point) -> Self;
 }
 
-impl Point {
-	pub fn new(x: i32, y: i32) -> Self {
-		Self { x, y }
+impl PointExt for Point {
+	fn scale(self, cx: f64, cy: f64) -> Self {
+		Point::new(
+			(f64::from(self.x) * cx).round() as i32,
+			(f64::from(self.y) * cy).round() as i32,
+		)
 	}
-}
 
-impl From<Point> for Point2 {
-	fn from(t: Point) -> Self {
-		Point2::new(t.x as f32, t.y as f32)
+	fn offset(self, dx: i32, dy: i32) -> Self {
+		Point::new(self.x + dx, self.y + dy)
 	}
-}
 
-impl From<Point> for Vec2 {
-	fn from(t: Point) -> Self {
-		Vec2::new(t.x as f32, t.y as f32)
+	fn plus(self, other: Point) -> Self {
+		self.offset(other.x, other.y)
+	}
+
+	fn minus(self, other: Point) -> Self {
+		self.offset(-other.x, -other.y)
 	}
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct Size {
-	pub width: u32,
-	pub height: u32,
+/// Build a rectangle from edges computed in 64 bit arithmetic, clamping it to the range of `Rect`
+fn rect_from_edges(x: i64, y: i64, right: i64, bottom: i64) -> Rect {
+	let x = x.max(i64::from(i32::MIN)).min(i64::from(i32::MAX));
+	let y = y.max(i64::from(i32::MIN)).min(i64::from(i32::MAX));
+	Rect::new(
+		x as i32,
+		y as i32,
+		(right - x).max(0).min(i64::from(u32::MAX)) as u32,
+		(bottom - y).max(0).min(i64::from(u32::MAX)) as u32,
+	)
 }
 
-impl Size {
-	pub fn new(width: u32, height: u32) -> Self {
-		Self { width, height }
-	}
+/// Get the smallest rectangle that contains both rectangles
+pub fn rect_union(a: Rect, b: Rect) -> Rect {
+	rect_from_edges(
+		i64::from(a.x).min(i64::from(b.x)),
+		i64::from(a.y).min(i64::from(b.y)),
+		(i64::from(a.x) + i64::from(a.width)).max(i64::from(b.x) + i64::from(b.width)),
+		(i64::from(a.y) + i64::from(a.height)).max(i64::from(b.y) + i64::from(b.height)),
+	)
 }
 
-impl From<Size> for Vec2 {
-	fn from(t: Size) -> Self {
-		Vec2::new(t.width as f32, t.height as f32)
+/// Get the pixels two rectangles have in common, or None if they don't overlap
+pub fn rect_intersection(a: Rect, b: Rect) -> Option<Rect> {
+	let x = i64::from(a.x).max(i64::from(b.x));
+	let y = i64::from(a.y).max(i64::from(b.y));
+	let right = (i64::from(a.x) + i64::from(a.width)).min(i64::from(b.x) + i64::from(b.width));
+	let bottom = (i64::from(a.y) + i64::from(a.height)).min(i64::from(b.y) + i64::from(b.height));
+	if right <= x || bottom <= y {
+		return None;
 	}
+	Some(rect_from_edges(x, y, right, bottom))
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct Rect {
-	pub x: i32,
-	pub y: i32,
-	pub width: u32,
-	pub height: u32,
+/// Check whether `outer` contains every pixel of `inner`
+pub fn rect_contains(outer: Rect, inner: Rect) -> bool {
+	i64::from(inner.x) >= i64::from(outer.x)
+		&& i64::from(inner.y) >= i64::from(outer.y)
+		&& i64::from(inner.x) + i64::from(inner.width) <= i64::from(outer.x) + i64::from(outer.width)
+		&& i64::from(inner.y) + i64::from(inner.height) <= i64::from(outer.y) + i64::from(outer.height)
 }
 
-impl Rect {
-	pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
-		Self { x, y, width, height }
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn coords(point: Point) -> (i32, i32) {
+		(point.x, point.y)
 	}
 
-	pub fn point(self) -> Point {
-		Point { x: self.x, y: self.y }
+	#[test]
+	fn scale_rounds_to_nearest() {
+		assert_eq!(coords(Point::new(3, -3).scale(0.5, 0.5)), (2, -2));
+		assert_eq!(coords(Point::new(10, 10).scale(2.0, 0.25)), (20, 3));
 	}
-	pub fn size(self) -> Size {
-		Size {
-			width: self.width,
-			height: self.height,
-		}
+
+	#[test]
+	fn offset_and_arithmetic() {
+		let point = Point::new(5, -5);
+		assert_eq!(coords(point.offset(-10, 10)), (-5, 5));
+		assert_eq!(coords(point.plus(Point::new(1, 2))), (6, -3));
+		assert_eq!(coords(point.minus(Point::new(1, 2))), (4, -7));
+		assert_eq!(coords(point.plus(Point::new(7, 8)).minus(Point::new(7, 8))), (5, -5));
 	}
 
-	pub fn contains_point(self, point: Point) -> bool {
-		point.x >= self.x && point.y >= self.y && point.x <= self.x + self.width as i32 && point.y <= self.y + self.height as i32
+	#[test]
+	fn union_of_disjoint_rects() {
+		let union = rect_union(Rect::new(0, 0, 10, 10), Rect::new(20, -5, 10, 10));
+		assert_eq!(union, Rect::new(0, -5, 30, 15));
+	}
+
+	#[test]
+	fn union_of_nested_rects() {
+		let outer = Rect::new(-10, -10, 100, 100);
+		let inner = Rect::new(0, 0, 10, 10);
+		assert_eq!(rect_union(outer, inner), outer);
+		assert_eq!(rect_union(inner, outer), outer);
 	}
-}
 
-impl From<(Point, Size)> for Rect {
-	fn from(t: (Point, Size)) -> Self {
-		Rect {
-			x: t.0.x,
-			y: t.0.y,
-			width: t.1.width,
-			height: t.1.height,
-		}
+	#[test]
+	fn union_of_huge_rects() {
+		let union = rect_union(Rect::new(i32::MIN, 0, u32::MAX, 1), Rect::new(i32::MAX, 0, u32::MAX, 1));
+		assert_eq!(union, Rect::new(i32::MIN, 0, u32::MAX, 1));
 	}
-}
\ No newline at end of file
+
+	#[test]
+	fn intersection_of_overlapping_rects() {
+		let intersection = rect_intersection(Rect::new(0, 0, 10, 10), Rect::new(5, -5, 10, 10));
+		assert_eq!(intersection, Some(Rect::new(5, 0, 5, 5)));
+	}
+
+	#[test]
+	fn intersection_of_disjoint_rects() {
+		assert_eq!(
+			rect_intersection(Rect::new(0, 0, 10, 10), Rect::new(10, 0, 10, 10)),
+			None
+		);
+	}
+
+	#[test]
+	fn intersection_of_huge_rects() {
+		let intersection = rect_intersection(Rect::new(0, 0, u32::MAX, u32::MAX), Rect::new(i32::MAX - 1, 0, 10, 10));
+		assert_eq!(intersection, Some(Rect::new(i32::MAX - 1, 0, 10, 10)));
+	}
+
+	#[test]
+	fn contains_nested_rects() {
+		let outer = Rect::new(-10, -10, 100, 100);
+		assert!(rect_contains(outer, Rect::new(0, 0, 10, 10)));
+		assert!(rect_contains(outer, outer));
+		assert!(!rect_contains(Rect::new(0, 0, 10, 10), outer));
+		assert!(!rect_contains(outer, Rect::new(80, 80, 20, 20)));
+	}
+
+	#[test]
+	fn contains_huge_rects() {
+		// The right edge of the inner rectangle overflows an i32
+		assert!(!rect_contains(
+			Rect::new(0, 0, 100, 100),
+			Rect::new(50, 0, u32::MAX, 10)
+		));
+		assert!(rect_contains(
+			Rect::new(0, 0, u32::MAX, 100),
+			Rect::new(i32::MAX - 1, 0, 10, 10)
+		));
+	}
+}
@@ -5,7 +5,36 @@ use std::{
 
 use xkbcommon::xkb;
 
-use crate::compositor::prelude::*;
+use crate::{
+	backend::{KeyboardLeds, ModifiersChanged},
+	compositor::prelude::*,
+};
+
+bitflags::bitflags! {
+	/// The modifier keys that can be part of a keybinding
+	pub struct KeyModifiers: u32 {
+		const SHIFT = 0b0001;
+		const CTRL = 0b0010;
+		const ALT = 0b0100;
+		const LOGO = 0b1000;
+	}
+}
+
+/// A key combination that runs an action in the compositor instead of being sent to clients
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keybinding {
+	/// The modifiers that have to be held down, no more and no less
+	pub modifiers: KeyModifiers,
+	/// The keysym the key produces, e.g. `xkb::keysyms::KEY_Return`. Keybindings that include shift have to use
+	/// the shifted keysym of the key.
+	pub keysym: xkb::Keysym,
+}
+
+impl Keybinding {
+	pub fn new(modifiers: KeyModifiers, keysym: xkb::Keysym) -> Self {
+		Self { modifiers, keysym }
+	}
+}
 
 pub struct KeyboardState {
 	pub xkb: xkb::Context,
@@ -15,6 +44,10 @@ pub struct KeyboardState {
 	pub fd: RawFd,
 	pub tmp: std::fs::File,
 	pub xkb_modifiers_state: XkbModifiersState,
+	/// How many times per second a held key repeats, where 0 disables repeating
+	pub repeat_rate: i32,
+	/// How long a key has to be held before it starts repeating, in milliseconds
+	pub repeat_delay: i32,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -25,6 +58,11 @@ pub struct XkbModifiersState {
 	pub group: u32,
 }
 
+/// The repeat rate used if none is configured, in repeats per second
+pub const DEFAULT_REPEAT_RATE: i32 = 25;
+/// The repeat delay used if none is configured, in milliseconds
+pub const DEFAULT_REPEAT_DELAY: i32 = 600;
+
 impl KeyboardState {
 	pub fn new() -> Self {
 		let xkb = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
@@ -49,11 +87,88 @@ impl KeyboardState {
 				mods_locked: 0,
 				group: 0,
 			},
+			repeat_rate: DEFAULT_REPEAT_RATE,
+			repeat_delay: DEFAULT_REPEAT_DELAY,
 		}
 	}
 
 	pub fn update_key(&mut self, key_press: KeyPress) -> bool {
 		self.state.update_key(key_press.key + 8, key_press.state.into());
+		self.refresh_modifier_state()
+	}
+
+	/// Replace the depressed modifiers with the ones reported by a backend, keeping latched and locked modifiers as
+	/// they are. Returns whether the modifier state changed.
+	pub fn update_modifiers(&mut self, modifiers: ModifiersChanged) -> bool {
+		let mut mods_depressed = 0;
+		for &(name, pressed) in &[
+			(xkb::MOD_NAME_SHIFT, modifiers.shift),
+			(xkb::MOD_NAME_CTRL, modifiers.ctrl),
+			(xkb::MOD_NAME_ALT, modifiers.alt),
+			(xkb::MOD_NAME_LOGO, modifiers.logo),
+		] {
+			let index = self.keymap.mod_get_index(name);
+			if pressed && index != xkb::MOD_INVALID {
+				mods_depressed |= 1 << index;
+			}
+		}
+		let current = self.xkb_modifiers_state;
+		self.state.update_mask(
+			mods_depressed,
+			current.mods_latched,
+			current.mods_locked,
+			0,
+			0,
+			current.group,
+		);
+		self.refresh_modifier_state()
+	}
+
+	/// Get the keysym that the key with the given evdev keycode produces in the current state
+	pub fn keysym(&self, key: u32) -> xkb::Keysym {
+		self.state.key_get_one_sym(key + 8)
+	}
+
+	/// Get the modifiers that are currently active
+	pub fn active_modifiers(&self) -> KeyModifiers {
+		let mut modifiers = KeyModifiers::empty();
+		for &(name, modifier) in &[
+			(xkb::MOD_NAME_SHIFT, KeyModifiers::SHIFT),
+			(xkb::MOD_NAME_CTRL, KeyModifiers::CTRL),
+			(xkb::MOD_NAME_ALT, KeyModifiers::ALT),
+			(xkb::MOD_NAME_LOGO, KeyModifiers::LOGO),
+		] {
+			let index = self.keymap.mod_get_index(name);
+			if index != xkb::MOD_INVALID && self.state.mod_index_is_active(index, xkb::STATE_MODS_EFFECTIVE) {
+				modifiers |= modifier;
+			}
+		}
+		modifiers
+	}
+
+	/// Get the lock LEDs that should currently be lit
+	pub fn leds(&self) -> KeyboardLeds {
+		let mut leds = KeyboardLeds::empty();
+		for &(name, led) in &[
+			(xkb::LED_NAME_NUM, KeyboardLeds::NUM_LOCK),
+			(xkb::LED_NAME_CAPS, KeyboardLeds::CAPS_LOCK),
+			(xkb::LED_NAME_SCROLL, KeyboardLeds::SCROLL_LOCK),
+		] {
+			if self.state.led_name_is_active(name) {
+				leds |= led;
+			}
+		}
+		leds
+	}
+
+	/// Get the lock LEDs that should currently be lit as the bits of [`KeyboardLeds`], for things like on-screen
+	/// lock indicators
+	pub fn current_leds(&self) -> u32 {
+		self.leds().bits()
+	}
+
+	/// Update the cached modifier state from the xkb state, returning whether it changed
+	fn refresh_modifier_state(&mut self) -> bool {
 		let new_modifiers = self.get_modifier_state();
 		if new_modifiers != self.xkb_modifiers_state {
 			self.xkb_modifiers_state = new_modifiers;
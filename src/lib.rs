@@ -0,0 +1,43 @@
+//! Wally is a wayland compositor that can also be embedded in other programs. A compositor is created from an
+//! [`InputBackend`](backend::InputBackend) and a [`GraphicsBackend`](backend::GraphicsBackend) with a
+//! [`CompositorBuilder`](compositor::builder::CompositorBuilder), which can also register keybindings and replace
+//! the window placement policy before the compositor starts.
+//!
+//! A minimal embedding that runs wally on the DRM and libinput backends looks like this:
+//!
+//! ```ignore
+//! use festus::{geometry::Size, present::drm::DrmPresentBackend};
+//! use wally::{
+//! 	backend::{libinput::LibinputInputBackend, vulkan::VulkanGraphicsBackend},
+//! 	compositor::builder::CompositorBuilder,
+//! 	input::{KeyModifiers, Keybinding},
+//! };
+//! use xkbcommon::xkb;
+//!
+//! let (renderer, present_backend, _) =
+//! 	festus::renderer::Renderer::new::<DrmPresentBackend>(Size::new(1920, 1080), ()).unwrap();
+//! let mut event_loop = calloop::EventLoop::new().unwrap();
+//! let graphics_backend = VulkanGraphicsBackend::new(renderer, present_backend);
+//! let input_backend = LibinputInputBackend::new(event_loop.handle()).unwrap();
+//! let mut compositor = CompositorBuilder::new(input_backend, graphics_backend)
+//! 	.socket_name("wayland-1")
+//! 	.keybinding(
+//! 		Keybinding::new(KeyModifiers::LOGO, xkb::keysyms::KEY_Escape),
+//! 		|inner| inner.stop(),
+//! 	)
+//! 	.build(event_loop.handle())
+//! 	.unwrap();
+//! compositor.start(&mut event_loop);
+//! ```
+
+pub mod backend;
+pub mod behavior;
+pub mod compositor;
+pub mod config;
+pub mod geometry;
+pub mod input;
+//pub mod logind;
+pub mod renderer;
+#[cfg(test)]
+mod test_util;
+//pub mod wl;
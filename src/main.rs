@@ -4,24 +4,22 @@ use calloop::EventLoop;
 };*/
 use structopt::StructOpt;
 
-use crate::backend::{vulkan::VulkanGraphicsBackend, winit::WinitInputBackend};
 use festus::{
 	geometry::Size,
 	present::{
 		drm::DrmPresentBackend,
 		//vk_display::DisplaySurfaceCreator,
 		winit::WinitSurfaceCreator,
+		PresentBackend,
 		SwapchainPresentBackend,
 	},
 };
-
-pub mod backend;
-pub mod compositor;
-//pub mod logind;
-pub mod behavior;
-pub mod input;
-pub mod renderer;
-//pub mod wl;
+use wally::{
+	backend::{self, vulkan::VulkanGraphicsBackend, winit::WinitInputBackend, GraphicsBackend},
+	compositor,
+	config::{self, Config},
+	renderer::Renderer,
+};
 
 #[derive(StructOpt)]
 #[structopt(name = "wally", about = "A wayland compositor")]
@@ -31,34 +29,85 @@ pub struct Opts {
 		long,
 		help = "Select the backend. Can be either \"winit\", \"drm\", or \"vk_display\""
 	)]
-	backend: String,
+	backend: Option<String>,
 	#[structopt(short, long, help = "Enable profiling output")]
 	profile: bool,
 	#[structopt(short, long, help = "Enable debugging output")]
 	debug: bool,
+	#[structopt(
+		short,
+		long,
+		parse(from_occurrences),
+		help = "Log more, starting from RUST_LOG or info. Can be given more than once"
+	)]
+	verbose: u8,
+	#[structopt(
+		short,
+		long,
+		parse(from_occurrences),
+		help = "Log less, starting from RUST_LOG or info. Can be given more than once"
+	)]
+	quiet: u8,
+	#[structopt(long, help = "Limit the frame rate to at most this many frames per second")]
+	max_fps: Option<u32>,
+	#[structopt(
+		long,
+		help = "Render this many frames of a synthetic scene, print frame time statistics and exit"
+	)]
+	bench: Option<u32>,
+	#[structopt(
+		long = "output-transform",
+		number_of_values = 1,
+		help = "Rotate or flip an output, as OUTPUT:TRANSFORM, such as 0:90. Can be given once for every output"
+	)]
+	output_transforms: Vec<config::OutputTransform>,
+	#[structopt(
+		long,
+		parse(from_os_str),
+		help = "Create a control socket at this path, which wallyctl can use to list and manage windows"
+	)]
+	ipc_socket: Option<std::path::PathBuf>,
 }
 
 fn main() {
-	setup_logging();
+	let opts = Opts::from_args();
+	setup_logging(log_level(&opts));
 
 	let event_loop = EventLoop::<()>::new().expect("Failed to create event loop");
-	let opts = Opts::from_args();
-	if opts.profile {
+	let config = match load_config(opts) {
+		Ok(config) => config,
+		Err(e) => {
+			eprintln!("{}", e);
+			return;
+		}
+	};
+	if config.profile {
+		// Frame timings are logged at the debug level, so they'd be hidden at the default level
+		if log::max_level() < log::LevelFilter::Debug {
+			log::set_max_level(log::LevelFilter::Debug);
+		}
 		compositor::PROFILE_OUTPUT.store(true, std::sync::atomic::Ordering::Relaxed);
 		festus::set_profile_output_enable(true);
 	}
-	if opts.debug {
+	if config.debug {
 		compositor::DEBUG_OUTPUT.store(true, std::sync::atomic::Ordering::Relaxed);
 	}
-	match opts.backend.as_str() {
+	let backend = match config.backend.clone() {
+		Some(backend) => backend,
+		None => {
+			eprintln!("No backend was selected, pass one with --backend or set it in the config file");
+			return;
+		}
+	};
+	match backend.as_str() {
 		"winit" => {
-			start_winit_compositor(event_loop);
+			start_winit_compositor(event_loop, config);
 		}
 		"vk_display" => {
-			unimplemented!() //start_vk_display_compositor(event_loop);
+			unimplemented!() //start_vk_display_compositor(event_loop, config);
 		}
 		"drm" => {
-			start_drm_compositor(event_loop);
+			start_drm_compositor(event_loop, config);
 		}
 		u => {
 			eprintln!("Unknown backend '{}'", u);
@@ -79,14 +128,30 @@ fn main() {
 	//let kb = libinput.path_add_device("/dev/input/by-id/ckb-Corsair_Gaming_K70_LUX_RGB_Keyboard_vKB_-event").unwrap();
 }
 
+/// Load the config file and override its options with the ones given on the command line
+fn load_config(opts: Opts) -> Result<Config, config::ConfigError> {
+	let mut config = Config::load()?;
+	if let Some(backend) = opts.backend {
+		config.backend = Some(backend);
+	}
+	config.profile |= opts.profile;
+	config.debug |= opts.debug;
+	if let Some(max_fps) = opts.max_fps {
+		config.max_fps = Some(max_fps);
+	}
+	config.bench = opts.bench;
+	if let Some(ipc_socket) = opts.ipc_socket {
+		config.ipc_socket = Some(ipc_socket);
+	}
+	// Transforms given later override earlier ones for the same output, so these take precedence over the config file
+	config.output_transforms.extend(opts.output_transforms);
+	Ok(config)
+}
+
 #[allow(unused)]
-fn start_winit_compositor(event_loop: calloop::EventLoop<()>) {
+fn start_winit_compositor(event_loop: calloop::EventLoop<()>, config: Config) {
 	let winit_event_loop = winit::event_loop::EventLoop::new();
-	let window = winit::window::WindowBuilder::new()
-		.with_inner_size(winit::dpi::PhysicalSize::new(1080, 720))
-		.with_resizable(false)
-		.build(&winit_event_loop)
-		.unwrap();
+	let window = create_window(&winit_event_loop);
 	let window_size = window.inner_size();
 	let (mut renderer, mut present_backend, window) = festus::renderer::Renderer::new::<
 		SwapchainPresentBackend<WinitSurfaceCreator>,
@@ -94,24 +159,46 @@ fn start_winit_compositor(event_loop: calloop::EventLoop<()>) {
 	.expect("Failed to initialize renderer");
 
 	let graphics_backend = VulkanGraphicsBackend::new(renderer, present_backend);
+	run_windowed_compositor(graphics_backend, winit_event_loop, window, config);
+}
+
+fn create_window(winit_event_loop: &winit::event_loop::EventLoop<()>) -> winit::window::Window {
+	winit::window::WindowBuilder::new()
+		.with_title("wally")
+		.with_inner_size(winit::dpi::PhysicalSize::new(1080, 720))
+		.with_resizable(true)
+		.build(winit_event_loop)
+		.unwrap()
+}
 
-	let (tx, rx) = std::sync::mpsc::channel();
-	std::thread::Builder::new()
-		.name(String::from("winit_compositor"))
-		.spawn(move || {
-			let input_backend = WinitInputBackend::new();
-			let sender = input_backend.get_sender();
-			tx.send(sender);
-			let mut event_loop = calloop::EventLoop::new().expect("Failed to create event loop");
-			let handle = event_loop.handle();
-			let mut compositor = compositor::Compositor::new(input_backend, graphics_backend, handle)
-				.expect("Failed to initialize compositor");
-			compositor.init();
-			compositor.start(&mut event_loop);
-		})
-		.unwrap();
-	let sender = rx.recv().unwrap();
-	WinitInputBackend::start(sender, winit_event_loop, window);
+/// Run the compositor on the current thread, along with the window it presents to. The window's event loop is
+/// pumped by the input backend every frame instead of running on its own.
+fn run_windowed_compositor<P: PresentBackend + 'static>(
+	graphics_backend: VulkanGraphicsBackend<P>,
+	winit_event_loop: winit::event_loop::EventLoop<()>,
+	window: std::sync::Arc<winit::window::Window>,
+	config: Config,
+) {
+	if let Some(frames) = config.bench {
+		// The window only has to stay open while rendering, so its events don't need to be handled
+		run_benchmark(graphics_backend, frames);
+		drop(window);
+		return;
+	}
+	let input_backend = WinitInputBackend::new(winit_event_loop, window);
+	let mut event_loop = calloop::EventLoop::new().expect("Failed to create event loop");
+	let handle = event_loop.handle();
+	let mut compositor = match compositor::Compositor::new(input_backend, graphics_backend, config, handle) {
+		Ok(compositor) => compositor,
+		Err(e) => {
+			eprintln!("Failed to initialize compositor: {}", e);
+			return;
+		}
+	};
+	// Clients started from wally's environment connect to it without being told where
+	std::env::set_var("WAYLAND_DISPLAY", compositor.socket_name());
+	compositor.init();
+	compositor.start(&mut event_loop);
 }
 
 /* #[allow(unused)]
@@ -130,21 +217,74 @@ fn start_vk_display_compositor(event_loop: calloop::EventLoop<()>) {
 } */
 
 #[allow(unused)]
-fn start_drm_compositor(event_loop: calloop::EventLoop<()>) {
+fn start_drm_compositor(event_loop: calloop::EventLoop<()>, config: Config) {
 	let (mut renderer, mut present_backend, window) =
 		festus::renderer::Renderer::new::<DrmPresentBackend>(Size::new(1920, 1080), ())
 			.expect("Failed to initialize renderer");
 	let mut event_loop = calloop::EventLoop::new().expect("Failed to create event loop");
 	let graphics_backend = VulkanGraphicsBackend::new(renderer, present_backend);
+	if let Some(frames) = config.bench {
+		run_benchmark(graphics_backend, frames);
+		return;
+	}
 	let input_backend =
 		backend::libinput::LibinputInputBackend::new(event_loop.handle()).expect("Failed to create libinput backend");
-	let mut compositor = compositor::Compositor::new(input_backend, graphics_backend, event_loop.handle())
-		.expect("Failed to initialize compositor");
+	let mut compositor = match compositor::Compositor::new(input_backend, graphics_backend, config, event_loop.handle())
+	{
+		Ok(compositor) => compositor,
+		Err(e) => {
+			eprintln!("Failed to initialize compositor: {}", e);
+			return;
+		}
+	};
+	std::env::set_var("WAYLAND_DISPLAY", compositor.socket_name());
 	compositor.init();
 	compositor.start(&mut event_loop);
 }
 
-fn setup_logging() {
+/// Render the benchmark scene with the given graphics backend and print its timing
+fn run_benchmark<G: GraphicsBackend + 'static>(graphics_backend: G, frames: u32) {
+	let mut renderer = match Renderer::init(graphics_backend) {
+		Ok(renderer) => renderer,
+		Err(e) => {
+			eprintln!("Failed to initialize renderer: {}", e);
+			return;
+		}
+	};
+	match renderer.run_benchmark(frames) {
+		Ok(stats) => println!("{}", stats),
+		Err(e) => eprintln!("Benchmark failed: {}", e),
+	}
+	if let Err(e) = renderer.shutdown() {
+		eprintln!("Failed to shut down the renderer: {}", e);
+	}
+}
+
+/// Get the level to log at, which is the level in `RUST_LOG` or info if it isn't set, raised once for every -v and
+/// lowered once for every -q
+fn log_level(opts: &Opts) -> log::LevelFilter {
+	const LEVELS: [log::LevelFilter; 6] = [
+		log::LevelFilter::Off,
+		log::LevelFilter::Error,
+		log::LevelFilter::Warn,
+		log::LevelFilter::Info,
+		log::LevelFilter::Debug,
+		log::LevelFilter::Trace,
+	];
+	let base = match std::env::var("RUST_LOG") {
+		Ok(level) => level.parse().unwrap_or_else(|_| {
+			eprintln!("Ignoring RUST_LOG, '{}' isn't a log level", level);
+			log::LevelFilter::Info
+		}),
+		Err(_) => log::LevelFilter::Info,
+	};
+	let index = (base as i64 + i64::from(opts.verbose) - i64::from(opts.quiet))
+		.max(0)
+		.min(5);
+	LEVELS[index as usize]
+}
+
+fn setup_logging(level: log::LevelFilter) {
 	let colors = Box::new(fern::colors::ColoredLevelConfig::new())
 		.info(fern::colors::Color::Blue)
 		.warn(fern::colors::Color::Yellow)
@@ -156,4 +296,6 @@ fn setup_logging() {
 		.chain(std::io::stderr())
 		.apply()
 		.expect("Failed to setup logging dispatch");
+	// The dispatch lets everything through, so the level can still be raised after this
+	log::set_max_level(level);
 }
@@ -7,14 +7,29 @@ use wayland_server::protocol::*;
 
 use crate::{
 	backend::{GraphicsBackend, RgbaInfo, Vertex},
-	compositor::{prelude::*, surface::SurfaceData},
+	compositor::{
+		buffer::{self, BufferData},
+		prelude::*,
+		surface::SurfaceData,
+		CustomCursor,
+	},
+	geometry::{rect_contains, rect_intersection, rect_union},
+	renderer::hud::Hud,
 };
 
+pub mod bench;
+pub mod hud;
+
 #[derive(Debug)]
 pub struct Output<G: GraphicsBackend> {
 	handle: G::OutputHandle,
 	render_target_handle: G::RenderTargetHandle,
 	pub viewport: Rect,
+	/// The scale clients should render their buffers at for this output
+	pub scale: i32,
+	/// How the output is rotated or flipped. The viewport is in the output's transformed orientation, and the
+	/// scene is transformed back to the orientation of the output's hardware when it's rendered.
+	pub transform: wl_output::Transform,
 }
 
 // Deriving this doesn't work for some reason
@@ -24,18 +39,114 @@ impl<G: GraphicsBackend> Clone for Output<G> {
 			handle: self.handle,
 			render_target_handle: self.render_target_handle,
 			viewport: self.viewport,
+			scale: self.scale,
+			transform: self.transform,
 		}
 	}
 }
 impl<G: GraphicsBackend> Copy for Output<G> {}
 
+impl<G: GraphicsBackend> Output<G> {
+	/// Get the size of the output's hardware mode, which is also the size of its render target
+	pub fn mode_size(&self) -> Size {
+		if transform_is_rotated(self.transform) {
+			Size::new(self.viewport.height, self.viewport.width)
+		} else {
+			self.viewport.size()
+		}
+	}
+}
+
+/// Whether a transform swaps the width and height of what it is applied to
+fn transform_is_rotated(transform: wl_output::Transform) -> bool {
+	match transform {
+		wl_output::Transform::_90
+		| wl_output::Transform::_270
+		| wl_output::Transform::Flipped90
+		| wl_output::Transform::Flipped270 => true,
+		_ => false,
+	}
+}
+
+/// Get the matrix that applies an output transform to clip space coordinates. Clip space y points down, so turning
+/// the contents counter-clockwise as wl_output transforms do takes (x, y) to (y, -x).
+fn transform_matrix(transform: wl_output::Transform) -> Mat4 {
+	// The effect of the transform on the x and y axes, as (x from x, x from y, y from x, y from y)
+	let (xx, xy, yx, yy) = match transform {
+		wl_output::Transform::_90 => (0.0, 1.0, -1.0, 0.0),
+		wl_output::Transform::_180 => (-1.0, 0.0, 0.0, -1.0),
+		wl_output::Transform::_270 => (0.0, -1.0, 1.0, 0.0),
+		wl_output::Transform::Flipped => (-1.0, 0.0, 0.0, 1.0),
+		wl_output::Transform::Flipped90 => (0.0, 1.0, 1.0, 0.0),
+		wl_output::Transform::Flipped180 => (1.0, 0.0, 0.0, -1.0),
+		wl_output::Transform::Flipped270 => (0.0, -1.0, -1.0, 0.0),
+		_ => (1.0, 0.0, 0.0, 1.0),
+	};
+	#[rustfmt::skip]
+	let matrix = Mat4::new(
+		xx, xy, 0.0, 0.0,
+		yx, yy, 0.0, 0.0,
+		0.0, 0.0, 1.0, 0.0,
+		0.0, 0.0, 0.0, 1.0,
+	);
+	matrix
+}
+
+/// The number of graphics backend resources created through the renderer that haven't been destroyed yet. Every
+/// resource should eventually be destroyed, so these counts are useful for catching leaks caused by client churn.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceCounts {
+	pub textures: usize,
+	pub vertex_buffers: usize,
+	pub mvp_buffers: usize,
+	pub render_targets: usize,
+}
+
+/// Every plane is drawn as this unit quad, scaled and positioned by the plane's MVP
+const QUAD_VERTICES: [Vertex; 4] = [
+	Vertex {
+		pos: [0.0, 0.0, 0.0],
+		uv: [0.0, 0.0],
+	},
+	Vertex {
+		pos: [1.0, 0.0, 0.0],
+		uv: [1.0, 0.0],
+	},
+	Vertex {
+		pos: [0.0, 1.0, 0.0],
+		uv: [0.0, 1.0],
+	},
+	Vertex {
+		pos: [1.0, 1.0, 0.0],
+		uv: [1.0, 1.0],
+	},
+];
+const QUAD_INDICES: [u32; 6] = [0, 1, 2, 1, 2, 3];
+
 pub struct Renderer<G: GraphicsBackend> {
 	// TODO not pub, b/c soundness (this should be fixable once output infrastructure is in place)
 	pub(crate) backend: G,
 	// TODO: reorganize this to prevent cloning of this all the time to avoid borrow check issues
 	outputs: Vec<Output<G>>,
+	/// The vertex buffer containing the unit quad shared by all planes
+	quad_vertex_buffer: G::VertexBufferHandle,
 	// This should always be some, and is only optional for initialization purposes
 	cursor_plane: Option<Plane<G>>,
+	/// A translucent black plane drawn over the surfaces of unresponsive clients
+	dim_plane: Option<Plane<G>>,
+	/// The debug HUD, which is only present while it is enabled
+	hud: Option<Hud<G>>,
+	resource_counts: ResourceCounts,
+	/// Client buffers read while building the frame that hasn't been presented yet
+	frame_buffers: Vec<wl_buffer::WlBuffer>,
+	/// Client buffers read by frames that have been presented but might still be in use by the GPU. These are
+	/// released once the backend reports that those frames have finished.
+	in_flight_buffers: Vec<wl_buffer::WlBuffer>,
+	/// The id given to the next plane that's created
+	next_plane_id: u64,
+	/// What was drawn to each output in the last frame, in the same order as the outputs. This is compared to the
+	/// next frame to find the part of each output that has to be drawn again.
+	output_frames: Vec<OutputFrame>,
 }
 
 impl<G: GraphicsBackend> Renderer<G> {
@@ -55,15 +166,33 @@ impl<G: GraphicsBackend> Renderer<G> {
 					handle,
 					render_target_handle,
 					viewport,
+					scale: 1,
+					transform: wl_output::Transform::Normal,
 				};
 				Ok(output)
 			})
 			.collect::<Result<Vec<_>, _>>()?;
 
+		let quad_vertex_buffer = backend.create_vertex_buffer(&QUAD_VERTICES, &QUAD_INDICES)?;
+
+		let resource_counts = ResourceCounts {
+			render_targets: outputs.len(),
+			vertex_buffers: 1,
+			..Default::default()
+		};
+
 		let mut renderer = Self {
 			backend,
 			outputs,
+			quad_vertex_buffer,
 			cursor_plane: None,
+			dim_plane: None,
+			hud: None,
+			resource_counts,
+			frame_buffers: Vec::new(),
+			in_flight_buffers: Vec::new(),
+			next_plane_id: 0,
+			output_frames: Vec::new(),
 		};
 
 		// Load the cursor image
@@ -83,11 +212,35 @@ impl<G: GraphicsBackend> Renderer<G> {
 			},
 		)?;
 		renderer.cursor_plane = Some(cursor_plane);
+
+		let dim_plane = renderer.create_plane_from_rgba(
+			Rect::new(0, 0, 1, 1),
+			RgbaInfo {
+				width: 1,
+				height: 1,
+				data: &[0, 0, 0, 128],
+			},
+		)?;
+		renderer.dim_plane = Some(dim_plane);
 		Ok(renderer)
 	}
 
 	pub fn update(&mut self) -> Result<(), G::Error> {
-		self.backend.update()
+		self.backend.update()?;
+		if !self.in_flight_buffers.is_empty() && self.backend.frames_finished()? {
+			for buffer in self.in_flight_buffers.drain(..) {
+				buffer::release_buffer::<G::ShmBuffer>(&buffer);
+			}
+		}
+		Ok(())
+	}
+
+	/// Release a client buffer once the GPU is done with every frame that might have read it. The buffer is held
+	/// until the frame being built is presented and has finished, so clients can't write to it while it's read. This
+	/// takes over the hold of the surface the buffer was committed to, so the buffer is only released once every
+	/// other surface it's attached to is done with it too.
+	pub fn release_buffer_after_frame(&mut self, buffer: wl_buffer::WlBuffer) {
+		self.frame_buffers.push(buffer);
 	}
 
 	pub fn create_shm_pool(&mut self, fd: RawFd, size: usize) -> Result<G::ShmPool, G::Error> {
@@ -116,41 +269,40 @@ impl<G: GraphicsBackend> Renderer<G> {
 		geometry: Rect,
 		texture_handle: G::TextureHandle,
 	) -> Result<Plane<G>, G::Error> {
-		let vertices = &[
-			Vertex {
-				pos: [0.0, 0.0, 0.0],
-				uv: [0.0, 0.0],
-			},
-			Vertex {
-				pos: [1.0, 0.0, 0.0],
-				uv: [1.0, 0.0],
-			},
-			Vertex {
-				pos: [0.0, 1.0, 0.0],
-				uv: [0.0, 1.0],
-			},
-			Vertex {
-				pos: [1.0, 1.0, 0.0],
-				uv: [1.0, 1.0],
-			},
-		];
-		let indices = &[0, 1, 2, 1, 2, 3];
-		let vertex_buffer_handle = self.backend.create_vertex_buffer(vertices, indices)?;
 		// Use a dummy view size since it will be overwritten before drawing anyway
-		let mvp_buffer_handle = self
-			.backend
-			.create_mvp_buffer(self.create_mvp(Size::new(1, 1), geometry))?;
+		let mvp_buffer_handle =
+			self.backend
+				.create_mvp_buffer(self.create_mvp(Size::new(1, 1), geometry, wl_output::Transform::Normal))?;
+		self.resource_counts.mvp_buffers += 1;
 		let plane = Plane {
-			vertex_buffer_handle,
+			id: self.next_plane_id,
 			mvp_buffer_handle,
 			texture_handle,
 		};
+		self.next_plane_id += 1;
 		Ok(plane)
 	}
 
-	fn create_mvp(&self, view_size: Size, geometry: Rect) -> [[[f32; 4]; 4]; 3] {
-		let pos = Point2::from(geometry.point());
-		let size = Vec2::from(geometry.size());
+	fn create_mvp(&self, view_size: Size, geometry: Rect, transform: wl_output::Transform) -> [[[f32; 4]; 4]; 3] {
+		self.create_mvp_at(
+			view_size,
+			(geometry.x as f64, geometry.y as f64),
+			geometry.size(),
+			transform,
+		)
+	}
+
+	/// Create an mvp for a quad of the given size positioned at a possibly fractional position, on an output with
+	/// the given view size and transform
+	fn create_mvp_at(
+		&self,
+		view_size: Size,
+		position: (f64, f64),
+		size: Size,
+		transform: wl_output::Transform,
+	) -> [[[f32; 4]; 4]; 3] {
+		let pos = Point2::new(position.0 as f32, position.1 as f32);
+		let size = Vec2::from(size);
 		let view_size = Vec2::from(view_size);
 
 		let scale = Mat4::new_nonuniform_scaling(&Vec3::new(size.x, size.y, 1.0));
@@ -165,7 +317,7 @@ impl<G: GraphicsBackend> Renderer<G> {
 		let mvp = festus::renderer::Mvp {
 			model: model,
 			view: view.to_homogeneous(),
-			projection: *projection.as_matrix(),
+			projection: transform_matrix(transform) * projection.as_matrix(),
 		};
 
 		mvp.into()
@@ -174,11 +326,21 @@ impl<G: GraphicsBackend> Renderer<G> {
 	/// Create a new plane positioned at `Point` from the given Rgba data
 	pub fn create_plane_from_rgba(&mut self, geometry: Rect, rgba: RgbaInfo) -> Result<Plane<G>, G::Error> {
 		let texture_handle = self.backend.create_texture_from_rgba(rgba)?;
-		self.create_plane_with_texture(geometry, texture_handle)
+		self.resource_counts.textures += 1;
+		match self.create_plane_with_texture(geometry, texture_handle) {
+			Ok(plane) => Ok(plane),
+			Err(e) => {
+				self.destroy_texture(texture_handle)?;
+				Err(e)
+			}
+		}
 	}
 
 	pub fn create_surface_renderer_data(&mut self) -> Result<SurfaceRendererData<G>, G::Error> {
-		Ok(SurfaceRendererData { plane: None })
+		Ok(SurfaceRendererData {
+			plane: None,
+			texture_buffer: None,
+		})
 	}
 
 	// TODO: handle other sorts of buffers (DMA buffers!)
@@ -186,9 +348,12 @@ impl<G: GraphicsBackend> Renderer<G> {
 		&mut self,
 		wl_buffer: wl_buffer::WlBuffer,
 	) -> Result<G::TextureHandle, G::Error> {
-		let buffer_data = wl_buffer.get_synced::<G::ShmBuffer>();
+		let buffer_data = wl_buffer.get_synced::<BufferData<G::ShmBuffer>>();
 		let buffer_data_lock = &mut *buffer_data.lock().unwrap();
-		let texture_handle = self.backend.create_texture_from_shm_buffer(buffer_data_lock)?;
+		let texture_handle = self
+			.backend
+			.create_texture_from_shm_buffer(&buffer_data_lock.shm_buffer)?;
+		self.resource_counts.textures += 1;
 		Ok(texture_handle)
 	}
 
@@ -196,19 +361,254 @@ impl<G: GraphicsBackend> Renderer<G> {
 		self.outputs.clone()
 	}
 
-	pub fn render_scene<'a, F: Fn(SceneRenderState<G>) -> Result<(), G::Error>>(
-		&'a mut self,
-		f: F,
-	) -> Result<(), G::Error> {
-		for output in self.outputs.clone() {
+	/// Get the bounding rectangle of all output viewports, or None if there are no outputs
+	pub fn desktop_bounds(&self) -> Option<Rect> {
+		self.outputs
+			.iter()
+			.map(|output| output.viewport)
+			.fold(None, |bounds, viewport| {
+				Some(match bounds {
+					Some(bounds) => rect_union(bounds, viewport),
+					None => viewport,
+				})
+			})
+	}
+
+	/// Read back the last frame rendered to the output at `index` as RGBA8 pixels with a stride of `width * 4`
+	pub fn read_output(&mut self, index: usize) -> Option<Result<(Size, Vec<u8>), G::Error>> {
+		let output = *self.outputs.get(index)?;
+		Some(
+			self.backend
+				.read_render_target(output.render_target_handle)
+				.map(|data| (output.mode_size(), data)),
+		)
+	}
+
+	/// Resize the output at `index` to a new mode size, recreating its render target and moving the other outputs so
+	/// that the viewports stay placed next to each other horizontally
+	pub fn resize_output(&mut self, index: usize, size: Size) -> Result<(), G::Error> {
+		let output = match self.outputs.get(index) {
+			Some(output) => *output,
+			None => {
+				log::warn!("Tried to resize nonexistent output {}", index);
+				return Ok(());
+			}
+		};
+		if size.width == 0 || size.height == 0 {
+			// This happens when a window is minimized, just keep the old render target around
+			return Ok(());
+		}
+		if output.mode_size() == size {
+			return Ok(());
+		}
+		let render_target_handle = self.backend.create_render_target(size)?;
+		self.resource_counts.render_targets += 1;
+		// Switch to the new render target first, so it's still used and destroyed later if destroying the old one fails
+		self.outputs[index].render_target_handle = render_target_handle;
+		self.set_output_viewport_size(index, size);
+		self.destroy_render_target(output.render_target_handle)
+	}
+
+	/// Get the indices of the outputs the graphics backend doesn't have anymore, like monitors that were unplugged.
+	/// The backend can't give info about an output that's gone, so that's what this checks for.
+	pub fn disconnected_outputs(&self) -> Vec<usize> {
+		self.outputs
+			.iter()
+			.enumerate()
+			.filter(|(_index, output)| self.backend.get_output_info(output.handle).is_err())
+			.map(|(index, _output)| index)
+			.collect()
+	}
+
+	/// Remove the output at `index` and destroy its render target. The outputs after it are moved over to close the
+	/// gap it leaves.
+	pub fn remove_output(&mut self, index: usize) -> Result<(), G::Error> {
+		if index >= self.outputs.len() {
+			log::warn!("Tried to remove nonexistent output {}", index);
+			return Ok(());
+		}
+		let output = self.outputs.remove(index);
+		if index < self.output_frames.len() {
+			self.output_frames.remove(index);
+		}
+		self.lay_out_outputs();
+		self.destroy_render_target(output.render_target_handle)
+	}
+
+	/// Set the transform of the output at `index`. The output keeps its render target, but its viewport is rotated
+	/// to match the transform, and the other outputs are moved to stay next to it.
+	pub fn set_output_transform(&mut self, index: usize, transform: wl_output::Transform) {
+		let mode_size = match self.outputs.get(index) {
+			Some(output) => output.mode_size(),
+			None => {
+				log::warn!("Tried to set the transform of nonexistent output {}", index);
+				return;
+			}
+		};
+		self.outputs[index].transform = transform;
+		self.set_output_viewport_size(index, mode_size);
+	}
+
+	/// Set the viewport size of the output at `index` from its mode size and transform, and lay the outputs out
+	/// next to each other horizontally again
+	fn set_output_viewport_size(&mut self, index: usize, mode_size: Size) {
+		let output = &mut self.outputs[index];
+		if transform_is_rotated(output.transform) {
+			output.viewport.width = mode_size.height;
+			output.viewport.height = mode_size.width;
+		} else {
+			output.viewport.width = mode_size.width;
+			output.viewport.height = mode_size.height;
+		}
+		self.lay_out_outputs();
+	}
+
+	/// Place the output viewports next to each other horizontally, in order
+	fn lay_out_outputs(&mut self) {
+		let mut current_width = 0;
+		for output in &mut self.outputs {
+			output.viewport.x = current_width as i32;
+			current_width += output.viewport.width;
+		}
+	}
+
+	pub fn resource_counts(&self) -> ResourceCounts {
+		self.resource_counts
+	}
+
+	/// Get the live resources that don't belong to the renderer itself, which are the ones owned by surfaces. Once
+	/// every surface is destroyed, anything counted here has leaked.
+	pub fn surface_resource_counts(&self) -> ResourceCounts {
+		let planes = self.cursor_plane.iter().count() + self.dim_plane.iter().count() + self.hud.iter().count();
+		ResourceCounts {
+			textures: self.resource_counts.textures.saturating_sub(planes),
+			vertex_buffers: self.resource_counts.vertex_buffers.saturating_sub(1),
+			mvp_buffers: self.resource_counts.mvp_buffers.saturating_sub(planes),
+			render_targets: self.resource_counts.render_targets.saturating_sub(self.outputs.len()),
+		}
+	}
+
+	/// Get the number of bytes of memory used by the graphics backend's live textures, vertex buffers and MVP
+	/// buffers
+	pub fn memory_usage(&self) -> u64 {
+		self.backend.memory_usage()
+	}
+
+	/// Build the scene by calling `f` once, then draw the scene to the render target of every output. Work that
+	/// should happen once per frame, like uploading textures and sending frame callbacks, is done while the
+	/// scene is built, so it doesn't depend on the number of outputs.
+	///
+	/// Only the part of each output that changed since the last frame is drawn again, if the graphics backend can
+	/// keep the rest of the render target's contents. Outputs where nothing changed aren't drawn at all.
+	pub fn render_scene<F>(&mut self, f: F) -> Result<(), G::Error>
+	where
+		F: FnOnce(&mut SceneRenderState<G>) -> Result<(), G::Error>,
+	{
+		let mut scene_render_state = SceneRenderState {
+			renderer: self,
+			quads: Vec::new(),
+		};
+		f(&mut scene_render_state)?;
+		let quads = scene_render_state.quads;
+
+		// If drawing fails partway through, the render targets are in an unknown state, so every output is drawn
+		// completely in the next frame
+		let previous_frames = std::mem::take(&mut self.output_frames);
+		let mut frames = Vec::with_capacity(self.outputs.len());
+		for (index, output) in self.outputs.clone().into_iter().enumerate() {
+			let placements = quads
+				.iter()
+				.map(|quad| quad.placement(output.scale))
+				.collect::<Vec<_>>();
+			// Find the quads that can be seen on this output
+			let mut visible = Vec::new();
+			let mut drawn_quads = Vec::new();
+			let mut contents_changed = Vec::new();
+			for (i, quad) in quads.iter().enumerate() {
+				let (position, size) = placements[i];
+				let bounds = quad_bounds(position, size);
+				if !bounds.intersects(output.viewport) {
+					continue;
+				}
+				// Quads hidden behind an opaque quad drawn after them can't be seen
+				let occluded = quads[i + 1..]
+					.iter()
+					.zip(&placements[i + 1..])
+					.filter(|(above, _placement)| above.opaque)
+					.filter_map(|(_above, &(position, size))| quad_interior(position, size))
+					.any(|interior| rect_contains(interior, bounds));
+				if occluded {
+					continue;
+				}
+				// A plane can be drawn more than once in a frame, like the HUD on every output
+				let occurrence = quads[..i]
+					.iter()
+					.filter(|other| other.plane_id == quad.plane_id)
+					.count();
+				visible.push(i);
+				drawn_quads.push(DrawnQuad {
+					key: (quad.plane_id, occurrence),
+					bounds,
+				});
+				contents_changed.push(quad.contents_changed);
+			}
+
+			let previous_frame = previous_frames.get(index).filter(|frame| {
+				frame.viewport == output.viewport && frame.transform == output.transform && frame.scale == output.scale
+			});
+			let damage = match previous_frame {
+				Some(frame) => frame_damage(&frame.quads, &drawn_quads, &contents_changed, output.viewport),
+				None => Some(output.viewport),
+			};
+			frames.push(OutputFrame {
+				viewport: output.viewport,
+				transform: output.transform,
+				scale: output.scale,
+				quads: drawn_quads.clone(),
+			});
+			let damage = match damage {
+				Some(damage) => damage,
+				// The render target still holds the last frame
+				None => continue,
+			};
+
 			unsafe {
-				self.backend.begin_render_pass(output.render_target_handle)?;
-				let scene_render_state = SceneRenderState { renderer: self };
-				// TODO: This should really be an FnOnce and not be called in a loop
-				f(scene_render_state)?;
+				// The scissor is in render target coordinates, which only match the viewport's orientation if the
+				// output isn't transformed
+				let scissored = if damage != output.viewport && output.transform == wl_output::Transform::Normal {
+					let scissor = Rect::new(
+						damage.x - output.viewport.x,
+						damage.y - output.viewport.y,
+						damage.width,
+						damage.height,
+					);
+					self.backend
+						.begin_scissored_render_pass(output.render_target_handle, scissor)?
+				} else {
+					self.backend.begin_render_pass(output.render_target_handle)?;
+					false
+				};
+				for (&i, drawn_quad) in visible.iter().zip(&drawn_quads) {
+					if scissored && !drawn_quad.bounds.intersects(damage) {
+						continue;
+					}
+					let quad = &quads[i];
+					let (position, size) = placements[i];
+					let output_local_position = (
+						position.0 - f64::from(output.viewport.x),
+						position.1 - f64::from(output.viewport.y),
+					);
+					let mvp = self.create_mvp_at(output.viewport.size(), output_local_position, size, output.transform);
+					if let Some(mvp_map) = self.backend.map_mvp_buffer(quad.mvp_buffer_handle) {
+						*mvp_map = mvp;
+					}
+					self.backend
+						.draw(self.quad_vertex_buffer, quad.texture_handle, quad.mvp_buffer_handle)?;
+				}
 				self.backend.end_render_pass(output.render_target_handle)?;
 			}
 		}
+		self.output_frames = frames;
 
 		Ok(())
 	}
@@ -218,32 +618,80 @@ impl<G: GraphicsBackend> Renderer<G> {
 			let render_target_handle = output.render_target_handle;
 			self.backend.present_target(output.handle, render_target_handle)?;
 		}
+		self.in_flight_buffers.append(&mut self.frame_buffers);
 		Ok(())
 	}
 
 	pub fn destroy_vertex_buffer(&mut self, handle: G::VertexBufferHandle) -> Result<(), G::Error> {
-		self.backend.destroy_vertex_buffer(handle)
+		self.backend.destroy_vertex_buffer(handle)?;
+		self.resource_counts.vertex_buffers -= 1;
+		Ok(())
 	}
 
 	pub fn destroy_mvp_buffer(&mut self, handle: G::MvpBufferHandle) -> Result<(), G::Error> {
-		self.backend.destroy_mvp_buffer(handle)
+		self.backend.destroy_mvp_buffer(handle)?;
+		self.resource_counts.mvp_buffers -= 1;
+		Ok(())
 	}
 
 	pub fn destroy_texture(&mut self, handle: G::TextureHandle) -> Result<(), G::Error> {
-		self.backend.destroy_texture(handle)
+		self.backend.destroy_texture(handle)?;
+		self.resource_counts.textures -= 1;
+		Ok(())
 	}
 
 	pub fn destroy_render_target(&mut self, handle: G::RenderTargetHandle) -> Result<(), G::Error> {
-		self.backend.destroy_render_target(handle)
+		self.backend.destroy_render_target(handle)?;
+		self.resource_counts.render_targets -= 1;
+		Ok(())
+	}
+
+	/// Destroy every resource owned by the renderer and shut down the graphics backend. Surfaces own their
+	/// renderer data, so they have to be destroyed before this is called. The renderer can't be used afterwards.
+	pub fn shutdown(&mut self) -> Result<(), G::Error> {
+		if let Some(hud) = self.hud.take() {
+			self.destroy_plane(hud.plane)?;
+		}
+		if let Some(cursor_plane) = self.cursor_plane.take() {
+			self.destroy_plane(cursor_plane)?;
+		}
+		if let Some(dim_plane) = self.dim_plane.take() {
+			self.destroy_plane(dim_plane)?;
+		}
+		self.destroy_vertex_buffer(self.quad_vertex_buffer)?;
+		for output in std::mem::take(&mut self.outputs) {
+			self.destroy_render_target(output.render_target_handle)?;
+		}
+		if self.resource_counts != ResourceCounts::default() {
+			log::warn!("Renderer resources leaked at shutdown: {:?}", self.resource_counts);
+		}
+		let result = self.backend.shutdown();
+		// Nothing is drawn after this, so every buffer can be released now
+		for buffer in self.in_flight_buffers.drain(..).chain(self.frame_buffers.drain(..)) {
+			buffer::release_buffer::<G::ShmBuffer>(&buffer);
+		}
+		result
 	}
 
 	pub fn destroy_plane(&mut self, plane: Plane<G>) -> Result<(), G::Error> {
-		self.destroy_vertex_buffer(plane.vertex_buffer_handle)?;
 		self.destroy_mvp_buffer(plane.mvp_buffer_handle)?;
 		self.destroy_texture(plane.texture_handle)?;
 		Ok(())
 	}
 
+	/// Destroy the plane a surface is drawn with, keeping its renderer data so the surface can be drawn again once
+	/// it's given a new buffer
+	pub fn release_surface_plane(
+		&mut self,
+		surface_renderer_data: &mut SurfaceRendererData<G>,
+	) -> Result<(), G::Error> {
+		surface_renderer_data.texture_buffer = None;
+		if let Some(plane) = surface_renderer_data.plane.take() {
+			self.destroy_plane(plane)?;
+		}
+		Ok(())
+	}
+
 	pub fn destroy_surface_renderer_data(
 		&mut self,
 		surface_renderer_data: SurfaceRendererData<G>,
@@ -255,18 +703,21 @@ impl<G: GraphicsBackend> Renderer<G> {
 	}
 }
 
-/// A `Plane` represents a textured rectangle that can be drawn on a render target. It consists of a
-/// vertex buffer, an MVP (uniform) buffer, and a texture. In the future, the vertex buffer should be
-/// moved to be stored as a singleton in the renderer instance because it is never modified, and all
-/// manipulation of the drawing is done through the MVP buffer and the texture.
+/// A `Plane` represents a textured rectangle that can be drawn on a render target. It consists of an MVP
+/// (uniform) buffer and a texture. All planes are drawn with the renderer's shared quad vertex buffer, since it
+/// is never modified and all manipulation of the drawing is done through the MVP buffer and the texture.
 pub struct Plane<G: GraphicsBackend> {
-	vertex_buffer_handle: G::VertexBufferHandle,
+	/// Identifies the plane across frames, which the handles can't do since the backend may reuse them
+	id: u64,
 	mvp_buffer_handle: G::MvpBufferHandle,
 	texture_handle: G::TextureHandle,
 }
 
 pub struct SurfaceRendererData<G: GraphicsBackend> {
 	pub plane: Option<Plane<G>>,
+	/// The buffer that the plane's texture was last uploaded from. If the same buffer is committed again without
+	/// any damage, the texture is still up to date and the upload can be skipped.
+	pub texture_buffer: Option<wl_buffer::WlBuffer>,
 }
 
 /// SceneRenderState represents an in progress draw call.
@@ -274,75 +725,212 @@ pub struct SceneRenderState<'a, G: GraphicsBackend> {
 	// TODO! make this private and make all required methods available through a SceneRenderState impl, thus
 	// making this interface sound. Right now it is UNSOUND!! (like a lot of other things in this crate)
 	pub renderer: &'a mut Renderer<G>,
+	/// The quads that make up the scene, in the order they are drawn
+	quads: Vec<SceneQuad<G>>,
 }
 
-impl<'a, G: GraphicsBackend + 'static> SceneRenderState<'a, G> {
-	pub fn draw(
-		&mut self,
-		vertex_buffer: G::VertexBufferHandle,
-		texture: G::TextureHandle,
-		mvp: G::MvpBufferHandle,
-	) -> Result<(), G::Error> {
-		unsafe {
-			self.renderer.backend.draw(vertex_buffer, texture, mvp)?;
+/// A textured quad in the scene, positioned in global coordinates
+struct SceneQuad<G: GraphicsBackend> {
+	/// The id of the plane the quad is drawn with
+	plane_id: u64,
+	texture_handle: G::TextureHandle,
+	mvp_buffer_handle: G::MvpBufferHandle,
+	position: (f64, f64),
+	size: Size,
+	/// If set, the quad is scaled by the scale of each output it's drawn on, around this point relative to the
+	/// quad's top left corner, so it has the same physical size on every output
+	scale_origin: Option<(f64, f64)>,
+	/// Whether every pixel of the quad is fully opaque, which hides everything beneath it
+	opaque: bool,
+	/// Whether the plane's texture was changed since the last frame
+	contents_changed: bool,
+}
+
+impl<G: GraphicsBackend> SceneQuad<G> {
+	/// Create an unscaled quad that draws `plane` at the given position and size
+	fn new(plane: &Plane<G>, position: (f64, f64), size: Size) -> Self {
+		Self {
+			plane_id: plane.id,
+			texture_handle: plane.texture_handle,
+			mvp_buffer_handle: plane.mvp_buffer_handle,
+			position,
+			size,
+			scale_origin: None,
+			opaque: false,
+			contents_changed: false,
+		}
+	}
+
+	/// Get the global position and size of the quad when it's drawn on an output with the given scale
+	fn placement(&self, output_scale: i32) -> ((f64, f64), Size) {
+		match self.scale_origin {
+			Some(origin) if output_scale > 1 => {
+				let scale = f64::from(output_scale);
+				let position = (
+					self.position.0 + origin.0 - origin.0 * scale,
+					self.position.1 + origin.1 - origin.1 * scale,
+				);
+				let size = Size::new(
+					self.size.width * output_scale as u32,
+					self.size.height * output_scale as u32,
+				);
+				(position, size)
+			}
+			_ => (self.position, self.size),
 		}
-		Ok(())
+	}
+}
+
+/// Get the pixels a quad at the given position with the given size covers completely, or None if there are none
+fn quad_interior(position: (f64, f64), size: Size) -> Option<Rect> {
+	let x = position.0.ceil();
+	let y = position.1.ceil();
+	let right = (position.0 + f64::from(size.width)).floor();
+	let bottom = (position.1 + f64::from(size.height)).floor();
+	if right <= x || bottom <= y {
+		return None;
+	}
+	Some(Rect::new(x as i32, y as i32, (right - x) as u32, (bottom - y) as u32))
+}
+
+/// A quad that was drawn to an output, as remembered for finding what changed in the next frame
+#[derive(Debug, Clone, PartialEq)]
+struct DrawnQuad {
+	/// The id of the quad's plane and the number of times that plane was drawn before it in the same frame
+	key: (u64, usize),
+	bounds: Rect,
+}
+
+/// What was drawn to an output in a frame
+#[derive(Debug)]
+struct OutputFrame {
+	viewport: Rect,
+	transform: wl_output::Transform,
+	scale: i32,
+	/// The quads that could be seen on the output, in the order they were drawn
+	quads: Vec<DrawnQuad>,
+}
+
+/// Find the part of an output's viewport that has to be drawn again, given the quads drawn to it in the last frame,
+/// the quads that will be drawn to it now, and whether the contents of each of those changed. Quads that appeared,
+/// disappeared, moved, or changed damage both where they were and where they are now. If the quads that are in both
+/// frames are stacked in a different order, the whole viewport is damaged. Returns None if nothing changed.
+fn frame_damage(
+	previous: &[DrawnQuad],
+	current: &[DrawnQuad],
+	contents_changed: &[bool],
+	viewport: Rect,
+) -> Option<Rect> {
+	let in_previous = |quad: &&DrawnQuad| previous.iter().any(|other| other.key == quad.key);
+	let in_current = |quad: &&DrawnQuad| current.iter().any(|other| other.key == quad.key);
+	let kept_order = current.iter().filter(in_previous).map(|quad| quad.key);
+	if !kept_order.eq(previous.iter().filter(in_current).map(|quad| quad.key)) {
+		return Some(viewport);
+	}
+
+	let mut damage: Option<Rect> = None;
+	let mut add_damage = |rect: Rect| {
+		damage = Some(match damage {
+			Some(damage) => rect_union(damage, rect),
+			None => rect,
+		})
+	};
+	for (quad, &changed) in current.iter().zip(contents_changed) {
+		match previous.iter().find(|other| other.key == quad.key) {
+			Some(old) if old == quad && !changed => {}
+			Some(old) => {
+				add_damage(old.bounds);
+				add_damage(quad.bounds);
+			}
+			None => add_damage(quad.bounds),
+		}
+	}
+	for old in previous.iter().filter(|quad| !in_current(quad)) {
+		add_damage(old.bounds);
+	}
+	damage.and_then(|damage| rect_intersection(damage, viewport))
+}
+
+/// Get the pixels a quad at the given position with the given size touches
+fn quad_bounds(position: (f64, f64), size: Size) -> Rect {
+	let x = position.0.floor();
+	let y = position.1.floor();
+	let right = (position.0 + f64::from(size.width)).ceil();
+	let bottom = (position.1 + f64::from(size.height)).ceil();
+	Rect::new(x as i32, y as i32, (right - x) as u32, (bottom - y) as u32)
+}
+
+impl<'a, G: GraphicsBackend + 'static> SceneRenderState<'a, G> {
+	/// Add a quad with the given texture at a position in global coordinates to the top of the scene. The mvp
+	/// buffer is rewritten for every output the quad is drawn on.
+	pub fn draw(&mut self, plane: &Plane<G>, position: (f64, f64), size: Size) {
+		self.quads.push(SceneQuad::new(plane, position, size));
 	}
 
 	/// Draw a surface on
 	pub fn draw_surface(&mut self, surface: wl_surface::WlSurface) -> Result<(), G::Error> {
+		self.draw_surface_at(surface, None)
+	}
+
+	/// Draw a surface at `position` in global coordinates, or at the surface's own position if it's None
+	fn draw_surface_at(&mut self, surface: wl_surface::WlSurface, position: Option<Point>) -> Result<(), G::Error> {
 		let surface_data = surface.get_synced::<SurfaceData<G>>();
 		let surface_data_lock = &mut *surface_data.lock().unwrap();
 
 		// If the surface has been committed a buffer that hasn't been uploaded to the graphics
 		// backend yet, do that now.
 		// TODO: don't ignore the buffer/texture offset
+		let mut contents_changed = false;
 		if let Some(committed_buffer) = surface_data_lock.committed_buffer.take() {
-			let texture = self
-				.renderer
-				.create_texture_from_wl_buffer(committed_buffer.clone().0)
-				.unwrap();
-			if let Some(ref mut renderer_data) = surface_data_lock.renderer_data {
-				if let Some(ref mut plane) = renderer_data.plane {
-					let old_texture = std::mem::replace(&mut plane.texture_handle, texture);
-					self.renderer.destroy_texture(old_texture)?;
-				} else {
-					// Use a dummy value for the geometry because it will be overwritten before drawing TODO clean this up?
-					let plane = self
-						.renderer
-						.create_plane_with_texture(Rect::new(0, 0, 1, 1), texture)?;
-					renderer_data.plane = Some(plane);
-				}
+			let damaged = !std::mem::take(&mut surface_data_lock.damage).is_empty();
+			let renderer_data = surface_data_lock
+				.renderer_data
+				.as_mut()
+				.expect("Tried to draw a surface whose renderer data has been destroyed");
+			let up_to_date = renderer_data.plane.is_some()
+				&& renderer_data.texture_buffer.as_ref() == Some(&committed_buffer.0)
+				&& !damaged;
+			if up_to_date {
+				self.renderer.release_buffer_after_frame(committed_buffer.0);
 			} else {
-				panic!("Tried to draw a surface whose renderer data has been destroyed");
+				self.upload_surface_texture(renderer_data, committed_buffer.0)?;
+				contents_changed = true;
 			}
-			committed_buffer.0.release();
+		} else if surface_data_lock.buffer_size.is_none() {
+			// A null buffer was committed, which unmaps the surface, so its plane isn't needed anymore
+			let renderer_data = surface_data_lock
+				.renderer_data
+				.as_mut()
+				.expect("Tried to draw a surface whose renderer data has been destroyed");
+			self.renderer.release_surface_plane(renderer_data)?;
 		}
 
-		// If the surface has known geometry and a plane ready for drawing, write the geometry data to the surfaces MVP buffer and draw the surface
-		let surface_geometry_opt = surface_data_lock.try_get_surface_geometry();
-		if let Some(ref mut plane) = surface_data_lock
+		// If the surface has known geometry and a plane ready for drawing, add the surface to the scene
+		let surface_geometry_opt = match position {
+			Some(position) => surface_data_lock
+				.buffer_size
+				.map(|buffer_size| Rect::from((position, buffer_size))),
+			None => surface_data_lock.try_get_surface_geometry(),
+		};
+		if let Some(plane) = surface_data_lock
 			.renderer_data
-			.as_mut()
-			.and_then(|renderer_data| renderer_data.plane.as_mut())
+			.as_ref()
+			.and_then(|renderer_data| renderer_data.plane.as_ref())
 		{
-			if let Some(surface_geometry) = surface_geometry_opt {
-				for output in self.renderer.outputs.clone() {
-					if let Some(output_local_point) = get_local_coordinates(output.viewport, surface_geometry) {
-						let mut output_local_geometry = surface_geometry;
-						output_local_geometry.x = output_local_point.x;
-						output_local_geometry.y = output_local_point.y;
-						let mvp = self.renderer.create_mvp(output.viewport.size(), output_local_geometry);
-						self.renderer
-							.backend
-							.map_mvp_buffer(plane.mvp_buffer_handle)
-							.map(|mvp_map| *mvp_map = mvp);
-						self.draw(
-							plane.vertex_buffer_handle,
-							plane.texture_handle,
-							plane.mvp_buffer_handle,
-						)?;
-					}
+			// Zero size surfaces have nothing to draw
+			let visible_geometry = surface_geometry_opt.filter(|geometry| geometry.width > 0 && geometry.height > 0);
+			if let Some(surface_geometry) = visible_geometry {
+				let position = (f64::from(surface_geometry.x), f64::from(surface_geometry.y));
+				self.quads.push(SceneQuad {
+					opaque: surface_data_lock.is_opaque(),
+					contents_changed,
+					..SceneQuad::new(plane, position, surface_geometry.size())
+				});
+				// Surfaces of clients that stopped answering pings are dimmed until they answer again
+				let unresponsive = surface_data_lock.client_info.lock().unwrap().unresponsive;
+				if let Some(dim_plane) = self.renderer.dim_plane.as_ref().filter(|_| unresponsive) {
+					self.quads
+						.push(SceneQuad::new(dim_plane, position, surface_geometry.size()));
 				}
 			}
 		}
@@ -350,67 +938,75 @@ impl<'a, G: GraphicsBackend + 'static> SceneRenderState<'a, G> {
 		surface_data_lock
 			.callback
 			.take()
-			.map(|callback| callback.done(crate::compositor::get_input_serial()));
+			.map(|callback| callback.done(crate::backend::get_time_ms()));
+
+		Ok(())
+	}
 
+	/// Upload the contents of `buffer` to the texture of the surface's plane, creating the plane if necessary
+	fn upload_surface_texture(
+		&mut self,
+		renderer_data: &mut SurfaceRendererData<G>,
+		buffer: wl_buffer::WlBuffer,
+	) -> Result<(), G::Error> {
+		let texture = self.renderer.create_texture_from_wl_buffer(buffer.clone());
+		// The texture upload might still be reading the buffer until the frame finishes. The buffer has to be
+		// released even if the upload failed, or the client would wait for it forever.
+		self.renderer.release_buffer_after_frame(buffer.clone());
+		let texture = texture?;
+		if let Some(ref mut plane) = renderer_data.plane {
+			let old_texture = std::mem::replace(&mut plane.texture_handle, texture);
+			self.renderer.destroy_texture(old_texture)?;
+		} else {
+			// Use a dummy value for the geometry because it will be overwritten before drawing TODO clean this up?
+			match self.renderer.create_plane_with_texture(Rect::new(0, 0, 1, 1), texture) {
+				Ok(plane) => renderer_data.plane = Some(plane),
+				Err(e) => {
+					// The texture isn't referenced by any plane, so it has to be destroyed here
+					self.renderer.destroy_texture(texture)?;
+					return Err(e);
+				}
+			}
+		}
+		renderer_data.texture_buffer = Some(buffer);
 		Ok(())
 	}
 
-	pub fn draw_cursor(&mut self, position: Point) -> Result<(), G::Error> {
+	/// Draw the cursor with its hotspot at `position`. The position isn't rounded, so the cursor can be placed at
+	/// sub-pixel offsets. The hotspot is a whole number of pixels and is subtracted before anything else, so the
+	/// fractional part of the position is carried over to the cursor image unchanged. On outputs with a scale
+	/// greater than 1 the cursor is scaled up around its hotspot, so it has the same physical size on every output.
+	///
+	/// If a client set a cursor surface, that surface is drawn instead, at the pointer position rounded to a pixel.
+	pub fn draw_cursor(&mut self, position: (f64, f64), custom_cursor: Option<&CustomCursor>) -> Result<(), G::Error> {
+		if let Some(custom_cursor) = custom_cursor.filter(|custom_cursor| custom_cursor.surface.as_ref().is_alive()) {
+			let surface_position = Point::new(
+				position.0.round() as i32 - custom_cursor.hotspot.x,
+				position.1.round() as i32 - custom_cursor.hotspot.y,
+			);
+			return self.draw_surface_at(custom_cursor.surface.clone(), Some(surface_position));
+		}
+
 		// TODO: nah
 		const CURSOR_WIDTH: u32 = 24;
 		const CURSOR_HEIGHT: u32 = 24;
 		const CURSOR_HOTSPOT_X: i32 = 4;
 		const CURSOR_HOTSPOT_Y: i32 = 4;
-		let cursor_rect = Rect::new(
-			position.x - CURSOR_HOTSPOT_X,
-			position.y - CURSOR_HOTSPOT_Y,
-			CURSOR_WIDTH,
-			CURSOR_HEIGHT,
+		let cursor_position = (
+			position.0 - f64::from(CURSOR_HOTSPOT_X),
+			position.1 - f64::from(CURSOR_HOTSPOT_Y),
 		);
-
-		for output in self.renderer.outputs.clone() {
-			if let Some(output_local_coordinates) = get_local_coordinates(output.viewport, cursor_rect) {
-				let output_local_rect = Rect::new(
-					output_local_coordinates.x,
-					output_local_coordinates.y,
-					cursor_rect.width,
-					cursor_rect.height,
-				);
-				let mvp = self.renderer.create_mvp(output.viewport.size(), output_local_rect);
-				// I wrote this at 12:34 AM
-				if let Some((vertex_buffer_handle, texture_handle, mvp_buffer_handle)) =
-					if let Some(ref cursor_plane) = self.renderer.cursor_plane {
-						let mvp_map = self
-							.renderer
-							.backend
-							.map_mvp_buffer(cursor_plane.mvp_buffer_handle)
-							.unwrap();
-						*mvp_map = mvp;
-						Some((
-							cursor_plane.vertex_buffer_handle,
-							cursor_plane.texture_handle,
-							cursor_plane.mvp_buffer_handle,
-						))
-					} else {
-						None
-					} {
-					self.draw(vertex_buffer_handle, texture_handle, mvp_buffer_handle)?;
-				}
-			}
+		if let Some(ref cursor_plane) = self.renderer.cursor_plane {
+			self.quads.push(SceneQuad {
+				scale_origin: Some((f64::from(CURSOR_HOTSPOT_X), f64::from(CURSOR_HOTSPOT_Y))),
+				..SceneQuad::new(cursor_plane, cursor_position, Size::new(CURSOR_WIDTH, CURSOR_HEIGHT))
+			});
 		}
 
 		Ok(())
 	}
 }
 
-fn get_local_coordinates(viewport: Rect, rect: Rect) -> Option<Point> {
-	if rect.intersects(viewport) {
-		Some(Point::new(rect.x - viewport.x, rect.y - viewport.y))
-	} else {
-		None
-	}
-}
-
 #[derive(Debug, Error)]
 pub enum RendererError<G: GraphicsBackend + 'static>
 where
@@ -419,3 +1015,177 @@ where
 	#[error("An error occurred in the graphics backend")]
 	GraphicsBackendError(#[source] G::Error),
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_util::{
+		received_events, test_buffer, test_client, test_client_info, test_surface, TestBackend, TestShmBuffer,
+	};
+
+	/// Create a surface with a 10x10 buffer committed to it at the top left corner of the first output
+	fn mapped_surface(client: &Client) -> (wl_surface::WlSurface, Synced<SurfaceData<TestBackend>>) {
+		let buffer = test_buffer(client, 10, 10);
+		let (surface, surface_data) = test_surface(client, &test_client_info(client));
+		{
+			let mut surface_data_lock = surface_data.lock().unwrap();
+			buffer::hold_buffer::<TestShmBuffer>(&buffer);
+			surface_data_lock.committed_buffer = Some((buffer, Point::new(0, 0)));
+			surface_data_lock.buffer_size = Some(Size::new(10, 10));
+			surface_data_lock.position = Some(Point::new(0, 0));
+		}
+		(surface, surface_data)
+	}
+
+	#[test]
+	fn unmapped_surface_is_not_drawn() {
+		let mut display = Display::new();
+		let (client, _socket) = test_client(&mut display);
+		let mut renderer = Renderer::init(TestBackend::default()).unwrap();
+		// The renderer has textures of its own for the cursor and for dimming
+		let renderer_textures = renderer.backend.textures;
+
+		let (surface, surface_data) = mapped_surface(&client);
+		renderer
+			.render_scene(|state| state.draw_surface(surface.clone()))
+			.unwrap();
+		assert_eq!(renderer.backend.draws, 1);
+		assert_eq!(renderer.backend.textures, renderer_textures + 1);
+
+		// Committing a null buffer leaves the surface without a buffer size
+		surface_data.lock().unwrap().buffer_size = None;
+		renderer
+			.render_scene(|state| state.draw_surface(surface.clone()))
+			.unwrap();
+		assert_eq!(renderer.backend.draws, 1);
+		assert_eq!(renderer.backend.textures, renderer_textures);
+		let surface_data_lock = surface_data.lock().unwrap();
+		assert!(surface_data_lock.renderer_data.as_ref().unwrap().plane.is_none());
+	}
+
+	#[test]
+	fn destroyed_surfaces_leave_no_resources() {
+		let mut display = Display::new();
+		let (client, _socket) = test_client(&mut display);
+		let mut renderer = Renderer::init(TestBackend::default()).unwrap();
+		assert_eq!(renderer.surface_resource_counts(), ResourceCounts::default());
+
+		let surfaces = vec![mapped_surface(&client), mapped_surface(&client)];
+		renderer
+			.render_scene(|state| {
+				for (surface, _surface_data) in &surfaces {
+					state.draw_surface(surface.clone())?;
+				}
+				Ok(())
+			})
+			.unwrap();
+		let surface_resources = ResourceCounts {
+			textures: 2,
+			mvp_buffers: 2,
+			..Default::default()
+		};
+		assert_eq!(renderer.surface_resource_counts(), surface_resources);
+
+		for (_surface, surface_data) in surfaces {
+			let renderer_data = surface_data.lock().unwrap().renderer_data.take().unwrap();
+			renderer.destroy_surface_renderer_data(renderer_data).unwrap();
+		}
+		assert_eq!(renderer.surface_resource_counts(), ResourceCounts::default());
+		renderer.shutdown().unwrap();
+		assert_eq!(renderer.resource_counts(), ResourceCounts::default());
+		assert_eq!(renderer.backend.textures, 0);
+	}
+
+	#[test]
+	fn disconnected_outputs_are_removed() {
+		let mut renderer = Renderer::init(TestBackend::default()).unwrap();
+		assert_eq!(renderer.outputs().len(), 2);
+		assert!(renderer.disconnected_outputs().is_empty());
+
+		renderer.backend.disconnected.push(0);
+		assert_eq!(renderer.disconnected_outputs(), vec![0]);
+		renderer.remove_output(0).unwrap();
+		let outputs = renderer.outputs();
+		assert_eq!(outputs.len(), 1);
+		assert_eq!(outputs[0].handle, 1);
+		// The remaining output moves over to where the removed one was
+		assert_eq!(outputs[0].viewport, Rect::new(0, 0, 100, 100));
+		assert_eq!(renderer.resource_counts().render_targets, 1);
+		assert!(renderer.disconnected_outputs().is_empty());
+	}
+
+	#[test]
+	fn unresponsive_clients_are_dimmed() {
+		let mut display = Display::new();
+		let (client, _socket) = test_client(&mut display);
+		let mut renderer = Renderer::init(TestBackend::default()).unwrap();
+
+		let (surface, surface_data) = mapped_surface(&client);
+		surface_data.lock().unwrap().client_info.lock().unwrap().unresponsive = true;
+		renderer
+			.render_scene(|state| state.draw_surface(surface.clone()))
+			.unwrap();
+		assert_eq!(renderer.backend.draws, 2);
+
+		surface_data.lock().unwrap().client_info.lock().unwrap().unresponsive = false;
+		renderer
+			.render_scene(|state| state.draw_surface(surface.clone()))
+			.unwrap();
+		assert_eq!(renderer.backend.draws, 3);
+	}
+
+	#[test]
+	fn buffer_is_released_when_its_upload_fails() {
+		let mut display = Display::new();
+		let (client, mut socket) = test_client(&mut display);
+		let mut renderer = Renderer::init(TestBackend::default()).unwrap();
+		let (surface, surface_data) = mapped_surface(&client);
+		let buffer = surface_data.lock().unwrap().committed_buffer.clone().unwrap().0;
+
+		renderer.backend.fail_uploads = true;
+		assert!(renderer
+			.render_scene(|state| state.draw_surface(surface.clone()))
+			.is_err());
+		renderer.present().unwrap();
+		renderer.update().unwrap();
+		display.flush_clients(&mut ());
+		assert!(received_events(&mut socket)
+			.iter()
+			.any(|(object, opcode, _)| *object == buffer.as_ref().id() && *opcode == 0));
+	}
+
+	#[test]
+	fn scissored_frames_only_draw_the_damage() {
+		let mut display = Display::new();
+		let (client, _socket) = test_client(&mut display);
+		let mut renderer = Renderer::init(TestBackend {
+			scissors: Some(Vec::new()),
+			..TestBackend::default()
+		})
+		.unwrap();
+		let (still, _still_data) = mapped_surface(&client);
+		let (moving, moving_data) = mapped_surface(&client);
+		moving_data.lock().unwrap().position = Some(Point::new(50, 50));
+		let surfaces = [still, moving];
+		renderer
+			.render_scene(|state| {
+				surfaces
+					.iter()
+					.try_for_each(|surface| state.draw_surface(surface.clone()))
+			})
+			.unwrap();
+		assert_eq!(renderer.backend.draws, 2);
+
+		// Only the surface that moved is inside the damage, which covers where it was and where it is now
+		moving_data.lock().unwrap().position = Some(Point::new(60, 60));
+		renderer
+			.render_scene(|state| {
+				surfaces
+					.iter()
+					.try_for_each(|surface| state.draw_surface(surface.clone()))
+			})
+			.unwrap();
+		assert_eq!(renderer.backend.draws, 3);
+		assert_eq!(renderer.backend.scissors, Some(vec![Rect::new(50, 50, 20, 20)]));
+	}
+}
@@ -0,0 +1,146 @@
+//! A benchmark that renders a synthetic scene of moving textured quads, giving a reproducible frame time to compare
+//! renderer changes against without needing any clients.
+
+use std::{
+	fmt,
+	time::{Duration, Instant},
+};
+
+use festus::geometry::*;
+
+use crate::{
+	backend::{GraphicsBackend, RgbaInfo},
+	renderer::{Plane, Renderer},
+};
+
+/// The number of quads in the benchmark scene
+const QUAD_COUNT: usize = 4;
+/// The width and height of every quad in the benchmark scene
+const QUAD_SIZE: u32 = 256;
+
+/// The timing of a benchmark run
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchStats {
+	pub frames: u32,
+	pub min_frame_time: Duration,
+	pub avg_frame_time: Duration,
+	pub max_frame_time: Duration,
+	pub total_time: Duration,
+}
+
+impl fmt::Display for BenchStats {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"Rendered {} frames in {:.2} ms (min {:.3} ms, avg {:.3} ms, max {:.3} ms)",
+			self.frames,
+			self.total_time.as_secs_f64() * 1000.0,
+			self.min_frame_time.as_secs_f64() * 1000.0,
+			self.avg_frame_time.as_secs_f64() * 1000.0,
+			self.max_frame_time.as_secs_f64() * 1000.0,
+		)
+	}
+}
+
+impl<G: GraphicsBackend + 'static> Renderer<G> {
+	/// Render and present `frames` frames of the benchmark scene, timing each frame from the start of rendering until
+	/// presenting returns
+	pub fn run_benchmark(&mut self, frames: u32) -> Result<BenchStats, G::Error> {
+		let mut planes = Vec::with_capacity(QUAD_COUNT);
+		for i in 0..QUAD_COUNT {
+			let data = quad_pattern(i);
+			let rgba = RgbaInfo {
+				width: QUAD_SIZE,
+				height: QUAD_SIZE,
+				data: &data,
+			};
+			match self.create_plane_from_rgba(Rect::new(0, 0, QUAD_SIZE, QUAD_SIZE), rgba) {
+				Ok(plane) => planes.push(plane),
+				Err(e) => {
+					self.destroy_bench_planes(planes)?;
+					return Err(e);
+				}
+			}
+		}
+		let result = self.render_bench_frames(&planes, frames);
+		self.destroy_bench_planes(planes)?;
+		result
+	}
+
+	fn render_bench_frames(&mut self, planes: &[Plane<G>], frames: u32) -> Result<BenchStats, G::Error> {
+		let bounds = self
+			.desktop_bounds()
+			.unwrap_or_else(|| Rect::new(0, 0, QUAD_SIZE, QUAD_SIZE));
+		let mut min_frame_time = Duration::from_secs(u64::MAX);
+		let mut max_frame_time = Duration::from_secs(0);
+		let start = Instant::now();
+		for frame in 0..frames {
+			let frame_start = Instant::now();
+			self.update()?;
+			self.render_scene(|scene_render_state| {
+				for (i, plane) in planes.iter().enumerate() {
+					let position = quad_position(bounds, i, frame);
+					let size = Size::new(QUAD_SIZE, QUAD_SIZE);
+					scene_render_state.draw(plane, position, size);
+				}
+				Ok(())
+			})?;
+			self.present()?;
+			let frame_time = frame_start.elapsed();
+			min_frame_time = min_frame_time.min(frame_time);
+			max_frame_time = max_frame_time.max(frame_time);
+		}
+		let total_time = start.elapsed();
+		Ok(BenchStats {
+			frames,
+			min_frame_time: if frames > 0 {
+				min_frame_time
+			} else {
+				Duration::from_secs(0)
+			},
+			avg_frame_time: total_time / frames.max(1),
+			max_frame_time,
+			total_time,
+		})
+	}
+
+	fn destroy_bench_planes(&mut self, planes: Vec<Plane<G>>) -> Result<(), G::Error> {
+		for plane in planes {
+			self.destroy_plane(plane)?;
+		}
+		Ok(())
+	}
+}
+
+/// Get the position of a quad in a frame. Every quad bounces around the desktop at its own speed, so they overlap
+/// each other and cross output boundaries.
+fn quad_position(bounds: Rect, quad: usize, frame: u32) -> (f64, f64) {
+	let range_x = f64::from(bounds.width.saturating_sub(QUAD_SIZE).max(1));
+	let range_y = f64::from(bounds.height.saturating_sub(QUAD_SIZE).max(1));
+	let t = f64::from(frame) * 0.01 * (quad + 1) as f64;
+	let x = (t.sin() * 0.5 + 0.5) * range_x;
+	let y = ((t * 1.3).cos() * 0.5 + 0.5) * range_y;
+	(f64::from(bounds.x) + x, f64::from(bounds.y) + y)
+}
+
+/// Generate a checkerboard in a different color for every quad, so the texture is sampled like a real window's
+fn quad_pattern(quad: usize) -> Vec<u8> {
+	const CHECKER_SIZE: u32 = 32;
+	let color = [
+		[0xe0, 0x40, 0x40],
+		[0x40, 0xe0, 0x40],
+		[0x40, 0x40, 0xe0],
+		[0xe0, 0xe0, 0x40],
+	][quad % 4];
+	let mut data = Vec::with_capacity((QUAD_SIZE * QUAD_SIZE * 4) as usize);
+	for y in 0..QUAD_SIZE {
+		for x in 0..QUAD_SIZE {
+			if (x / CHECKER_SIZE + y / CHECKER_SIZE) % 2 == 0 {
+				data.extend_from_slice(&[color[0], color[1], color[2], 0xff]);
+			} else {
+				data.extend_from_slice(&[0x20, 0x20, 0x20, 0xff]);
+			}
+		}
+	}
+	data
+}
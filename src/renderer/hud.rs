@@ -0,0 +1,161 @@
+//! An optional on-screen overlay that displays frame statistics in the corner of every output. It is meant for
+//! development, so the text is drawn with a tiny built-in bitmap font instead of a real font rasterizer.
+
+use festus::geometry::*;
+
+use crate::{
+	backend::{GraphicsBackend, RgbaInfo},
+	renderer::{Plane, Renderer, SceneRenderState},
+};
+
+/// The width and height of each glyph of the HUD font, in font pixels
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+/// The number of screen pixels used for each font pixel
+const FONT_SCALE: u32 = 2;
+/// The distance of the HUD from the top left corner of each output
+const HUD_MARGIN: i32 = 8;
+
+/// The statistics shown by the HUD
+#[derive(Debug, Clone, PartialEq)]
+pub struct HudStats {
+	pub fps: f64,
+	pub frame_time_ms: f64,
+	pub window_count: usize,
+	/// The protocol id of the surface that has keyboard focus
+	pub focused_surface: Option<u32>,
+}
+
+impl HudStats {
+	fn text(&self) -> String {
+		let focus = self
+			.focused_surface
+			.map(|id| id.to_string())
+			.unwrap_or_else(|| String::from("-"));
+		format!(
+			"FPS {:.0} FRAME {:.1}MS WINDOWS {} FOCUS {}",
+			self.fps, self.frame_time_ms, self.window_count, focus
+		)
+	}
+}
+
+pub struct Hud<G: GraphicsBackend> {
+	plane: Plane<G>,
+	text: String,
+	size: Size,
+}
+
+impl<G: GraphicsBackend> Renderer<G> {
+	/// Update the statistics displayed by the HUD, hiding it if `stats` is None. The HUD texture is only
+	/// recreated when the displayed text actually changes.
+	pub fn update_hud(&mut self, stats: Option<&HudStats>) -> Result<(), G::Error> {
+		let text = stats.map(HudStats::text);
+		if self.hud.as_ref().map(|hud| &hud.text) == text.as_ref() {
+			return Ok(());
+		}
+		if let Some(hud) = self.hud.take() {
+			self.destroy_plane(hud.plane)?;
+		}
+		if let Some(text) = text {
+			let (width, height, data) = rasterize_text(&text);
+			let plane = self.create_plane_from_rgba(
+				Rect::new(0, 0, width, height),
+				RgbaInfo {
+					width,
+					height,
+					data: &data,
+				},
+			)?;
+			self.hud = Some(Hud {
+				plane,
+				text,
+				size: Size::new(width, height),
+			});
+		}
+		Ok(())
+	}
+}
+
+impl<'a, G: GraphicsBackend + 'static> SceneRenderState<'a, G> {
+	/// Draw the HUD in the top left corner of every output if it is enabled
+	pub fn draw_hud(&mut self) -> Result<(), G::Error> {
+		let hud = match self.renderer.hud {
+			Some(ref hud) => hud,
+			None => return Ok(()),
+		};
+		for output in &self.renderer.outputs {
+			let position = (
+				f64::from(output.viewport.x + HUD_MARGIN),
+				f64::from(output.viewport.y + HUD_MARGIN),
+			);
+			self.quads.push(SceneQuad::new(&hud.plane, position, hud.size));
+		}
+		Ok(())
+	}
+}
+
+/// Rasterize a line of text into white-on-black RGBA pixels, returning the width, height, and data of the image
+fn rasterize_text(text: &str) -> (u32, u32, Vec<u8>) {
+	// Every glyph is followed by a column of spacing, and the whole line is surrounded by a one pixel border
+	let columns = text.chars().count() as u32 * (GLYPH_WIDTH + 1) + 1;
+	let rows = GLYPH_HEIGHT + 2;
+	let width = columns * FONT_SCALE;
+	let height = rows * FONT_SCALE;
+	let mut data = vec![0u8; (width * height * 4) as usize];
+	for pixel in data.chunks_mut(4) {
+		pixel[3] = 0xff;
+	}
+	for (i, c) in text.chars().enumerate() {
+		let glyph = glyph(c);
+		for (row, bits) in glyph.iter().enumerate() {
+			for column in 0..GLYPH_WIDTH {
+				if bits & (0b100 >> column) == 0 {
+					continue;
+				}
+				let font_x = 1 + i as u32 * (GLYPH_WIDTH + 1) + column;
+				let font_y = 1 + row as u32;
+				for y in font_y * FONT_SCALE..(font_y + 1) * FONT_SCALE {
+					for x in font_x * FONT_SCALE..(font_x + 1) * FONT_SCALE {
+						let index = ((y * width + x) * 4) as usize;
+						data[index..index + 3].copy_from_slice(&[0xff, 0xff, 0xff]);
+					}
+				}
+			}
+		}
+	}
+	(width, height, data)
+}
+
+/// Get the rows of a glyph, where the three low bits of each row are its pixels from left to right. Only the
+/// characters the HUD needs are defined, everything else is blank.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT as usize] {
+	match c {
+		'0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+		'1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+		'2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+		'3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+		'4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+		'5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+		'6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+		'7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+		'8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+		'9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+		'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+		'C' => [0b111, 0b100, 0b100, 0b100, 0b111],
+		'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+		'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+		'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+		'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+		'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+		'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+		'O' => [0b111, 0b101, 0b101, 0b101, 0b111],
+		'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+		'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+		'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+		'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+		'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+		'.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+		'-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+		_ => [0b000; GLYPH_HEIGHT as usize],
+	}
+}
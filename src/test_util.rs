@@ -0,0 +1,329 @@
+//! Fixtures shared by the unit tests. Tests run without real backends or clients, so this provides backends that
+//! only count what they're asked to do, and clients connected over socket pairs.
+
+use std::{
+	io::{self, Read},
+	os::unix::{
+		io::{IntoRawFd, RawFd},
+		net::UnixStream,
+	},
+};
+
+use calloop::{channel::Channel, timer::Timer};
+
+use crate::{
+	backend::{self, KeyboardLeds, OutputInfo, RgbaInfo, Vertex},
+	compositor::{buffer::BufferData, client::ClientUsage, prelude::*, CompositorInner},
+	config::Config,
+	renderer::{Renderer, SurfaceRendererData},
+};
+
+/// An input backend without any devices, which never has events
+pub(crate) struct TestInputBackend;
+
+impl InputBackend for TestInputBackend {
+	type Error = io::Error;
+
+	fn update(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+
+	fn get_event_source(&mut self) -> Channel<BackendEvent> {
+		calloop::channel::channel().1
+	}
+
+	fn set_keyboard_leds(&mut self, _leds: KeyboardLeds) {}
+}
+
+#[derive(Debug)]
+pub(crate) struct TestShmBuffer {
+	pub(crate) width: u32,
+	pub(crate) height: u32,
+}
+
+impl backend::ShmBuffer for TestShmBuffer {
+	fn offset(&self) -> usize {
+		0
+	}
+
+	fn width(&self) -> u32 {
+		self.width
+	}
+
+	fn height(&self) -> u32 {
+		self.height
+	}
+
+	fn stride(&self) -> u32 {
+		self.width * 4
+	}
+
+	fn format(&self) -> wl_shm::Format {
+		wl_shm::Format::Argb8888
+	}
+}
+
+/// A graphics backend with two 100x100 outputs that only keeps track of its textures and draw calls
+#[derive(Debug, Default)]
+pub(crate) struct TestBackend {
+	next_handle: u64,
+	/// The outputs that were unplugged
+	pub(crate) disconnected: Vec<u64>,
+	/// Whether uploading shm buffers to textures fails
+	pub(crate) fail_uploads: bool,
+	/// The scissors of the scissored render passes that were begun, if the backend supports them
+	pub(crate) scissors: Option<Vec<Rect>>,
+	pub(crate) textures: usize,
+	mvps: Vec<[[[f32; 4]; 4]; 3]>,
+	pub(crate) draws: usize,
+}
+
+impl TestBackend {
+	fn next_handle(&mut self) -> u64 {
+		self.next_handle += 1;
+		self.next_handle
+	}
+}
+
+impl GraphicsBackend for TestBackend {
+	type Error = io::Error;
+
+	type ShmPool = ();
+	type ShmBuffer = TestShmBuffer;
+
+	type VertexBufferHandle = u64;
+	type TextureHandle = u64;
+	type MvpBufferHandle = usize;
+
+	type RenderTargetHandle = u64;
+
+	type OutputHandle = u64;
+
+	fn update(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+
+	fn create_shm_pool(&mut self, _fd: RawFd, _size: usize) -> io::Result<()> {
+		Ok(())
+	}
+
+	fn resize_shm_pool(&mut self, _shm_pool: &mut (), _new_size: usize) -> io::Result<()> {
+		Ok(())
+	}
+
+	fn create_shm_buffer(
+		&mut self,
+		_shm_pool: &mut (),
+		_offset: usize,
+		width: u32,
+		height: u32,
+		_stride: u32,
+		_format: wl_shm::Format,
+	) -> io::Result<TestShmBuffer> {
+		Ok(TestShmBuffer { width, height })
+	}
+
+	fn create_texture_from_rgba(&mut self, _rgba: RgbaInfo) -> io::Result<u64> {
+		self.textures += 1;
+		Ok(self.next_handle())
+	}
+
+	fn create_texture_from_shm_buffer(&mut self, _shm_buffer: &TestShmBuffer) -> io::Result<u64> {
+		if self.fail_uploads {
+			return Err(io::Error::new(
+				io::ErrorKind::Other,
+				"The test backend failed an upload",
+			));
+		}
+		self.textures += 1;
+		Ok(self.next_handle())
+	}
+
+	fn create_vertex_buffer(&mut self, _vertices: &[Vertex], _indices: &[u32]) -> io::Result<u64> {
+		Ok(self.next_handle())
+	}
+
+	fn create_mvp_buffer(&mut self, mvp: [[[f32; 4]; 4]; 3]) -> io::Result<usize> {
+		self.mvps.push(mvp);
+		Ok(self.mvps.len() - 1)
+	}
+
+	fn map_mvp_buffer(&mut self, handle: usize) -> Option<&mut [[[f32; 4]; 4]; 3]> {
+		self.mvps.get_mut(handle)
+	}
+
+	fn create_texture(&mut self, _size: Size) -> io::Result<u64> {
+		self.textures += 1;
+		Ok(self.next_handle())
+	}
+
+	fn create_render_target(&mut self, _size: Size) -> io::Result<u64> {
+		Ok(self.next_handle())
+	}
+
+	fn get_current_outputs(&self) -> Vec<u64> {
+		(0..2).filter(|output| !self.disconnected.contains(output)).collect()
+	}
+
+	fn get_output_info(&self, output: u64) -> io::Result<OutputInfo> {
+		if self.disconnected.contains(&output) {
+			return Err(io::Error::new(io::ErrorKind::NotFound, "output was disconnected"));
+		}
+		Ok(OutputInfo {
+			size: Size::new(100, 100),
+		})
+	}
+
+	unsafe fn begin_render_pass(&mut self, _target: u64) -> io::Result<()> {
+		Ok(())
+	}
+
+	unsafe fn begin_scissored_render_pass(&mut self, _target: u64, scissor: Rect) -> io::Result<bool> {
+		match self.scissors {
+			Some(ref mut scissors) => {
+				scissors.push(scissor);
+				Ok(true)
+			}
+			None => Ok(false),
+		}
+	}
+
+	unsafe fn draw(&mut self, _vertex_buffer: u64, _texture: u64, _mvp: usize) -> io::Result<()> {
+		self.draws += 1;
+		Ok(())
+	}
+
+	unsafe fn end_render_pass(&mut self, _target: u64) -> io::Result<()> {
+		Ok(())
+	}
+
+	fn present_target(&mut self, _output: u64, _handle: u64) -> io::Result<()> {
+		Ok(())
+	}
+
+	fn frames_finished(&mut self) -> io::Result<bool> {
+		Ok(true)
+	}
+
+	fn read_render_target(&mut self, _handle: u64) -> io::Result<Vec<u8>> {
+		Err(io::Error::new(
+			io::ErrorKind::Other,
+			"The test backend can't read render targets",
+		))
+	}
+
+	fn destroy_texture(&mut self, _handle: u64) -> io::Result<()> {
+		self.textures -= 1;
+		Ok(())
+	}
+
+	fn memory_usage(&self) -> u64 {
+		0
+	}
+
+	fn destroy_vertex_buffer(&mut self, _handle: u64) -> io::Result<()> {
+		Ok(())
+	}
+
+	fn destroy_mvp_buffer(&mut self, _handle: usize) -> io::Result<()> {
+		Ok(())
+	}
+
+	fn destroy_render_target(&mut self, _handle: u64) -> io::Result<()> {
+		Ok(())
+	}
+
+	fn shutdown(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+/// Connect a client to `display` over a socket pair. The client's end of the socket is returned too, since the
+/// client is disconnected once it's dropped.
+pub(crate) fn test_client(display: &mut Display) -> (Client, UnixStream) {
+	let (server, client) = UnixStream::pair().unwrap();
+	let client_handle = unsafe { display.create_client(server.into_raw_fd(), &mut ()) };
+	(client_handle, client)
+}
+
+/// Read the events the server has sent to a test client over its end of the socket, as the id of the object each
+/// was sent to, its opcode and its arguments. The display has to be flushed first.
+pub(crate) fn received_events(socket: &mut UnixStream) -> Vec<(u32, u16, Vec<u32>)> {
+	socket.set_nonblocking(true).unwrap();
+	let mut bytes = Vec::new();
+	let mut buf = [0u8; 4096];
+	loop {
+		match socket.read(&mut buf) {
+			Ok(0) => break,
+			Ok(len) => bytes.extend_from_slice(&buf[..len]),
+			Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+			Err(e) => panic!("Failed to read from the test client's socket: {}", e),
+		}
+	}
+	let words: Vec<u32> = bytes
+		.chunks_exact(4)
+		.map(|word| u32::from_ne_bytes([word[0], word[1], word[2], word[3]]))
+		.collect();
+	let mut events = Vec::new();
+	let mut i = 0;
+	while i + 2 <= words.len() {
+		// The second word of the header holds the message's size in bytes and its opcode
+		let len = ((words[i + 1] >> 16) as usize / 4).max(2).min(words.len() - i);
+		let opcode = (words[i + 1] & 0xffff) as u16;
+		events.push((words[i], opcode, words[i + 2..i + len].to_vec()));
+		i += len;
+	}
+	events
+}
+
+/// Create the info the compositor keeps about a client that hasn't bound anything yet
+pub(crate) fn test_client_info(client: &Client) -> Synced<ClientInfo> {
+	Arc::new(Mutex::new(ClientInfo {
+		client: client.clone(),
+		keyboards: Vec::new(),
+		pointers: Vec::new(),
+		touches: Vec::new(),
+		outputs: Vec::new(),
+		data_devices: Vec::new(),
+		swipe_gestures: Vec::new(),
+		pinch_gestures: Vec::new(),
+		usage: ClientUsage::default(),
+		unresponsive: false,
+	}))
+}
+
+/// Create a surface with surface data, but without a role or a buffer
+pub(crate) fn test_surface<G: GraphicsBackend + 'static>(
+	client: &Client,
+	client_info: &Synced<ClientInfo>,
+) -> (wl_surface::WlSurface, Synced<SurfaceData<G>>) {
+	let surface = (*client.create_resource::<wl_surface::WlSurface>(4).unwrap()).clone();
+	let renderer_data = SurfaceRendererData {
+		plane: None,
+		texture_buffer: None,
+	};
+	let surface_data = Arc::new(Mutex::new(SurfaceData::new(Arc::clone(client_info), renderer_data)));
+	let surface_data_clone = Arc::clone(&surface_data);
+	surface.as_ref().user_data().set_threadsafe(|| surface_data_clone);
+	(surface, surface_data)
+}
+
+/// Create a wl_buffer backed by a test shm buffer of the given size
+pub(crate) fn test_buffer(client: &Client, width: u32, height: u32) -> wl_buffer::WlBuffer {
+	let buffer = (*client.create_resource::<wl_buffer::WlBuffer>(1).unwrap()).clone();
+	let buffer_data = Arc::new(Mutex::new(BufferData::new(TestShmBuffer { width, height })));
+	buffer.as_ref().user_data().set_threadsafe(|| buffer_data);
+	buffer
+}
+
+/// Create the state of a compositor with the test backend's two outputs, which are side by side with the first one
+/// at the origin
+pub(crate) fn test_inner(display: &mut Display) -> CompositorInner<TestInputBackend, TestBackend> {
+	let mut inner = CompositorInner::new(Config::default(), Timer::new().handle());
+	let renderer = Renderer::init(TestBackend::default()).unwrap();
+	for output in renderer.outputs() {
+		let output_global = display.create_global(2, Filter::new(|_: (Main<wl_output::WlOutput>, u32), _, _| {}));
+		inner.output_globals.push((output_global, Arc::new(Mutex::new(output))));
+	}
+	inner
+}